@@ -0,0 +1,141 @@
+//! A small client for a to-do list API, used to show how Servirtium records and plays back the
+//! conversation between a client and the API it talks to.
+
+#[cfg(test)]
+mod mock_api;
+
+use hyper::{body, header, Body, Client, Method, Request};
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Todo {
+    pub id: u32,
+    pub title: String,
+    pub completed: bool,
+}
+
+#[derive(Serialize)]
+struct NewTodo<'a> {
+    title: &'a str,
+    api_key: &'a str,
+}
+
+#[derive(Serialize)]
+struct TodoUpdate<'a> {
+    completed: bool,
+    api_key: &'a str,
+}
+
+pub struct TodoClient {
+    base_url: String,
+    api_key: String,
+}
+
+impl TodoClient {
+    pub fn new<S1: Into<String>, S2: Into<String>>(base_url: S1, api_key: S2) -> Self {
+        TodoClient {
+            base_url: base_url.into(),
+            api_key: api_key.into(),
+        }
+    }
+
+    /// Creates a to-do with a `POST /todos` request.
+    pub fn create_todo(&self, title: &str) -> Result<Todo, Error> {
+        let body = serde_json::to_string(&NewTodo {
+            title,
+            api_key: &self.api_key,
+        })?;
+
+        self.send_json(Method::POST, "/todos", body)
+    }
+
+    /// Marks a to-do as completed or not with a `PATCH /todos/{id}` request.
+    pub fn set_completed(&self, id: u32, completed: bool) -> Result<Todo, Error> {
+        let body = serde_json::to_string(&TodoUpdate {
+            completed,
+            api_key: &self.api_key,
+        })?;
+
+        self.send_json(Method::PATCH, &format!("/todos/{}", id), body)
+    }
+
+    fn send_json(&self, method: Method, path: &str, body: String) -> Result<Todo, Error> {
+        Runtime::new()?.block_on(async {
+            let request = Request::builder()
+                .method(method)
+                .uri(format!("{}{}", self.base_url, path))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))?;
+
+            let response = Client::new().request(request).await?;
+
+            if !response.status().is_success() {
+                return Err(format!("the API responded with {}", response.status()).into());
+            }
+
+            let body = body::to_bytes(response.into_body()).await?;
+            Ok(serde_json::from_slice(&body)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mock_api, Todo, TodoClient};
+    use servirtium::{
+        servirtium_playback_test, servirtium_record_test, MarkdownInteractionManager,
+        ServirtiumConfiguration, ServirtiumMode,
+    };
+
+    const SERVIRTIUM_URL: &str = "http://localhost:61417";
+
+    fn configure_record(config: &mut ServirtiumConfiguration) {
+        config.set_domain_name(mock_api::serve(61524));
+        // the API key must never end up in the recording
+        config.add_saved_request_mutations(|builder| builder.json_mask_keys(vec!["api_key"]));
+        config.add_record_response_mutations(|builder| builder.remove_headers(vec!["date"]));
+    }
+
+    fn create_and_complete_todo(base_url: &str) {
+        let client = TodoClient::new(base_url, "secret-api-key");
+
+        let todo = client.create_todo("Write the docs").unwrap();
+        assert_eq!(
+            todo,
+            Todo {
+                id: 1,
+                title: String::from("Write the docs"),
+                completed: false,
+            }
+        );
+
+        let todo = client.set_completed(todo.id, true).unwrap();
+        assert!(todo.completed);
+    }
+
+    #[servirtium_record_test("test_recordings/todos.md", configure_record)]
+    fn create_and_complete_todo_record() {
+        create_and_complete_todo(SERVIRTIUM_URL);
+    }
+
+    #[servirtium_playback_test("test_recordings/todos.md", "http://localhost:61524")]
+    fn create_and_complete_todo_playback() {
+        create_and_complete_todo(SERVIRTIUM_URL);
+    }
+
+    // the same playback without the attribute macro, the guard ends the test when it's dropped
+    #[test]
+    fn create_and_complete_todo_playback_with_guard() {
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(MarkdownInteractionManager::new("test_recordings/todos.md")),
+        );
+        config.set_domain_name("http://localhost:61524");
+
+        let server = servirtium::start(config);
+        create_and_complete_todo(&server.base_url());
+    }
+}
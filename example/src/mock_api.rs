@@ -0,0 +1,68 @@
+use crate::Todo;
+use hyper::{
+    body,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde_json::Value;
+use std::{convert::Infallible, net::TcpListener, thread};
+use tokio::runtime::Runtime;
+
+/// Starts a mock of the to-do list API on the given port in a background thread and returns its
+/// URL. It knows a single to-do with the id 1.
+pub fn serve(port: u16) -> String {
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+
+    thread::spawn(move || {
+        Runtime::new().unwrap().block_on(async move {
+            let server = Server::from_tcp(listener)
+                .unwrap()
+                .serve(make_service_fn(|_| async {
+                    Ok::<_, Infallible>(service_fn(|req| async {
+                        Ok::<_, Infallible>(handle(req).await)
+                    }))
+                }));
+
+            let _ = server.await;
+        });
+    });
+
+    format!("http://127.0.0.1:{}", port)
+}
+
+async fn handle(request: Request<Body>) -> Response<Body> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+    let body = body::to_bytes(request.into_body()).await.unwrap();
+    let fields: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    if fields["api_key"] != "secret-api-key" {
+        return status(StatusCode::UNAUTHORIZED);
+    }
+
+    let todo = match (method, path.as_str()) {
+        (Method::POST, "/todos") => Todo {
+            id: 1,
+            title: fields["title"].as_str().unwrap_or_default().to_owned(),
+            completed: false,
+        },
+        (Method::PATCH, "/todos/1") => Todo {
+            id: 1,
+            title: String::from("Write the docs"),
+            completed: fields["completed"].as_bool().unwrap_or_default(),
+        },
+        _ => return status(StatusCode::NOT_FOUND),
+    };
+
+    Response::builder()
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_string(&todo).unwrap()))
+        .unwrap()
+}
+
+fn status(status_code: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status_code)
+        .body(Body::empty())
+        .unwrap()
+}
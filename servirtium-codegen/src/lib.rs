@@ -3,32 +3,53 @@ use proc_macro2::Span;
 use quote::quote;
 use quote::quote_spanned;
 use std::path::Path;
+use syn::spanned::Spanned;
 
 #[proc_macro_attribute]
 pub fn servirtium_record(attrs: TokenStream, item: TokenStream) -> TokenStream {
-    servirtium_test(attrs, item, quote! { servirtium::ServirtiumMode::Record }, false)
+    servirtium_test(
+        attrs,
+        item,
+        quote! { servirtium::ServirtiumMode::Record },
+        false,
+    )
 }
 
 #[proc_macro_attribute]
 pub fn servirtium_playback(attrs: TokenStream, item: TokenStream) -> TokenStream {
-    servirtium_test(attrs, item, quote! { servirtium::ServirtiumMode::Playback }, false)
+    servirtium_test(
+        attrs,
+        item,
+        quote! { servirtium::ServirtiumMode::Playback },
+        false,
+    )
 }
 
 #[proc_macro_attribute]
 pub fn servirtium_record_test(attrs: TokenStream, item: TokenStream) -> TokenStream {
-    servirtium_test(attrs, item, quote! { servirtium::ServirtiumMode::Record }, true)
+    servirtium_test(
+        attrs,
+        item,
+        quote! { servirtium::ServirtiumMode::Record },
+        true,
+    )
 }
 
 #[proc_macro_attribute]
 pub fn servirtium_playback_test(attrs: TokenStream, item: TokenStream) -> TokenStream {
-    servirtium_test(attrs, item, quote! { servirtium::ServirtiumMode::Playback }, true)
+    servirtium_test(
+        attrs,
+        item,
+        quote! { servirtium::ServirtiumMode::Playback },
+        true,
+    )
 }
 
 fn servirtium_test(
     attrs: TokenStream,
     item: TokenStream,
     enum_variant: proc_macro2::TokenStream,
-    with_test_attribute: bool
+    with_test_attribute: bool,
 ) -> TokenStream {
     let input = syn::parse_macro_input!(item as syn::ItemFn);
     let args = syn::parse_macro_input!(attrs as syn::AttributeArgs);
@@ -51,7 +72,7 @@ fn servirtium_test(
             return stream.into();
         }
     } else {
-        return quote! {
+        return quote_spanned! {args[0].span()=>
             compile_error!("The first argument should be a string literal!");
         }
         .into();
@@ -64,19 +85,68 @@ fn servirtium_test(
             configuration_function = Some(function_path);
         }
         syn::NestedMeta::Lit(syn::Lit::Str(domain_name_arg)) => {
-            domain_name = Some(domain_name_arg.value());
+            let value = domain_name_arg.value();
+            if let Err(stream) = validate_domain_name(&value, domain_name_arg.span()) {
+                return stream.into();
+            }
+            domain_name = Some(value);
         }
         _ => {
-            let error = quote! {
-                compile_error!("The second argument should be a configuration function!");
+            let error = quote_spanned! {args[1].span()=>
+                compile_error!("The second argument should be a configuration function or a domain name!");
             };
 
             return error.into();
         }
     };
 
+    let mut catch_panics = true;
+    let mut overrides = Vec::new();
+    for arg in &args[2..] {
+        match arg {
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("no_catch") => {
+                catch_panics = false;
+            }
+            // `name = value` calls `set_name(value)` on the configuration, so a misspelled name is
+            // reported as an unknown method at the argument
+            syn::NestedMeta::Meta(syn::Meta::NameValue(name_value)) => {
+                let name = match name_value.path.get_ident() {
+                    Some(name) => name,
+                    None => {
+                        return syn::Error::new_spanned(
+                            &name_value.path,
+                            "The name of an override should be an identifier",
+                        )
+                        .to_compile_error()
+                        .into();
+                    }
+                };
+                let setter = syn::Ident::new(&format!("set_{}", name), name.span());
+                let value = &name_value.lit;
+
+                overrides.push(quote_spanned! {name_value.span()=>
+                    __servirtium_configuration.#setter(#value);
+                });
+            }
+            _ => {
+                return syn::Error::new_spanned(
+                    arg,
+                    "Unknown argument, expected `no_catch` or `name = value`",
+                )
+                .to_compile_error()
+                .into();
+            }
+        }
+    }
+
     let configure = if let Some(configuration_function) = configuration_function {
-        quote! { #configuration_function(&mut __servirtium_configuration); }
+        // the coercion reports a function with a wrong signature at the attribute argument instead
+        // of somewhere in the generated code
+        quote_spanned! {configuration_function.span()=>
+            let __servirtium_configure: fn(&mut servirtium::ServirtiumConfiguration) =
+                #configuration_function;
+            __servirtium_configure(&mut __servirtium_configuration);
+        }
     } else if let Some(domain_name) = domain_name {
         quote! { __servirtium_configuration.set_domain_name(#domain_name); }
     } else {
@@ -89,17 +159,8 @@ fn servirtium_test(
         quote! {}
     };
 
-    let output = quote! {
-        #test_attribute
-        #signature {
-            let mut __servirtium_configuration = servirtium::ServirtiumConfiguration::new(
-                #enum_variant,
-                Box::new(servirtium::MarkdownInteractionManager::new(#markdown_name))
-            );
-
-            #configure
-            servirtium::TestSession::before_test(__servirtium_configuration);
-
+    let run_test = if catch_panics {
+        quote! {
             if let Err(e) = std::panic::catch_unwind(|| {
                 #block
             }) {
@@ -112,6 +173,28 @@ fn servirtium_test(
                 panic!("Servirtium Error: {}", e);
             }
         }
+    } else {
+        // the guard runs the teardown when it's dropped, even if the test panics
+        quote! {
+            let __servirtium_guard = servirtium::TestSessionGuard::new();
+            #block
+        }
+    };
+
+    let output = quote! {
+        #test_attribute
+        #signature {
+            let mut __servirtium_configuration = servirtium::ServirtiumConfiguration::with_global_defaults(
+                #enum_variant,
+                Box::new(servirtium::MarkdownInteractionManager::new(#markdown_name))
+            );
+
+            #configure
+            #(#overrides)*
+            servirtium::TestSession::before_test(__servirtium_configuration);
+
+            #run_test
+        }
     };
 
     TokenStream::from(output)
@@ -129,3 +212,20 @@ fn validate_markdown_path<P: AsRef<Path>>(
 
     Ok(())
 }
+
+// without a scheme the domain name would only be rejected when the first request is forwarded
+fn validate_domain_name(domain_name: &str, span: Span) -> Result<(), proc_macro2::TokenStream> {
+    let host = ["http://", "https://"]
+        .iter()
+        .find_map(|scheme| domain_name.strip_prefix(scheme));
+
+    match host {
+        Some(host) if !host.is_empty() => Ok(()),
+        Some(_) => Err(quote_spanned! {span=>
+            compile_error!("The domain name should contain a host after the scheme, e.g. \"https://example.com\"!");
+        }),
+        None => Err(quote_spanned! {span=>
+            compile_error!("The domain name should start with http:// or https://, e.g. \"https://example.com\"!");
+        }),
+    }
+}
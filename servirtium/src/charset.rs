@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+#[cfg(feature = "charset")]
+use crate::util;
+#[cfg(feature = "charset")]
+use encoding_rs::{Encoding, UTF_8};
+
+/// Decodes a body using the charset of its `Content-Type` header. Bodies without a charset, with
+/// an unknown one or without the `charset` feature are decoded as UTF-8, replacing invalid
+/// sequences.
+pub fn decode(headers: &HashMap<String, String>, body: &[u8]) -> String {
+    #[cfg(feature = "charset")]
+    if let Some(encoding) = encoding(headers) {
+        let (decoded, _) = encoding.decode_without_bom_handling(body);
+        return decoded.into_owned();
+    }
+
+    #[cfg(not(feature = "charset"))]
+    let _ = headers;
+
+    String::from_utf8_lossy(body).into()
+}
+
+/// Encodes a body back into the charset of its `Content-Type` header, the reverse of [`decode`].
+pub fn encode(headers: &HashMap<String, String>, body: String) -> Vec<u8> {
+    #[cfg(feature = "charset")]
+    if let Some(encoding) = encoding(headers) {
+        let (encoded, _, _) = encoding.encode(&body);
+        return encoded.into_owned();
+    }
+
+    #[cfg(not(feature = "charset"))]
+    let _ = headers;
+
+    body.into_bytes()
+}
+
+/// Returns the encoding named by the `charset` parameter of the `Content-Type` header, unless it's
+/// UTF-8 which doesn't need any conversion.
+#[cfg(feature = "charset")]
+fn encoding(headers: &HashMap<String, String>) -> Option<&'static Encoding> {
+    let charset = util::content_type(headers)?
+        .split(';')
+        .skip(1)
+        .filter_map(|parameter| parameter.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .map(|(_, value)| value.trim().trim_matches('"'))?;
+
+    Encoding::for_label(charset.as_bytes()).filter(|encoding| *encoding != UTF_8)
+}
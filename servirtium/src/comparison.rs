@@ -0,0 +1,41 @@
+use crate::markdown::error::MarkdownsHeaderDifference;
+use std::collections::HashMap;
+
+/// Returns the first header that is missing on one side or has a different value, going through
+/// the header names in sorted order so the result is stable. The values are compared without the
+/// surrounding whitespace.
+pub fn compare_headers(
+    old_headers: &HashMap<String, String>,
+    new_headers: &HashMap<String, String>,
+) -> Option<MarkdownsHeaderDifference> {
+    header_differences(old_headers, new_headers).next()
+}
+
+/// Returns all the headers that are missing on one side or have a different value, in the order
+/// of [`compare_headers`].
+pub(crate) fn header_differences<'a>(
+    old_headers: &'a HashMap<String, String>,
+    new_headers: &'a HashMap<String, String>,
+) -> impl Iterator<Item = MarkdownsHeaderDifference> + 'a {
+    let mut header_names = old_headers
+        .keys()
+        .chain(new_headers.keys())
+        .collect::<Vec<_>>();
+    header_names.sort();
+    header_names.dedup();
+
+    header_names.into_iter().filter_map(move |header_name| {
+        let old_value = old_headers.get(header_name).map(|value| value.trim());
+        let new_value = new_headers.get(header_name).map(|value| value.trim());
+
+        if old_value == new_value {
+            None
+        } else {
+            Some(MarkdownsHeaderDifference {
+                header_name: header_name.clone(),
+                old_header_value: old_value.map(String::from),
+                new_header_value: new_value.map(String::from),
+            })
+        }
+    })
+}
@@ -1,16 +1,32 @@
-use std::collections::HashMap;
+use crate::util;
+#[cfg(any(feature = "json", feature = "xml"))]
+use crate::{deserialize, error::Error};
+#[cfg(any(feature = "json", feature = "xml"))]
+use serde::de::DeserializeOwned;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+
+const BODY_PREVIEW_LENGTH: usize = 64;
 
 #[derive(Debug, Clone)]
 pub struct InteractionData {
-    pub interaction_number: u8,
+    pub interaction_number: usize,
     pub request_data: RequestData,
     pub response_data: ResponseData,
+    /// How long the destination API took to respond when the interaction was recorded.
+    pub response_time: Option<Duration>,
 }
 
 #[derive(Debug, Clone)]
 pub struct RequestData {
     pub uri: String,
     pub method: String,
+    /// The version from the request line, e.g. `HTTP/1.1`, if it is known.
+    pub http_version: Option<String>,
+    /// Multiple values of the same header are joined with `\n`.
     pub headers: HashMap<String, String>,
     pub body: String,
 }
@@ -18,6 +34,100 @@ pub struct RequestData {
 #[derive(Debug, Clone)]
 pub struct ResponseData {
     pub status_code: u16,
+    /// The reason phrase from the status line, e.g. `Not Quite OK`, if it isn't the canonical one
+    /// for the status code.
+    pub reason_phrase: Option<String>,
+    /// Multiple values of the same header are joined with `\n`.
     pub headers: HashMap<String, String>,
     pub body: String,
 }
+
+// The helpers check the content type before deserializing the body, so the failures are easier to
+// understand than a parser error.
+impl RequestData {
+    /// The first value of the `Content-Type` header, or `None` if the request doesn't have one.
+    pub fn content_type(&self) -> Option<&str> {
+        util::content_type(&self.headers)
+    }
+
+    #[cfg(feature = "json")]
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        deserialize::json(&self.headers, &self.body)
+    }
+
+    #[cfg(feature = "xml")]
+    pub fn xml<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        deserialize::xml(&self.headers, &self.body)
+    }
+}
+
+impl ResponseData {
+    /// The first value of the `Content-Type` header, or `None` if the response doesn't have one.
+    pub fn content_type(&self) -> Option<&str> {
+        util::content_type(&self.headers)
+    }
+
+    #[cfg(feature = "json")]
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        deserialize::json(&self.headers, &self.body)
+    }
+
+    #[cfg(feature = "xml")]
+    pub fn xml<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        deserialize::xml(&self.headers, &self.body)
+    }
+}
+
+// The summaries are one-liners like `POST /x -> 201 (132 bytes)`. The alternate form (`{:#}`) also
+// includes the beginning of the bodies.
+impl Display for InteractionData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write!(
+                f,
+                "#{} {:#} -> {:#}",
+                self.interaction_number, self.request_data, self.response_data
+            )
+        } else {
+            write!(
+                f,
+                "#{} {} {} -> {}",
+                self.interaction_number,
+                self.request_data.method,
+                self.request_data.uri,
+                self.response_data
+            )
+        }
+    }
+}
+
+impl Display for RequestData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({} bytes)",
+            self.method,
+            self.uri,
+            self.body.len()
+        )?;
+        write_body_preview(f, &self.body)
+    }
+}
+
+impl Display for ResponseData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({} bytes)", self.status_code, self.body.len())?;
+        write_body_preview(f, &self.body)
+    }
+}
+
+fn write_body_preview(f: &mut Formatter<'_>, body: &str) -> fmt::Result {
+    if !f.alternate() || body.is_empty() {
+        return Ok(());
+    }
+
+    match body.char_indices().nth(BODY_PREVIEW_LENGTH) {
+        Some((index, _)) => write!(f, " \"{}...\"", body[..index].escape_default()),
+        None => write!(f, " \"{}\"", body.escape_default()),
+    }
+}
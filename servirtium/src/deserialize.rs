@@ -0,0 +1,32 @@
+use crate::{error::Error, util};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+
+#[cfg(feature = "json")]
+pub fn json<T: DeserializeOwned>(
+    headers: &HashMap<String, String>,
+    body: &str,
+) -> Result<T, Error> {
+    check_content_type(headers, "json")?;
+    serde_json::from_str(body).map_err(|e| Error::Deserialize(Box::new(e)))
+}
+
+#[cfg(feature = "xml")]
+pub fn xml<T: DeserializeOwned>(headers: &HashMap<String, String>, body: &str) -> Result<T, Error> {
+    check_content_type(headers, "xml")?;
+    quick_xml::de::from_str(body).map_err(|e| Error::Deserialize(Box::new(e)))
+}
+
+// matches both `application/json` and suffixes like `application/problem+json`
+fn check_content_type(headers: &HashMap<String, String>, subtype: &str) -> Result<(), Error> {
+    let content_type = util::content_type(headers).ok_or(Error::MissingContentType)?;
+    let mime_type = content_type.split(';').next().unwrap_or_default().trim();
+
+    if mime_type.ends_with(&format!("/{}", subtype))
+        || mime_type.ends_with(&format!("+{}", subtype))
+    {
+        Ok(())
+    } else {
+        Err(Error::UnexpectedContentType(String::from(content_type)))
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{
+    error::Error, interaction_manager::InteractionManager, InteractionData,
+    MarkdownInteractionManager, ServirtiumConfiguration,
+};
+use std::{path::PathBuf, str};
+
+/// Reads the markdown from static data, e.g. embedded with `include_str!` or `include_bytes!`, so
+/// the tests can be played back without shipping the markdown files. The interactions can't be
+/// saved, so it can only be used for playback.
+#[derive(Debug)]
+pub struct EmbeddedInteractionManager {
+    markdown: &'static [u8],
+    format: MarkdownInteractionManager,
+}
+
+impl EmbeddedInteractionManager {
+    pub fn new(markdown: &'static str) -> Self {
+        Self::from_bytes(markdown.as_bytes())
+    }
+
+    pub fn from_bytes(markdown: &'static [u8]) -> Self {
+        Self {
+            markdown,
+            format: MarkdownInteractionManager::new(PathBuf::new()),
+        }
+    }
+}
+
+impl InteractionManager for EmbeddedInteractionManager {
+    fn load_interactions(
+        &self,
+    ) -> Result<Vec<InteractionData>, Box<dyn std::error::Error + Send + Sync>> {
+        let markdown = str::from_utf8(self.markdown)?;
+
+        Ok(self.format.parse_markdown(markdown)?)
+    }
+
+    fn save_interactions(
+        &self,
+        _interactions: &[InteractionData],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Err(Box::new(Error::ReadOnlyInteractionManager))
+    }
+
+    fn check_data_unchanged(
+        &self,
+        interactions: &[InteractionData],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let stored_interactions = self.load_interactions()?;
+
+        MarkdownInteractionManager::compare_interactions(
+            &stored_interactions,
+            interactions,
+            self.format.strict_body_comparison(),
+        )?;
+
+        Ok(())
+    }
+
+    fn configure(&self, configuration: &ServirtiumConfiguration) {
+        self.format.configure(configuration);
+    }
+}
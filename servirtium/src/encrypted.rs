@@ -0,0 +1,131 @@
+use crate::{
+    error::Error, interaction_manager::InteractionManager, InteractionData,
+    MarkdownInteractionManager, ServirtiumConfiguration,
+};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use std::{env, fs, path::PathBuf};
+
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+/// Stores the markdown of the wrapped manager encrypted with ChaCha20-Poly1305, so fixtures with
+/// sensitive data can be committed.
+///
+/// The key is read from the given environment variable on every load and save and must consist of
+/// 64 hex digits, e.g. generated with `openssl rand -hex 32`. Keep it out of the repository, for
+/// example in the secrets of the CI. The fixtures can be neither played back nor recorded without
+/// it, and they have to be recorded again if it is lost. Every save uses a new random nonce,
+/// which is written in front of the encrypted markdown.
+#[derive(Debug)]
+pub struct EncryptedInteractionManager {
+    markdown: MarkdownInteractionManager,
+    key_variable: String,
+}
+
+impl EncryptedInteractionManager {
+    pub fn new<S: Into<String>>(markdown: MarkdownInteractionManager, key_variable: S) -> Self {
+        Self {
+            markdown,
+            key_variable: key_variable.into(),
+        }
+    }
+
+    fn cipher(&self) -> Result<ChaCha20Poly1305, Error> {
+        let key = env::var(&self.key_variable)
+            .ok()
+            .and_then(|key| decode_hex(key.trim()))
+            .filter(|key| key.len() == KEY_LENGTH)
+            .ok_or_else(|| {
+                Error::Encryption(format!(
+                    "the environment variable {} should contain a key of 64 hex digits",
+                    self.key_variable
+                ))
+            })?;
+
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+}
+
+// `usize::is_multiple_of` needs Rust 1.87
+#[allow(clippy::manual_is_multiple_of)]
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+impl InteractionManager for EncryptedInteractionManager {
+    fn load_interactions(
+        &self,
+    ) -> Result<Vec<InteractionData>, Box<dyn std::error::Error + Send + Sync>> {
+        let cipher = self.cipher()?;
+        let contents =
+            fs::read(self.markdown.markdown_path()).map_err(|e| self.markdown.read_error(e))?;
+
+        if contents.len() < NONCE_LENGTH {
+            return Err(Box::new(Error::Encryption(String::from(
+                "the file is too short",
+            ))));
+        }
+
+        let (nonce, encrypted) = contents.split_at(NONCE_LENGTH);
+        let markdown = cipher
+            .decrypt(Nonce::from_slice(nonce), encrypted)
+            .map_err(|_| Error::Encryption(String::from("the file can't be decrypted")))?;
+        let markdown = String::from_utf8(markdown)
+            .map_err(|_| Error::Encryption(String::from("the decrypted file isn't UTF-8")))?;
+
+        Ok(self.markdown.parse_markdown(&markdown)?)
+    }
+
+    fn save_interactions(
+        &self,
+        interactions: &[InteractionData],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let cipher = self.cipher()?;
+        let mut markdown = Vec::new();
+        self.markdown.write_markdown(&mut markdown, interactions)?;
+
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let encrypted = cipher
+            .encrypt(&nonce, markdown.as_slice())
+            .map_err(|_| Error::Encryption(String::from("the markdown can't be encrypted")))?;
+
+        let mut contents = nonce.to_vec();
+        contents.extend(encrypted);
+        fs::write(self.markdown.markdown_path(), contents)?;
+
+        Ok(())
+    }
+
+    fn check_data_unchanged(
+        &self,
+        interactions: &[InteractionData],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let stored_interactions = self.load_interactions()?;
+
+        MarkdownInteractionManager::compare_interactions(
+            &stored_interactions,
+            interactions,
+            self.markdown.strict_body_comparison(),
+        )?;
+
+        Ok(())
+    }
+
+    fn configure(&self, configuration: &ServirtiumConfiguration) {
+        self.markdown.configure(configuration);
+    }
+
+    fn fixture_paths(&self) -> Vec<PathBuf> {
+        vec![self.markdown.markdown_path().to_path_buf()]
+    }
+}
@@ -1,5 +1,9 @@
+use crate::{
+    markdown,
+    matching::{self, CandidateMatch},
+};
 use hyper::http;
-use std::{fmt::Display, io, sync};
+use std::{fmt::Display, io, net::SocketAddr, sync};
 
 #[derive(Debug)]
 pub enum Error {
@@ -14,7 +18,60 @@ pub enum Error {
     Hyper(hyper::Error),
     ParseUri,
     Http(http::Error),
+    Tls(native_tls::Error),
     InteractionManager(Box<dyn std::error::Error + Send + Sync>),
+    TruncatedResponse {
+        expected: usize,
+        received: usize,
+    },
+    NoMatchingInteraction(String),
+    NoMatchingInteractionCandidates {
+        request: String,
+        candidates: Vec<CandidateMatch>,
+    },
+    InterimResponse(u16),
+    InvalidRedirect(String),
+    TooManyRedirects(String),
+    InvalidHar(String),
+    ConnectionClosed,
+    PathNotAllowed(String),
+    MethodNotAllowed(String),
+    Encryption(String),
+    UnexpectedContentType(String),
+    MissingContentType,
+    Deserialize(Box<dyn std::error::Error + Send + Sync>),
+    RecordingChanged(String),
+    PlaybackUnused,
+    ReadOnlyInteractionManager,
+    RequestTimeout(String),
+    ProbableDeadlock(String),
+    NestedTest,
+    ServerAddressConflict {
+        configured: SocketAddr,
+        running: SocketAddr,
+    },
+    UnexpectedInteractions {
+        expected: Vec<String>,
+        actual: Vec<String>,
+    },
+    MissingRequestHeaders {
+        request: String,
+        missing: Vec<String>,
+    },
+    InvalidJsonl {
+        line: usize,
+        reason: String,
+    },
+    InvalidResponseSchema(String),
+    ResponseSchemaViolation {
+        request: String,
+        reason: String,
+    },
+    InteractionCountMismatch {
+        recorded: usize,
+        requested: usize,
+        unused: Vec<String>,
+    },
 }
 
 impl std::error::Error for Error {}
@@ -32,8 +89,128 @@ impl Display for Error {
             Error::Hyper(e) => write!(f, "Hyper error: {}", e),
             Error::ParseUri => write!(f, "Parse URI Error"),
             Error::Http(e) => write!(f, "Http Error: {}", e),
+            Error::Tls(e) => write!(f, "TLS error: {}", e),
             Error::InvalidDomainName => write!(f, "Couldn't parse the domain name"),
             Error::InteractionManager(e) => write!(f, "Markdown manager error: {}", e),
+            Error::TruncatedResponse { expected, received } => write!(
+                f,
+                "The response is truncated: expected {} bytes, received {}",
+                expected, received
+            ),
+            Error::NoMatchingInteraction(request) => {
+                write!(f, "No recorded interaction matches the request {}", request)
+            }
+            Error::NoMatchingInteractionCandidates {
+                request,
+                candidates,
+            } => {
+                write!(f, "No recorded interaction matches the request {}", request)?;
+                if candidates.is_empty() {
+                    write!(f, ". There are no recorded interactions")
+                } else {
+                    matching::write_candidates(f, candidates)
+                }
+            }
+            Error::InvalidRedirect(location) => {
+                write!(f, "The location of the redirect {} is invalid", location)
+            }
+            Error::TooManyRedirects(request) => write!(
+                f,
+                "The destination API redirected {} too many times, there may be a loop",
+                request
+            ),
+            Error::InterimResponse(status_code) => write!(
+                f,
+                "The HTTP client returned the interim response {} instead of the final one",
+                status_code
+            ),
+            Error::UnexpectedContentType(content_type) => {
+                write!(f, "Unexpected content type: \"{}\"", content_type)
+            }
+            Error::MissingContentType => write!(f, "The Content-Type header is missing"),
+            Error::Deserialize(e) => write!(f, "Couldn't deserialize the body: {}", e),
+            Error::ConnectionClosed => write!(f, "The connection was closed deliberately"),
+            Error::MethodNotAllowed(request) => write!(
+                f,
+                "The request {} wasn't forwarded because its method isn't allowed",
+                request
+            ),
+            Error::Encryption(reason) => write!(f, "Encryption error: {}", reason),
+            Error::PathNotAllowed(uri) => write!(
+                f,
+                "The request to {} wasn't forwarded because its path isn't allowed",
+                uri
+            ),
+            Error::RecordingChanged(changes) => write!(
+                f,
+                "The recorded interactions differ from the previous recording: {}",
+                changes
+            ),
+            Error::PlaybackUnused => write!(
+                f,
+                "The test didn't replay any recorded interaction, the requests may not reach the Servirtium server"
+            ),
+            Error::ReadOnlyInteractionManager => {
+                write!(f, "The interaction manager can't save interactions")
+            }
+            Error::RequestTimeout(request) => write!(
+                f,
+                "The client didn't finish sending the request {} in time",
+                request
+            ),
+            Error::ProbableDeadlock(waited_for) => write!(
+                f,
+                "The session timeout passed while waiting for {}, the Servirtium server has \
+                 probably deadlocked or a test is still running",
+                waited_for
+            ),
+            Error::NestedTest => write!(
+                f,
+                "A Servirtium test was started while the same thread is already running one. Tests \
+                 can't be nested, call TestSession::after_test before starting the next one"
+            ),
+            Error::InvalidHar(reason) => write!(f, "Invalid HAR file: {}", reason),
+            Error::ServerAddressConflict { configured, running } => write!(
+                f,
+                "The Servirtium server can't listen on {} because it's already listening on {}. \
+                 There is a single server per process, so all the tests have to use the same address",
+                configured, running
+            ),
+            Error::UnexpectedInteractions { expected, actual } => write!(
+                f,
+                "The recorded interactions differ from the expected ones. Expected: [{}]. Actual: [{}]",
+                expected.join(", "),
+                actual.join(", ")
+            ),
+            Error::MissingRequestHeaders { request, missing } => write!(
+                f,
+                "The request {} was sent without the expected headers: {}",
+                request,
+                missing.join(", ")
+            ),
+            Error::InteractionCountMismatch {
+                recorded,
+                requested,
+                unused,
+            } => write!(
+                f,
+                "The recording has {} interactions but the client made {} requests. \
+                 Unused interactions: [{}]",
+                recorded,
+                requested,
+                unused.join(", ")
+            ),
+            Error::InvalidJsonl { line, reason } => {
+                write!(f, "Invalid JSONL interaction on line {}: {}", line, reason)
+            }
+            Error::InvalidResponseSchema(reason) => {
+                write!(f, "The JSON schema of the responses is invalid: {}", reason)
+            }
+            Error::ResponseSchemaViolation { request, reason } => write!(
+                f,
+                "The response to {} doesn't conform to its JSON schema: {}",
+                request, reason
+            ),
         }
     }
 }
@@ -73,3 +250,23 @@ impl From<http::Error> for Error {
         Error::Http(e)
     }
 }
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for Error {
+    fn from(e: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        Error::InteractionManager(e)
+    }
+}
+
+/// Markdown parse errors don't have their own variant, they are reported the same way as the
+/// errors of any other interaction manager.
+impl From<markdown::error::Error> for Error {
+    fn from(e: markdown::error::Error) -> Self {
+        Error::InteractionManager(Box::new(e))
+    }
+}
+
+impl From<native_tls::Error> for Error {
+    fn from(e: native_tls::Error) -> Self {
+        Error::Tls(e)
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{
+    interaction_manager::InteractionManager, markdown::error::Error, InteractionData,
+    MarkdownInteractionManager, ServirtiumConfiguration,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::PathBuf,
+};
+
+/// Stores the markdown of the wrapped manager compressed with gzip in a file next to the markdown
+/// path with `.gz` appended, e.g. `weather.md.gz`. The interactions are compared after they have
+/// been decompressed, so the checks behave as with the plain markdown.
+#[derive(Debug)]
+pub struct GzipInteractionManager {
+    markdown: MarkdownInteractionManager,
+}
+
+impl GzipInteractionManager {
+    pub fn new(markdown: MarkdownInteractionManager) -> Self {
+        Self { markdown }
+    }
+
+    pub fn compressed_path(&self) -> PathBuf {
+        let mut path = self.markdown.markdown_path().as_os_str().to_owned();
+        path.push(".gz");
+        path.into()
+    }
+}
+
+impl InteractionManager for GzipInteractionManager {
+    fn load_interactions(
+        &self,
+    ) -> Result<Vec<InteractionData>, Box<dyn std::error::Error + Send + Sync>> {
+        let compressed_path = self.compressed_path();
+        let file = File::open(&compressed_path).map_err(|e| Error::read(&compressed_path, e))?;
+
+        let mut bytes = Vec::new();
+        GzDecoder::new(file).read_to_end(&mut bytes)?;
+        let markdown = self.markdown.decode_markdown(bytes, 0)?;
+
+        Ok(self.markdown.parse_markdown(&markdown)?)
+    }
+
+    fn save_interactions(
+        &self,
+        interactions: &[InteractionData],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        self.markdown.write_markdown(&mut encoder, interactions)?;
+        fs::write(self.compressed_path(), encoder.finish()?)?;
+
+        Ok(())
+    }
+
+    fn check_data_unchanged(
+        &self,
+        interactions: &[InteractionData],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let stored_interactions = self.load_interactions()?;
+
+        MarkdownInteractionManager::compare_interactions(
+            &stored_interactions,
+            interactions,
+            self.markdown.strict_body_comparison(),
+        )?;
+
+        Ok(())
+    }
+
+    fn configure(&self, configuration: &ServirtiumConfiguration) {
+        self.markdown.configure(configuration);
+    }
+
+    fn fixture_paths(&self) -> Vec<PathBuf> {
+        vec![self.compressed_path()]
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{
+    error::Error, util, HttpClient, InteractionData, InteractionManager, RequestData,
+    ReqwestHttpClient,
+};
+use hyper::Uri;
+use serde_json::Value;
+use std::{collections::HashMap, fs, path::Path, time::Instant};
+use tokio::runtime::Runtime;
+
+/// Replays the requests of a HAR capture against the hosts they were originally sent to and saves
+/// the responses as interactions with the given interaction manager. It's meant to seed fixtures
+/// from real browser sessions rather than to be used in tests. The function blocks, so it must
+/// not be called from within an async runtime.
+pub fn record_har<P: AsRef<Path>>(
+    har_path: P,
+    interaction_manager: &dyn InteractionManager,
+) -> Result<(), Error> {
+    let har: Value = serde_json::from_str(&fs::read_to_string(har_path)?)
+        .map_err(|e| Error::InvalidHar(e.to_string()))?;
+    let entries = har["log"]["entries"]
+        .as_array()
+        .ok_or_else(|| Error::InvalidHar(String::from("log.entries is missing")))?;
+
+    let http_client = ReqwestHttpClient::new();
+    let runtime = Runtime::new()?;
+    let mut interactions = Vec::new();
+
+    for (index, entry) in entries.iter().enumerate() {
+        let (domain_name, request_data) = read_request(&entry["request"])?;
+        let start = Instant::now();
+        let response_data =
+            runtime.block_on(http_client.make_request(&domain_name, &request_data))?;
+
+        interactions.push(InteractionData {
+            interaction_number: index,
+            request_data,
+            response_data,
+            response_time: Some(start.elapsed()),
+        });
+    }
+
+    Ok(interaction_manager.save_interactions(&interactions)?)
+}
+
+fn read_request(request: &Value) -> Result<(String, RequestData), Error> {
+    let invalid_entry = |field: &str| Error::InvalidHar(format!("request.{} is missing", field));
+
+    let method = request["method"]
+        .as_str()
+        .ok_or_else(|| invalid_entry("method"))?;
+    let url = request["url"]
+        .as_str()
+        .ok_or_else(|| invalid_entry("url"))?
+        .parse::<Uri>()
+        .map_err(|_| Error::ParseUri)?;
+
+    let domain_name = format!(
+        "{}://{}",
+        url.scheme_str().ok_or(Error::ParseUri)?,
+        url.authority().ok_or(Error::ParseUri)?
+    );
+    let uri = url
+        .path_and_query()
+        .map_or_else(|| String::from("/"), |path| path.to_string());
+
+    let mut headers = HashMap::new();
+    for header in request["headers"].as_array().into_iter().flatten() {
+        if let (Some(name), Some(value)) = (header["name"].as_str(), header["value"].as_str()) {
+            // HTTP/2 captures contain pseudo-headers like `:authority`
+            if !name.starts_with(':') {
+                util::append_header_value(&mut headers, &name.to_lowercase(), value);
+            }
+        }
+    }
+
+    let body = request["postData"]["text"]
+        .as_str()
+        .map(String::from)
+        .unwrap_or_default();
+
+    Ok((
+        domain_name,
+        RequestData {
+            uri,
+            method: method.to_uppercase(),
+            http_version: request["httpVersion"].as_str().map(String::from),
+            headers,
+            body,
+        },
+    ))
+}
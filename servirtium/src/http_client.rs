@@ -1,8 +1,21 @@
-use crate::{error::Error, util, RequestData, ResponseData};
+use crate::{charset, error::Error, util, RequestData, ResponseData};
 use async_trait::async_trait;
-use hyper::{body, Body, HeaderMap, Request};
+use hyper::{
+    body::HttpBody, client::HttpConnector, ext::ReasonPhrase, Body, Client, Request, Response,
+};
 use hyper_tls::HttpsConnector;
-use std::{collections::HashMap, fmt::Debug};
+use std::{
+    error::Error as _,
+    fmt::Debug,
+    io::{self, Write},
+    panic,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::{self, Instant};
+
+pub(crate) const DEFAULT_EVENT_STREAM_MAX_EVENTS: usize = 100;
+pub(crate) const DEFAULT_EVENT_STREAM_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[async_trait]
 pub trait HttpClient: Debug {
@@ -11,64 +24,320 @@ pub trait HttpClient: Debug {
         url: &str,
         request_data: &RequestData,
     ) -> Result<ResponseData, Error>;
+
+    /// Makes a request and streams the response body into `body_writer` once it grows beyond
+    /// `threshold` bytes, leaving `ResponseData::body` empty. Smaller bodies are returned as usual.
+    /// The default implementation buffers the whole body before writing it.
+    async fn make_streaming_request(
+        &self,
+        url: &str,
+        request_data: &RequestData,
+        threshold: usize,
+        mut body_writer: Box<dyn Write + Send>,
+    ) -> Result<ResponseData, Error> {
+        let mut response_data = self.make_request(url, request_data).await?;
+
+        if response_data.body.len() > threshold {
+            body_writer.write_all(response_data.body.as_bytes())?;
+            response_data.body.clear();
+        }
+
+        Ok(response_data)
+    }
+
+    /// Makes a request and also returns the response body as it was received, before it was
+    /// decoded as UTF-8. The default implementation returns the bytes of the decoded body.
+    async fn make_raw_request(
+        &self,
+        url: &str,
+        request_data: &RequestData,
+    ) -> Result<(ResponseData, Vec<u8>), Error> {
+        let response_data = self.make_request(url, request_data).await?;
+        let raw_body = response_data.body.as_bytes().to_vec();
+
+        Ok((response_data, raw_body))
+    }
+}
+
+/// A synchronous alternative to [`HttpClient`] for clients that block, e.g. `reqwest::blocking`.
+/// It can be used with `ServirtiumConfiguration::set_blocking_http_client`.
+pub trait BlockingHttpClient: Debug {
+    fn make_request(&self, url: &str, request_data: &RequestData) -> Result<ResponseData, Error>;
+}
+
+/// Runs a [`BlockingHttpClient`] on the blocking thread pool of the tokio runtime the Servirtium
+/// server runs on, so the blocking requests don't stall the server. The client can create its own
+/// runtime if it needs one, because it isn't called from an async context.
+#[derive(Debug)]
+pub struct BlockingHttpClientAdapter<C> {
+    client: Arc<C>,
+}
+
+impl<C> BlockingHttpClientAdapter<C> {
+    pub fn new(client: C) -> Self {
+        Self {
+            client: Arc::new(client),
+        }
+    }
+}
+
+#[async_trait]
+impl<C: BlockingHttpClient + Send + Sync + 'static> HttpClient for BlockingHttpClientAdapter<C> {
+    async fn make_request(
+        &self,
+        url: &str,
+        request_data: &RequestData,
+    ) -> Result<ResponseData, Error> {
+        let client = self.client.clone();
+        let url = url.to_owned();
+        let request_data = request_data.clone();
+
+        tokio::task::spawn_blocking(move || client.make_request(&url, &request_data))
+            .await
+            .unwrap_or_else(|e| panic::resume_unwind(e.into_panic()))
+    }
 }
 
 #[derive(Debug)]
-pub struct ReqwestHttpClient {}
+pub struct ReqwestHttpClient {
+    accept_invalid_certs: bool,
+    event_stream_max_events: usize,
+    event_stream_timeout: Duration,
+    /// Created on the first request and shared by all the following ones, so that connections to
+    /// the destination API are pooled and reused.
+    client: Arc<Mutex<Option<Client<HttpsConnector<HttpConnector>>>>>,
+}
 
 impl ReqwestHttpClient {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            accept_invalid_certs: false,
+            event_stream_max_events: DEFAULT_EVENT_STREAM_MAX_EVENTS,
+            event_stream_timeout: DEFAULT_EVENT_STREAM_TIMEOUT,
+            client: Arc::new(Mutex::new(None)),
+        }
     }
 
-    fn extract_headers(header_map: &HeaderMap) -> HashMap<String, String> {
-        // it currently ignores header values with opaque characters
-        header_map
-            .iter()
-            .map(|(k, v)| (String::from(k.as_str()), v.to_str()))
-            .filter_map(|(key, value)| value.ok().map(|v| (key, String::from(v))))
-            .collect::<HashMap<_, _>>()
+    /// `text/event-stream` responses usually never end, so their body is only read until it
+    /// contains `max_events` events or until `timeout` has passed since the response started.
+    pub fn with_event_stream_limits(mut self, max_events: usize, timeout: Duration) -> Self {
+        self.event_stream_max_events = max_events;
+        self.event_stream_timeout = timeout;
+        self
     }
-}
 
-#[async_trait]
-impl HttpClient for ReqwestHttpClient {
-    async fn make_request(
+    /// Disables the verification of the certificates and the host names of the destination API.
+    /// Only use it for test environments with self-signed certificates or private CAs.
+    pub fn with_danger_accept_invalid_certs(mut self, accept_invalid_certs: bool) -> Self {
+        self.accept_invalid_certs = accept_invalid_certs;
+        self.client = Arc::new(Mutex::new(None));
+        self
+    }
+
+    fn client(&self) -> Result<Client<HttpsConnector<HttpConnector>>, Error> {
+        let mut client = self.client.lock().unwrap();
+
+        if client.is_none() {
+            *client = Some(Client::builder().build(self.https_connector()?));
+        }
+
+        // cloning a hyper client is cheap and keeps sharing the connection pool
+        Ok(client.as_ref().unwrap().clone())
+    }
+
+    fn https_connector(&self) -> Result<HttpsConnector<HttpConnector>, Error> {
+        let tls_connector = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(self.accept_invalid_certs)
+            .danger_accept_invalid_hostnames(self.accept_invalid_certs)
+            .build()?;
+
+        let mut http_connector = HttpConnector::new();
+        http_connector.enforce_http(false);
+
+        Ok(HttpsConnector::from((
+            http_connector,
+            tokio_native_tls::TlsConnector::from(tls_connector),
+        )))
+    }
+
+    async fn send(
         &self,
         domain_name: &str,
         request_data: &RequestData,
-    ) -> Result<ResponseData, Error> {
-        let url = format!("{}{}", domain_name, request_data.uri);
+    ) -> Result<Response<Body>, Error> {
+        let url = util::join_url(domain_name, &request_data.uri);
         let mut request_builder = Request::builder()
             .uri(url.as_str())
             .method(request_data.method.as_str());
 
+        let mut headers = request_data.headers.clone();
+        let body = charset::encode(&headers, request_data.body.clone());
+        if body.len() != request_data.body.len() {
+            util::set_content_length(&mut headers, body.len());
+        }
+
         if let Some(headers_mut) = request_builder.headers_mut() {
             util::put_headers(
                 headers_mut,
-                request_data
-                    .headers
+                headers
                     .iter()
                     .filter(|(header_name, _)| header_name.as_str() != "host"),
             )?;
         }
 
-        let request: Request<Body> = request_builder.body(request_data.body.clone().into())?;
-
-        let client = hyper::Client::builder().build(HttpsConnector::new());
+        let request: Request<Body> = request_builder.body(body.into())?;
 
-        let response = client.request(request).await?;
+        Ok(self.client()?.request(request).await?)
+    }
 
+    /// Reads the response body, buffering at most `threshold` bytes before switching to the
+    /// writer. The returned body and the raw bytes are empty if it has been streamed.
+    async fn read_body(
+        &self,
+        response: Response<Body>,
+        threshold: usize,
+        mut body_writer: Option<Box<dyn Write + Send>>,
+    ) -> Result<(ResponseData, Vec<u8>), Error> {
         let status_code = response.status().as_u16();
-        let headers = Self::extract_headers(response.headers());
-        let body = body::to_bytes(response.into_body()).await?;
-        let body: String = String::from_utf8_lossy(&body).into();
+        // hyper only keeps the reason phrase if it differs from the canonical one
+        let reason_phrase = response
+            .extensions()
+            .get::<ReasonPhrase>()
+            .map(|reason_phrase| String::from_utf8_lossy(reason_phrase.as_bytes()).into_owned());
+        let headers = util::extract_headers(response.headers());
+        let mut body = response.into_body();
+
+        let event_stream = util::find_header(&headers, "content-type")
+            .is_some_and(|content_type| content_type.trim_start().starts_with("text/event-stream"));
+        let deadline = Instant::now() + self.event_stream_timeout;
+        // event streams are cut short on purpose when they reach the limits
+        let expected_length = util::content_length(&headers).filter(|_| !event_stream);
+
+        let mut buffer = Vec::new();
+        let mut received = 0;
+        let mut streaming = false;
+
+        loop {
+            let chunk = if event_stream {
+                match time::timeout_at(deadline, body.data()).await {
+                    Ok(chunk) => chunk,
+                    Err(_) => break,
+                }
+            } else {
+                body.data().await
+            };
+
+            let chunk = match chunk {
+                Some(Ok(chunk)) => chunk,
+                // hyper fails if the connection closes before the end of the body, which is
+                // reported with the lengths below
+                Some(Err(e)) if Self::is_unexpected_eof(&e) && expected_length.is_some() => break,
+                Some(Err(e)) => return Err(e.into()),
+                None => break,
+            };
+            received += chunk.len();
+
+            match body_writer.as_mut() {
+                Some(writer) if streaming => writer.write_all(&chunk)?,
+                Some(writer) if buffer.len() + chunk.len() > threshold => {
+                    writer.write_all(&buffer)?;
+                    writer.write_all(&chunk)?;
+                    buffer.clear();
+                    streaming = true;
+                }
+                _ => buffer.extend_from_slice(&chunk),
+            }
 
-        Ok(ResponseData {
+            if event_stream {
+                if let Some(end) = Self::events_end(&buffer, self.event_stream_max_events) {
+                    buffer.truncate(end);
+                    break;
+                }
+            }
+        }
+
+        if let Some(expected) = expected_length {
+            if received < expected {
+                return Err(Error::TruncatedResponse { expected, received });
+            }
+        }
+
+        let response_data = ResponseData {
             status_code,
-            body,
+            reason_phrase,
+            body: charset::decode(&headers, &buffer),
             headers,
-        })
+        };
+
+        Ok((response_data, buffer))
+    }
+
+    fn is_unexpected_eof(error: &hyper::Error) -> bool {
+        error
+            .source()
+            .and_then(|source| source.downcast_ref::<io::Error>())
+            .is_some_and(|source| source.kind() == io::ErrorKind::UnexpectedEof)
+    }
+
+    /// Returns the position after the blank line that ends the `max_events`-th event.
+    fn events_end(buffer: &[u8], max_events: usize) -> Option<usize> {
+        let mut events = 0;
+        let mut line_start = 0;
+
+        for (index, byte) in buffer.iter().enumerate() {
+            if *byte != b'\n' {
+                continue;
+            }
+
+            let line = &buffer[line_start..index];
+            if line.is_empty() || line == b"\r" {
+                events += 1;
+                if events >= max_events {
+                    return Some(index + 1);
+                }
+            }
+            line_start = index + 1;
+        }
+
+        None
+    }
+}
+
+#[async_trait]
+impl HttpClient for ReqwestHttpClient {
+    async fn make_request(
+        &self,
+        domain_name: &str,
+        request_data: &RequestData,
+    ) -> Result<ResponseData, Error> {
+        let (response_data, _) = self.make_raw_request(domain_name, request_data).await?;
+
+        Ok(response_data)
+    }
+
+    async fn make_streaming_request(
+        &self,
+        domain_name: &str,
+        request_data: &RequestData,
+        threshold: usize,
+        body_writer: Box<dyn Write + Send>,
+    ) -> Result<ResponseData, Error> {
+        let response = self.send(domain_name, request_data).await?;
+        let (response_data, _) = self
+            .read_body(response, threshold, Some(body_writer))
+            .await?;
+
+        Ok(response_data)
+    }
+
+    async fn make_raw_request(
+        &self,
+        domain_name: &str,
+        request_data: &RequestData,
+    ) -> Result<(ResponseData, Vec<u8>), Error> {
+        let response = self.send(domain_name, request_data).await?;
+
+        self.read_body(response, usize::MAX, None).await
     }
 }
 
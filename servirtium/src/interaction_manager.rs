@@ -1,5 +1,5 @@
-use crate::InteractionData;
-use std::fmt::Debug;
+use crate::{InteractionData, ServirtiumConfiguration};
+use std::{fmt::Debug, io::Write, path::PathBuf};
 
 pub trait InteractionManager: Debug {
     fn load_interactions(
@@ -13,4 +13,42 @@ pub trait InteractionManager: Debug {
         &self,
         interactions: &[InteractionData],
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Called in record mode for responses larger than the configured streaming threshold.
+    /// Managers that store bodies outside of the main file can return a writer, and the response
+    /// body is then streamed into it instead of being kept in `ResponseData::body`, which is left
+    /// empty while the `Content-Length` header keeps the length of the streamed body. The manager
+    /// has to remember which bodies it streamed to save them. By default the body is buffered in
+    /// memory.
+    fn response_body_writer(
+        &self,
+        _interaction_number: usize,
+    ) -> Result<Option<Box<dyn Write + Send>>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(None)
+    }
+
+    /// Called in record mode if `ServirtiumConfiguration::set_dump_raw` is enabled, with the
+    /// request and response bodies as they were received, before they were decoded as UTF-8. It's
+    /// only meant to diagnose encoding issues, the bodies aren't used for playback. By default
+    /// they are discarded.
+    fn save_raw_bodies(
+        &self,
+        _interaction_number: usize,
+        _request_body: &[u8],
+        _response_body: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
+
+    /// Called when a test starts with its configuration, so the manager can follow the settings
+    /// about how the interactions are read and compared, e.g.
+    /// `ServirtiumConfiguration::set_strict_body_comparison`. By default they are ignored.
+    fn configure(&self, _configuration: &ServirtiumConfiguration) {}
+
+    /// Returns the files the interactions are stored in, which are collected by
+    /// [`referenced_fixtures`](crate::referenced_fixtures) when a test starts. By default there
+    /// are none, e.g. for the managers that keep the interactions in memory.
+    fn fixture_paths(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
 }
@@ -0,0 +1,171 @@
+use crate::{
+    error::Error, interaction_manager::InteractionManager, InteractionData,
+    MarkdownInteractionManager, RequestData, ResponseData,
+};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Stores the interactions as JSON Lines, one interaction per line. Saving appends the
+/// interactions to the file instead of rewriting it, so a single file can grow over many tests
+/// and be read while it's being written. Since the file keeps the interactions of the previous
+/// runs, the checks for a changed recording compare against all of them, and
+/// `set_fail_if_markdown_changed` shouldn't be used with it.
+#[derive(Debug)]
+pub struct JsonlInteractionManager {
+    path: PathBuf,
+}
+
+impl JsonlInteractionManager {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn to_json(interaction: &InteractionData) -> Value {
+        let request_data = &interaction.request_data;
+        let response_data = &interaction.response_data;
+
+        json!({
+            "interaction_number": interaction.interaction_number,
+            "request": {
+                "method": request_data.method,
+                "uri": request_data.uri,
+                "http_version": request_data.http_version,
+                "headers": request_data.headers,
+                "body": request_data.body,
+            },
+            "response": {
+                "status_code": response_data.status_code,
+                "reason_phrase": response_data.reason_phrase,
+                "headers": response_data.headers,
+                "body": response_data.body,
+            },
+            "response_time_ms": interaction
+                .response_time
+                .map(|response_time| response_time.as_millis() as u64),
+        })
+    }
+
+    fn from_json(value: &Value) -> Result<InteractionData, String> {
+        let string = |value: &Value, field: &str| {
+            value[field]
+                .as_str()
+                .map(String::from)
+                .ok_or_else(|| format!("{} is missing", field))
+        };
+        let headers = |value: &Value| -> Result<HashMap<String, String>, String> {
+            value["headers"]
+                .as_object()
+                .map(Map::iter)
+                .into_iter()
+                .flatten()
+                .map(|(name, value)| match value.as_str() {
+                    Some(value) => Ok((name.clone(), String::from(value))),
+                    None => Err(format!("the value of the header {} isn't a string", name)),
+                })
+                .collect()
+        };
+
+        let request = &value["request"];
+        let response = &value["response"];
+
+        Ok(InteractionData {
+            interaction_number: value["interaction_number"]
+                .as_u64()
+                .and_then(|number| usize::try_from(number).ok())
+                .ok_or("interaction_number is missing or invalid")?,
+            request_data: RequestData {
+                uri: string(request, "uri")?,
+                method: string(request, "method")?,
+                http_version: request["http_version"].as_str().map(String::from),
+                headers: headers(request)?,
+                body: string(request, "body")?,
+            },
+            response_data: ResponseData {
+                status_code: response["status_code"]
+                    .as_u64()
+                    .and_then(|status_code| u16::try_from(status_code).ok())
+                    .ok_or("status_code is missing or invalid")?,
+                reason_phrase: response["reason_phrase"].as_str().map(String::from),
+                headers: headers(response)?,
+                body: string(response, "body")?,
+            },
+            response_time: value["response_time_ms"]
+                .as_u64()
+                .map(Duration::from_millis),
+        })
+    }
+}
+
+impl InteractionManager for JsonlInteractionManager {
+    fn load_interactions(
+        &self,
+    ) -> Result<Vec<InteractionData>, Box<dyn std::error::Error + Send + Sync>> {
+        let reader = BufReader::new(File::open(&self.path)?);
+        let mut interactions = Vec::new();
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let invalid_line = |reason: String| Error::InvalidJsonl {
+                line: index + 1,
+                reason,
+            };
+            let value: Value =
+                serde_json::from_str(&line).map_err(|e| invalid_line(e.to_string()))?;
+            interactions.push(Self::from_json(&value).map_err(invalid_line)?);
+        }
+
+        Ok(interactions)
+    }
+
+    fn save_interactions(
+        &self,
+        interactions: &[InteractionData],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut writer = BufWriter::new(file);
+
+        for interaction in interactions {
+            writeln!(writer, "{}", Self::to_json(interaction))?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    fn check_data_unchanged(
+        &self,
+        interactions: &[InteractionData],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let stored_interactions = self.load_interactions()?;
+
+        MarkdownInteractionManager::compare_interactions(
+            &stored_interactions,
+            interactions,
+            false,
+        )?;
+
+        Ok(())
+    }
+
+    fn fixture_paths(&self) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+}
@@ -1,23 +1,72 @@
+mod charset;
+mod comparison;
 mod data;
+#[cfg(any(feature = "json", feature = "xml"))]
+mod deserialize;
+mod embedded;
+#[cfg(feature = "encryption")]
+mod encrypted;
 mod error;
+#[cfg(feature = "gzip")]
+mod gzip;
+#[cfg(feature = "har")]
+mod har;
 mod http_client;
 mod interaction_manager;
+#[cfg(feature = "json")]
+mod jsonl;
 mod markdown;
+mod matching;
+mod memory;
+mod multipart;
 mod mutations;
+mod playback_handle;
+mod range;
+mod redirect;
 mod runner;
 mod servirtium_configuration;
 mod servirtium_server;
+mod stream;
 mod test_session;
 mod util;
 
+pub use comparison::compare_headers;
 pub use data::{InteractionData, RequestData, ResponseData};
-pub use http_client::{HttpClient, ReqwestHttpClient};
+pub use embedded::EmbeddedInteractionManager;
+#[cfg(feature = "encryption")]
+pub use encrypted::EncryptedInteractionManager;
+pub use error::Error;
+#[cfg(feature = "gzip")]
+pub use gzip::GzipInteractionManager;
+#[cfg(feature = "har")]
+pub use har::record_har;
+pub use http_client::{
+    BlockingHttpClient, BlockingHttpClientAdapter, HttpClient, ReqwestHttpClient,
+};
 pub use interaction_manager::InteractionManager;
-pub use markdown::MarkdownInteractionManager;
+#[cfg(feature = "json")]
+pub use jsonl::JsonlInteractionManager;
+pub use markdown::{
+    error::{
+        Error as MarkdownError, MarkdownsBodyDifference, MarkdownsDifference,
+        MarkdownsDifferenceLocation, MarkdownsDifferenceType, MarkdownsHeaderDifference,
+    },
+    ExternalBodies, HeaderOrder, IndexedInteraction, MarkdownInteractionManager, NonUtf8BodyPolicy,
+};
+pub use matching::{CandidateMatch, MatchConfiguration, NoMatchBehavior};
+pub use memory::MemoryInteractionManager;
+#[cfg(feature = "json")]
+pub use mutations::JsonMaskKeysMutation;
+#[cfg(feature = "pretty-xml")]
+pub use mutations::XmlPrettyPrintMutation;
 pub use mutations::{
-    BodyMutation, HeadersMutation, MutationsBuilder, RequestMutation, ResponseMutation,
+    BodyMutation, ConditionalMutation, FullRequestMutation, FullResponseMutation, HeadersMutation,
+    MutationsBuilder, RequestMutation, ResponseMutation, UrlRewriteMutation,
 };
+pub use playback_handle::PlaybackHandle;
+pub use redirect::RedirectPolicy;
 pub use servirtium_codegen::{servirtium_playback_test, servirtium_record_test};
 pub use servirtium_configuration::ServirtiumConfiguration;
 pub use servirtium_server::{ServirtiumMode, ServirtiumServer};
-pub use test_session::TestSession;
+pub use stream::StreamInteractionManager;
+pub use test_session::{referenced_fixtures, start, ServerGuard, TestSession, TestSessionGuard};
@@ -1,15 +1,39 @@
 use std::fmt::Formatter;
-use std::{fmt::Display, io};
+use std::{
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug)]
 pub enum Error {
     Io(io::Error),
+    MarkdownNotFound(PathBuf),
+    BodyFileNotFound(PathBuf),
     InvalidMarkdownFormat,
     InvalidInteractionNumber,
     InvalidStatusCode,
+    InvalidUtf8 {
+        path: PathBuf,
+        offset: u64,
+    },
+    InvalidBase64Body {
+        interaction_number: usize,
+        reason: String,
+    },
     MarkdownsDiffer(MarkdownsDifferenceType, MarkdownsDifferenceLocation),
 }
 
+impl Error {
+    pub(crate) fn read(markdown_path: &Path, e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::NotFound {
+            Error::MarkdownNotFound(markdown_path.into())
+        } else {
+            Error::Io(e)
+        }
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self {
         Error::Io(e)
@@ -23,7 +47,34 @@ impl Display for Error {
         match self {
             Error::InvalidMarkdownFormat => write!(f, "Markdown format is invalid"),
             Error::Io(e) => write!(f, "IoError: {}", e),
+            Error::MarkdownNotFound(path) => write!(
+                f,
+                "The markdown file {} doesn't exist. Run the test in record mode first to create it",
+                path.display()
+            ),
+            Error::BodyFileNotFound(path) => write!(
+                f,
+                "The body file {} linked from the markdown file doesn't exist. Run the test in \
+                 record mode first to create it",
+                path.display()
+            ),
             Error::InvalidStatusCode => write!(f, "The status code is invalid"),
+            Error::InvalidUtf8 { path, offset } => write!(
+                f,
+                "The file {} isn't valid UTF-8 at byte {}. Fix the file, base64-encode the body \
+                 and read it with NonUtf8BodyPolicy::Base64, or read it with \
+                 NonUtf8BodyPolicy::Lossy",
+                path.display(),
+                offset
+            ),
+            Error::InvalidBase64Body {
+                interaction_number,
+                reason,
+            } => write!(
+                f,
+                "A base64 body of interaction {} can't be decoded: {}",
+                interaction_number, reason
+            ),
             Error::InvalidInteractionNumber => write!(
                 f,
                 "Couldn't parse interaction number from the markdown file"
@@ -39,6 +90,15 @@ impl Display for Error {
 pub enum MarkdownsDifferenceType {
     Body(MarkdownsBodyDifference),
     Header(MarkdownsHeaderDifference),
+    /// The method or the URI of the requests differ, e.g. `GET /users`.
+    Signature {
+        old: String,
+        new: String,
+    },
+    StatusCode {
+        old: u16,
+        new: u16,
+    },
 }
 
 impl Display for MarkdownsDifferenceType {
@@ -46,11 +106,54 @@ impl Display for MarkdownsDifferenceType {
         match self {
             MarkdownsDifferenceType::Body(b) => write!(f, "{}", b),
             MarkdownsDifferenceType::Header(h) => write!(f, "{}", h),
+            MarkdownsDifferenceType::Signature { old, new } => {
+                write!(f, "Requests differ. Old: \"{}\". New: \"{}\"", old, new)
+            }
+            MarkdownsDifferenceType::StatusCode { old, new } => {
+                write!(f, "Status codes differ. Old: {}. New: {}", old, new)
+            }
         }
     }
 }
 
+/// A difference between two recordings found by
+/// [`MarkdownInteractionManager::compare`](crate::MarkdownInteractionManager::compare).
 #[derive(Debug)]
+pub enum MarkdownsDifference {
+    InteractionCount {
+        old: usize,
+        new: usize,
+    },
+    /// A difference in the interaction at the given position of both recordings.
+    Interaction {
+        index: usize,
+        location: MarkdownsDifferenceLocation,
+        difference_type: MarkdownsDifferenceType,
+    },
+}
+
+impl Display for MarkdownsDifference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MarkdownsDifference::InteractionCount { old, new } => write!(
+                f,
+                "The number of interactions differs. Old: {}. New: {}",
+                old, new
+            ),
+            MarkdownsDifference::Interaction {
+                index,
+                location,
+                difference_type,
+            } => write!(
+                f,
+                "Interaction {}: {} - {}",
+                index, location, difference_type
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MarkdownsDifferenceLocation {
     Request,
     Response,
@@ -1,98 +1,530 @@
 pub mod error;
 
-use crate::{interaction_manager::InteractionManager, InteractionData, RequestData, ResponseData};
+use crate::{
+    charset, comparison, interaction_manager::InteractionManager, multipart, util, InteractionData,
+    RequestData, ResponseData, ServirtiumConfiguration,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
 use error::{
-    Error, MarkdownsBodyDifference, MarkdownsDifferenceLocation, MarkdownsDifferenceType,
-    MarkdownsHeaderDifference,
+    Error, MarkdownsBodyDifference, MarkdownsDifference, MarkdownsDifferenceLocation,
+    MarkdownsDifferenceType,
 };
 use fs::File;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{
+    borrow::Cow,
     collections::{HashMap, HashSet},
     fs,
-    io::Write,
-    path::PathBuf,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
 };
 
 lazy_static! {
-    static ref HEADER_REGEX: Regex =
-        Regex::new(r"(?m)(?P<header_key>[a-zA-Z\-]+): (?P<header_value>.*?)$").unwrap();
+    // `$` only matches before `\n`, so the `\r` of files with Windows line endings is left out of
+    // the value explicitly. The space after the colon is optional because editors strip it from
+    // headers with an empty value.
+    static ref HEADER_REGEX: Regex = Regex::new(
+        r"(?m)(?P<header_key>[a-zA-Z\-]+): ?(?P<header_value>[^\r\n]*)\r?$"
+    )
+    .unwrap();
+    static ref INTERACTION_HEADING_REGEX: Regex = Regex::new(
+        r"^## Interaction (?P<interaction_number>[0-9]+): (?P<http_method>[A-Z]+) (?P<uri>[^ \r\n]*)"
+    )
+    .unwrap();
+    static ref PART_REGEX: Regex =
+        Regex::new("(?s)\\#\\#\\#\\# Part [0-9]+:\\s*```(?P<part>.*?)```").unwrap();
     static ref MARKDOWN_REGEX: Regex = Regex::new(
         "(?ms)\
-            \\#\\# Interaction (?P<interaction_number>[0-9]+): (?P<http_method>[A-Z]+) (?P<uri>[^ ]*)\
+            \\#\\# Interaction (?P<interaction_number>[0-9]+): (?P<http_method>[A-Z]+) (?P<uri>[^ \\r\\n]*)\
+            (?: (?P<http_version>HTTP/[0-9.]+))?\\s*\
+            (?:Response time: (?P<response_time>[0-9]+) ms\\s*)?\
             \\#\\#\\# Request headers recorded for playback.*?\
             ```\\s*(?P<request_headers_part>.*?)\\s*```.*?\
             \\#\\#\\# Request body recorded for playback.*?\
-            ```\\s*(?P<request_body_part>.*?)\\s*```.*?\
+            (?:(?P<request_body_parts>(?:\\#\\#\\#\\# Part [0-9]+:\\s*```.*?```\\s*)+)|\
+            ```(?P<request_body_part>.*?)```|\\[[^\\]\\r\\n]*\\]\\((?P<request_body_file>[^)\\r\\n]+)\\)).*?\
             \\#\\#\\# Response headers recorded for playback.*?\
             ```\\s*(?P<response_headers_part>.*?)\\s*```.*?\
-            \\#\\#\\# Response body recorded for playback \\((?P<status_code>[0-9]+)[^)]*\\).*?\
-            ```\\s*(?P<response_body_part>.*?)\\s*```"
+            \\#\\#\\# Response body recorded for playback \\((?P<status_code>[0-9]+)(?: (?P<reason_phrase>[^:)]+))?[^)]*\\).*?\
+            (?:```(?P<response_body_part>.*?)```|\\[[^\\]\\r\\n]*\\]\\((?P<response_body_file>[^)\\r\\n]+)\\))"
     )
     .unwrap();
 }
 
+/// The order the headers are written in. Header maps don't keep the order of distinct header
+/// names, but the values of a repeated header (e.g. `Set-Cookie` or `Via`) are always written in
+/// the order they were received.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum HeaderOrder {
+    /// Sorts the headers by name, which keeps the diffs stable.
+    #[default]
+    Sorted,
+    /// Writes the given headers first, in the given order, followed by the rest sorted by name.
+    Leading(Vec<String>),
+    /// Writes the headers in the order of the header map without sorting them. The order of
+    /// distinct names can change between recordings, so the diffs are less stable.
+    Unsorted,
+}
+
+/// What happens when a body of a markdown file isn't valid UTF-8, e.g. because it was edited by
+/// hand or written by another tool. The bodies are kept as text, so invalid bytes can't be played
+/// back as they are unless they are valid in the charset of the body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonUtf8BodyPolicy {
+    /// Fails with `MarkdownError::InvalidUtf8`, which tells where the first invalid byte is.
+    #[default]
+    Error,
+    /// Reads the bodies whose code block is marked as base64, e.g. ```` ```base64 ````, as base64,
+    /// and decodes the bytes with the charset of their `Content-Type`, UTF-8 by default. Fails
+    /// with `MarkdownError::InvalidBase64Body` if a body isn't valid base64 or if its bytes aren't
+    /// valid in the charset, and like `Error` if the markdown file isn't valid UTF-8.
+    Base64,
+    /// Replaces the invalid bytes with `U+FFFD`, the replacement character.
+    Lossy,
+}
+
+/// Which bodies [`MarkdownInteractionManager::with_external_bodies`] writes to files next to the
+/// markdown file instead of inline. A body is written to a file if it's at least as long as the
+/// minimum length or if its content type is one of the listed ones. Empty bodies are always
+/// written inline.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ExternalBodies {
+    min_length: Option<usize>,
+    content_types: Vec<String>,
+}
+
+impl ExternalBodies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the bodies of at least `min_length` bytes to files.
+    pub fn with_min_length(mut self, min_length: usize) -> Self {
+        self.min_length = Some(min_length);
+        self
+    }
+
+    /// Writes the bodies with the given media type to files whatever their length, e.g.
+    /// `application/pdf`. The media types are compared case-insensitively and without their
+    /// parameters, and a subtype of `*` matches all of them, e.g. `image/*`.
+    pub fn with_content_type<S: Into<String>>(mut self, content_type: S) -> Self {
+        self.content_types
+            .push(content_type.into().to_ascii_lowercase());
+        self
+    }
+
+    fn is_external(&self, content_type: Option<&str>, body: &str) -> bool {
+        if body.is_empty() {
+            return false;
+        }
+
+        let media_type = media_type(content_type);
+        self.min_length
+            .is_some_and(|min_length| body.len() >= min_length)
+            || self
+                .content_types
+                .iter()
+                .any(|content_type| match content_type.strip_suffix("/*") {
+                    Some(top_level_type) => media_type.split('/').next() == Some(top_level_type),
+                    None => media_type == *content_type,
+                })
+    }
+}
+
+/// Returns the lowercase media type of a content type, without its parameters.
+fn media_type(content_type: Option<&str>) -> String {
+    content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// An interaction found by [`MarkdownInteractionManager::index_interactions`], which knows where
+/// it is in the markdown file but hasn't been parsed yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedInteraction {
+    pub interaction_number: usize,
+    pub method: String,
+    pub uri: String,
+    offset: u64,
+    length: u64,
+}
+
 #[derive(Debug)]
 pub struct MarkdownInteractionManager {
     markdown_path: PathBuf,
+    header_order: HeaderOrder,
+    write_http_version: bool,
+    write_response_time: bool,
+    base_url_placeholder: Option<String>,
+    strict_body_comparison: bool,
+    write_body_language: bool,
+    readable_form_bodies: bool,
+    non_utf8_body_policy: NonUtf8BodyPolicy,
+    external_bodies: Option<ExternalBodies>,
+    /// The settings of the configuration of the running test, which take precedence over the
+    /// ones of the builder methods, see [`InteractionManager::configure`].
+    configured: Mutex<ConfiguredSettings>,
+    /// The interactions whose response body has been streamed to a file by
+    /// [`InteractionManager::response_body_writer`] and isn't in the recorded data.
+    streamed_bodies: Mutex<HashSet<usize>>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ConfiguredSettings {
+    strict_body_comparison: Option<bool>,
+    non_utf8_body_policy: Option<NonUtf8BodyPolicy>,
 }
 
 impl MarkdownInteractionManager {
     pub fn new<P: Into<PathBuf>>(markdown_path: P) -> Self {
         Self {
             markdown_path: markdown_path.into(),
+            header_order: HeaderOrder::default(),
+            write_http_version: false,
+            write_response_time: false,
+            base_url_placeholder: None,
+            strict_body_comparison: false,
+            write_body_language: false,
+            readable_form_bodies: false,
+            non_utf8_body_policy: NonUtf8BodyPolicy::default(),
+            external_bodies: None,
+            configured: Mutex::new(ConfiguredSettings::default()),
+            streamed_bodies: Mutex::new(HashSet::new()),
         }
     }
 
-    fn parse_headers<T: AsRef<str>>(headers_part: T) -> HashMap<String, String> {
-        let mut headers = HashMap::new();
+    pub fn with_header_order(mut self, header_order: HeaderOrder) -> Self {
+        self.header_order = header_order;
+        self
+    }
 
-        for capture in HEADER_REGEX.captures_iter(headers_part.as_ref()) {
-            headers.insert(
-                String::from(capture["header_key"].trim()),
-                String::from(capture["header_value"].trim()),
-            );
+    /// Writes the HTTP version of the requests after their URI, e.g.
+    /// `## Interaction 0: GET /users HTTP/1.1`. Markdown files are read with or without it.
+    pub fn with_http_version(mut self, write_http_version: bool) -> Self {
+        self.write_http_version = write_http_version;
+        self
+    }
+
+    /// Writes how long the destination API took to respond below the interaction heading, e.g.
+    /// `Response time: 120 ms`, so it can be inspected after the test.
+    pub fn with_response_time(mut self, write_response_time: bool) -> Self {
+        self.write_response_time = write_response_time;
+        self
+    }
+
+    /// Writes the request URIs as full URLs whose base is the given placeholder, e.g.
+    /// `{{BASE_URL}}/users`, the way other Servirtium implementations store them. The placeholder
+    /// is removed again when the markdown is read, and so is the domain of absolute URIs.
+    pub fn with_base_url_placeholder<S: Into<String>>(mut self, placeholder: S) -> Self {
+        self.base_url_placeholder = Some(placeholder.into());
+        self
+    }
+
+    /// Compares the bodies byte for byte when checking whether the data has changed. By default
+    /// the leading and trailing whitespace is ignored and the line endings are normalized. The
+    /// bodies are also read without trimming them, only the line break after the opening and
+    /// before the closing code fence is removed. The setting of
+    /// `ServirtiumConfiguration::set_strict_body_comparison` takes precedence.
+    pub fn with_strict_body_comparison(mut self, strict_body_comparison: bool) -> Self {
+        self.strict_body_comparison = strict_body_comparison;
+        self
+    }
+
+    /// Writes the language of the bodies after their opening code fence, e.g. ```` ```json ````,
+    /// based on their content type, so that editors and GitHub highlight them. The info string of
+    /// the opening fence is ignored when the markdown is read, unless it marks a base64 body, see
+    /// [`NonUtf8BodyPolicy::Base64`].
+    pub fn with_body_language(mut self, write_body_language: bool) -> Self {
+        self.write_body_language = write_body_language;
+        self
+    }
+
+    /// Writes `application/x-www-form-urlencoded` request bodies with a `name=value` pair per line,
+    /// so they are easier to review. The pairs stay URL-encoded and are joined with `&` again when
+    /// the markdown is read. Other bodies are written as they are.
+    pub fn with_readable_form_bodies(mut self, readable_form_bodies: bool) -> Self {
+        self.readable_form_bodies = readable_form_bodies;
+        self
+    }
+
+    /// Sets what happens when a body isn't valid UTF-8. By default reading it fails. The policy
+    /// set with `ServirtiumConfiguration::set_non_utf8_body_policy` takes precedence.
+    pub fn with_non_utf8_body_policy(mut self, non_utf8_body_policy: NonUtf8BodyPolicy) -> Self {
+        self.non_utf8_body_policy = non_utf8_body_policy;
+        self
+    }
+
+    pub fn non_utf8_body_policy(&self) -> NonUtf8BodyPolicy {
+        self.configured
+            .lock()
+            .unwrap()
+            .non_utf8_body_policy
+            .unwrap_or(self.non_utf8_body_policy)
+    }
+
+    /// Writes the bodies chosen by `external_bodies` to files next to the markdown file, e.g.
+    /// `weather.0.resp.json` for the response body of the first interaction of `weather.md`, and
+    /// links them instead, e.g. `[weather.0.resp.json](weather.0.resp.json)`. The headers stay in
+    /// the markdown, and the linked files are read back as they are, whatever this option is. The
+    /// managers that wrap this one, e.g. [`GzipInteractionManager`](crate::GzipInteractionManager),
+    /// still write all the bodies inline.
+    pub fn with_external_bodies(mut self, external_bodies: ExternalBodies) -> Self {
+        self.external_bodies = Some(external_bodies);
+        self
+    }
+
+    pub fn strict_body_comparison(&self) -> bool {
+        self.configured
+            .lock()
+            .unwrap()
+            .strict_body_comparison
+            .unwrap_or(self.strict_body_comparison)
+    }
+
+    pub fn markdown_path(&self) -> &Path {
+        &self.markdown_path
+    }
+
+    /// The same as [`markdown_path`](Self::markdown_path), like the `path` of the other managers
+    /// that store the interactions in a file.
+    pub fn path(&self) -> &Path {
+        &self.markdown_path
+    }
+
+    /// Counts the interactions in a markdown file by scanning it for their headings, without
+    /// parsing the headers and bodies.
+    pub fn count_interactions<P: AsRef<Path>>(markdown_path: P) -> Result<usize, Error> {
+        Ok(Self::interaction_headings(markdown_path.as_ref())?.len())
+    }
+
+    /// Finds the interaction headings and the offsets where they start, skipping the code blocks.
+    fn interaction_headings(markdown_path: &Path) -> Result<Vec<(u64, String)>, Error> {
+        let file = File::open(markdown_path).map_err(|e| Error::read(markdown_path, e))?;
+        let mut reader = BufReader::new(file);
+
+        let mut headings = Vec::new();
+        let mut in_code_block = false;
+        let mut offset = 0;
+        let mut bytes = Vec::new();
+        loop {
+            bytes.clear();
+            let length = reader.read_until(b'\n', &mut bytes)?;
+            if length == 0 {
+                break;
+            }
+
+            // the headings are ASCII, the bodies are only decoded when the interactions are loaded
+            let line = String::from_utf8_lossy(&bytes);
+
+            // bodies are in code blocks and could contain a line that looks like a heading
+            if line.starts_with("```") {
+                in_code_block = !in_code_block;
+            } else if !in_code_block && INTERACTION_HEADING_REGEX.is_match(&line) {
+                headings.push((offset, line.trim_end().to_owned()));
+            }
+            offset += length as u64;
         }
 
-        headers
+        Ok(headings)
     }
 
-    fn check_headers(
-        lhs: &HashMap<String, String>,
-        rhs: &HashMap<String, String>,
-    ) -> Option<MarkdownsHeaderDifference> {
-        let left_keys = lhs.keys().collect::<HashSet<_>>();
-        let right_keys = rhs.keys().collect::<HashSet<_>>();
-        if let Some(&diff) = left_keys.difference(&right_keys).next() {
-            return Some(MarkdownsHeaderDifference {
-                header_name: diff.clone(),
-                old_header_value: lhs.get(diff).cloned(),
-                new_header_value: rhs.get(diff).cloned(),
-            });
+    /// Indexes the interactions by their method and URI without parsing their headers and bodies,
+    /// as an alternative to `load_interactions` for large fixtures of which only a few
+    /// interactions are needed. The indexed interactions are parsed with
+    /// [`load_indexed_interaction`](Self::load_indexed_interaction).
+    pub fn index_interactions(&self) -> Result<Vec<IndexedInteraction>, Error> {
+        let headings = Self::interaction_headings(&self.markdown_path)?;
+        let file_length = fs::metadata(&self.markdown_path)
+            .map_err(|e| self.read_error(e))?
+            .len();
+        let ends = headings
+            .iter()
+            .skip(1)
+            .map(|(offset, _)| *offset)
+            .chain(Some(file_length));
+
+        headings
+            .iter()
+            .zip(ends)
+            .map(|((offset, heading), end)| {
+                let captures = INTERACTION_HEADING_REGEX.captures(heading).unwrap();
+
+                Ok(IndexedInteraction {
+                    interaction_number: captures["interaction_number"]
+                        .parse()
+                        .map_err(|_| Error::InvalidInteractionNumber)?,
+                    method: captures["http_method"].into(),
+                    uri: self.restored_uri(&captures["uri"]).into(),
+                    offset: *offset,
+                    length: end - offset,
+                })
+            })
+            .collect()
+    }
+
+    /// Reads and parses a single interaction found by
+    /// [`index_interactions`](Self::index_interactions).
+    pub fn load_indexed_interaction(
+        &self,
+        indexed_interaction: &IndexedInteraction,
+    ) -> Result<InteractionData, Error> {
+        let mut file = File::open(&self.markdown_path).map_err(|e| self.read_error(e))?;
+        file.seek(SeekFrom::Start(indexed_interaction.offset))?;
+
+        let mut bytes = Vec::new();
+        file.take(indexed_interaction.length)
+            .read_to_end(&mut bytes)?;
+        let markdown = self.decode_markdown(bytes, indexed_interaction.offset)?;
+
+        self.parse_markdown(&markdown)?
+            .into_iter()
+            .next()
+            .ok_or(Error::InvalidMarkdownFormat)
+    }
+
+    pub(crate) fn read_error(&self, e: io::Error) -> Error {
+        Error::read(&self.markdown_path, e)
+    }
+
+    /// Decodes the bytes read from the given offset of the markdown file according to the
+    /// [`NonUtf8BodyPolicy`].
+    pub(crate) fn decode_markdown(&self, bytes: Vec<u8>, offset: u64) -> Result<String, Error> {
+        self.decode(&self.markdown_path, bytes, offset)
+    }
+
+    fn decode(&self, path: &Path, bytes: Vec<u8>, offset: u64) -> Result<String, Error> {
+        match String::from_utf8(bytes) {
+            Ok(markdown) => Ok(markdown),
+            Err(e) => match self.non_utf8_body_policy() {
+                NonUtf8BodyPolicy::Error | NonUtf8BodyPolicy::Base64 => Err(Error::InvalidUtf8 {
+                    path: path.into(),
+                    offset: offset + e.utf8_error().valid_up_to() as u64,
+                }),
+                NonUtf8BodyPolicy::Lossy => Ok(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+            },
         }
+    }
 
-        for key in left_keys {
-            let old_value = lhs.get(key).unwrap().trim();
-            let new_value = rhs.get(key).unwrap().trim();
+    /// Returns the path of the file a body is written to, next to the markdown file, e.g.
+    /// `weather.0.resp.json` for the suffix `0.resp`.
+    fn body_file_path(&self, file_suffix: &str, content_type: Option<&str>) -> PathBuf {
+        let media_type = media_type(content_type);
+        let extension = match Self::language(&media_type) {
+            "" if media_type.starts_with("text/") => "txt",
+            "" => "body",
+            language => language,
+        };
 
-            if old_value != new_value {
-                return Some(MarkdownsHeaderDifference {
-                    header_name: key.clone(),
-                    old_header_value: Some(old_value.into()),
-                    new_header_value: Some(new_value.into()),
-                });
-            }
+        self.markdown_path
+            .with_extension(format!("{}.{}", file_suffix, extension))
+    }
+
+    /// Returns the path of the file a streamed response body is linked from, e.g.
+    /// `weather.0.resp.body`, and the path it's written to until the interactions are saved.
+    fn streamed_body_paths(&self, interaction_number: usize) -> (PathBuf, PathBuf) {
+        let path = self.body_file_path(&format!("{}.resp", interaction_number), None);
+        let partial_path = path.with_extension("body.part");
+
+        (path, partial_path)
+    }
+
+    /// Whether the response body of the interaction has been streamed to a file, in which case the
+    /// recorded body is empty.
+    fn is_streamed(&self, interaction: &InteractionData) -> bool {
+        interaction.response_data.body.is_empty()
+            && self
+                .streamed_bodies
+                .lock()
+                .unwrap()
+                .contains(&interaction.interaction_number)
+    }
+
+    fn read_body_file(&self, file_name: &str) -> Result<String, Error> {
+        let path = self
+            .markdown_path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(file_name);
+        let bytes = fs::read(&path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => Error::BodyFileNotFound(path.clone()),
+            _ => Error::Io(e),
+        })?;
+
+        self.decode(&path, bytes, 0)
+    }
+
+    fn templated_uri<'a>(&self, uri: &'a str) -> Cow<'a, str> {
+        match &self.base_url_placeholder {
+            Some(placeholder) => format!("{}{}", placeholder, util::path_and_query(uri)).into(),
+            None => uri.into(),
+        }
+    }
+
+    fn restored_uri<'a>(&self, uri: &'a str) -> &'a str {
+        match &self.base_url_placeholder {
+            Some(placeholder) if uri.starts_with(placeholder.as_str()) => &uri[placeholder.len()..],
+            Some(_) => util::path_and_query(uri),
+            None => uri,
+        }
+    }
+
+    fn ordered_header_names<'a>(&self, headers: &'a HashMap<String, String>) -> Vec<&'a String> {
+        let mut header_names = headers.keys().collect::<Vec<_>>();
+        if self.header_order == HeaderOrder::Unsorted {
+            return header_names;
+        }
+        header_names.sort();
+
+        if let HeaderOrder::Leading(leading_headers) = &self.header_order {
+            let position = |name: &String| {
+                leading_headers
+                    .iter()
+                    .position(|leading| leading.eq_ignore_ascii_case(name))
+                    .unwrap_or(leading_headers.len())
+            };
+
+            // the sort is stable, so the rest of the headers stay sorted
+            header_names.sort_by_key(|name| position(name));
         }
 
-        None
+        header_names
+    }
+
+    fn parse_headers<T: AsRef<str>>(headers_part: T) -> HashMap<String, String> {
+        let mut headers = HashMap::new();
+
+        for capture in HEADER_REGEX.captures_iter(headers_part.as_ref()) {
+            util::append_header_value(
+                &mut headers,
+                capture["header_key"].trim(),
+                capture["header_value"].trim(),
+            );
+        }
+
+        headers
     }
 
     fn find_difference(old_body: &str, new_body: &str) -> Option<MarkdownsBodyDifference> {
         let mut line = 1;
         let mut column = 0;
-        for (index, (left, right)) in old_body.chars().zip(new_body.chars()).enumerate() {
-            if left == '\n' {
+        let (mut old_chars, mut new_chars) = (old_body.char_indices(), new_body.char_indices());
+
+        loop {
+            // a body that ends before the other one differs from it where it ends
+            let (left, right) = match (old_chars.next(), new_chars.next()) {
+                (None, None) => return None,
+                chars => chars,
+            };
+            // the bodies are the same up to here, so a character starts at the same byte in both
+            let index = left.or(right).map(|(index, _)| index).unwrap();
+            let (left, right) = (left.map(|(_, c)| c), right.map(|(_, c)| c));
+
+            if left == Some('\n') {
                 line += 1;
                 column = 1;
             } else {
@@ -108,197 +540,666 @@ impl MarkdownInteractionManager {
                 });
             }
         }
-
-        None
     }
 
+    /// Returns the characters around the byte `index`, which may be the length of the body.
     fn get_context(body: &str, index: usize) -> &str {
         const RADIUS: usize = 10;
 
-        let left_bound = if index >= RADIUS { index - RADIUS } else { 0 };
+        let mut left_bound = index.saturating_sub(RADIUS);
+        while !body.is_char_boundary(left_bound) {
+            left_bound -= 1;
+        }
+
+        let mut right_bound = (index + RADIUS).min(body.len());
+        while !body.is_char_boundary(right_bound) {
+            right_bound += 1;
+        }
+
+        &body[left_bound..right_bound]
+    }
+
+    fn normalize_body(body: &str) -> String {
+        let body = body.trim().replace("\r\n", "\n");
+
+        // pretty printed XML is compared regardless of the indentation
+        #[cfg(feature = "pretty-xml")]
+        if let Some(pretty_body) = util::pretty_print_xml(&body) {
+            return pretty_body;
+        }
+
+        body
+    }
+
+    /// Compares two recordings, e.g. a fixture recorded again into a temporary file with the one
+    /// that is committed, and returns all the differences between them instead of the first one.
+    /// The bodies are compared the way `check_data_unchanged` does by default, regardless of the
+    /// surrounding whitespace and the line endings, and the given headers are ignored, e.g. `Date`.
+    pub fn compare<S: AsRef<str>, I: IntoIterator<Item = S>>(
+        old_interactions: &[InteractionData],
+        new_interactions: &[InteractionData],
+        ignore_headers: I,
+    ) -> Result<(), Vec<MarkdownsDifference>> {
+        let ignore_headers = ignore_headers
+            .into_iter()
+            .map(|name| name.as_ref().to_lowercase())
+            .collect::<HashSet<_>>();
+        let compared_headers = |headers: &HashMap<String, String>| {
+            headers
+                .iter()
+                .filter(|(name, _)| !ignore_headers.contains(&name.to_lowercase()))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect::<HashMap<_, _>>()
+        };
+        let signature = |interaction: &InteractionData| {
+            format!(
+                "{} {}",
+                interaction.request_data.method, interaction.request_data.uri
+            )
+        };
+
+        let mut differences = Vec::new();
+        if old_interactions.len() != new_interactions.len() {
+            differences.push(MarkdownsDifference::InteractionCount {
+                old: old_interactions.len(),
+                new: new_interactions.len(),
+            });
+        }
+
+        for (index, (old, new)) in old_interactions.iter().zip(new_interactions).enumerate() {
+            let mut push = |location, difference_type| {
+                differences.push(MarkdownsDifference::Interaction {
+                    index,
+                    location,
+                    difference_type,
+                })
+            };
+
+            if signature(old) != signature(new) {
+                push(
+                    MarkdownsDifferenceLocation::Request,
+                    MarkdownsDifferenceType::Signature {
+                        old: signature(old),
+                        new: signature(new),
+                    },
+                );
+            }
+
+            for (location, old_data, new_data) in [
+                (
+                    MarkdownsDifferenceLocation::Request,
+                    (&old.request_data.headers, &old.request_data.body),
+                    (&new.request_data.headers, &new.request_data.body),
+                ),
+                (
+                    MarkdownsDifferenceLocation::Response,
+                    (&old.response_data.headers, &old.response_data.body),
+                    (&new.response_data.headers, &new.response_data.body),
+                ),
+            ] {
+                if matches!(location, MarkdownsDifferenceLocation::Response)
+                    && old.response_data.status_code != new.response_data.status_code
+                {
+                    push(
+                        MarkdownsDifferenceLocation::Response,
+                        MarkdownsDifferenceType::StatusCode {
+                            old: old.response_data.status_code,
+                            new: new.response_data.status_code,
+                        },
+                    );
+                }
+
+                let (old_headers, new_headers) =
+                    (compared_headers(old_data.0), compared_headers(new_data.0));
+                for difference in comparison::header_differences(&old_headers, &new_headers) {
+                    push(location, MarkdownsDifferenceType::Header(difference));
+                }
 
-        let right_bound = if index + RADIUS < body.len() {
-            index + RADIUS
+                if let Some(difference) = Self::find_difference(
+                    &Self::normalize_body(old_data.1),
+                    &Self::normalize_body(new_data.1),
+                ) {
+                    push(location, MarkdownsDifferenceType::Body(difference));
+                }
+            }
+        }
+
+        if differences.is_empty() {
+            Ok(())
         } else {
-            body.len() - 1
+            Err(differences)
+        }
+    }
+
+    pub(crate) fn compare_interactions(
+        old_interactions: &[InteractionData],
+        new_interactions: &[InteractionData],
+        strict_body_comparison: bool,
+    ) -> Result<(), Error> {
+        let normalize_body = |body: &str| {
+            if strict_body_comparison {
+                String::from(body)
+            } else {
+                Self::normalize_body(body)
+            }
         };
 
-        &body[left_bound..right_bound]
+        for (interaction_data, markdown_data) in
+            new_interactions.iter().zip(old_interactions.iter())
+        {
+            let markdown_request_body = normalize_body(&markdown_data.request_data.body);
+            let markdown_response_body = normalize_body(&markdown_data.response_data.body);
+            let new_request_body = normalize_body(&interaction_data.request_data.body);
+            let new_response_body = normalize_body(&interaction_data.response_data.body);
+
+            if let Some((difference, location)) =
+                Self::find_difference(&markdown_request_body, &new_request_body)
+                    .map(|d| (d, MarkdownsDifferenceLocation::Request))
+                    .or_else(|| {
+                        Self::find_difference(&markdown_response_body, &new_response_body)
+                            .map(|d| (d, MarkdownsDifferenceLocation::Response))
+                    })
+            {
+                return Err(Error::MarkdownsDiffer(
+                    MarkdownsDifferenceType::Body(difference),
+                    location,
+                ));
+            }
+
+            if let Some((difference, location)) = comparison::compare_headers(
+                &markdown_data.request_data.headers,
+                &interaction_data.request_data.headers,
+            )
+            .map(|d| (d, MarkdownsDifferenceLocation::Request))
+            .or_else(|| {
+                comparison::compare_headers(
+                    &markdown_data.response_data.headers,
+                    &interaction_data.response_data.headers,
+                )
+                .map(|d| (d, MarkdownsDifferenceLocation::Response))
+            }) {
+                return Err(Error::MarkdownsDiffer(
+                    MarkdownsDifferenceType::Header(difference),
+                    location,
+                ));
+            }
+        }
+
+        Ok(())
     }
-}
 
-impl InteractionManager for MarkdownInteractionManager {
-    fn load_interactions(
+    /// Reads a body from its code block, and decodes it if it's marked as base64 and the policy
+    /// is [`NonUtf8BodyPolicy::Base64`].
+    fn read_body(
         &self,
-    ) -> Result<Vec<InteractionData>, Box<dyn std::error::Error + Send + Sync>> {
-        let file_contents = fs::read_to_string(&self.markdown_path)?;
+        part: &str,
+        headers: &HashMap<String, String>,
+        interaction_number: usize,
+    ) -> Result<String, Error> {
+        let body = self.body_part(part);
+        let info_string = part
+            .find('\n')
+            .map_or("", |line_end| part[..line_end].trim());
+
+        if self.non_utf8_body_policy() != NonUtf8BodyPolicy::Base64
+            || !info_string.eq_ignore_ascii_case("base64")
+        {
+            return Ok(body.into());
+        }
+
+        let invalid = |reason: String| Error::InvalidBase64Body {
+            interaction_number,
+            reason,
+        };
+        let bytes = STANDARD
+            .decode(body.split_whitespace().collect::<String>())
+            .map_err(|e| invalid(e.to_string()))?;
+        let decoded = charset::decode(headers, &bytes);
+
+        // the replacement characters of invalid bytes don't encode back into the same bytes
+        if charset::encode(headers, decoded.clone()) != bytes {
+            return Err(invalid(String::from(
+                "the decoded bytes aren't valid in the charset of the body",
+            )));
+        }
+
+        Ok(decoded)
+    }
+
+    fn body_part<'a>(&self, part: &'a str) -> &'a str {
+        // like in CommonMark, the rest of the line of the opening fence is an info string
+        let part = match part.find('\n') {
+            Some(line_end) if !part[..line_end].trim().is_empty() => &part[line_end..],
+            _ => part,
+        };
+
+        if !self.strict_body_comparison() {
+            return part.trim();
+        }
+
+        let part = part
+            .strip_prefix("\r\n")
+            .or_else(|| part.strip_prefix('\n'))
+            .unwrap_or(part);
+        part.strip_suffix("\r\n")
+            .or_else(|| part.strip_suffix('\n'))
+            .unwrap_or(part)
+    }
+
+    /// Returns the parts of a `multipart/form-data` request body that's written inline, which are
+    /// written in a section each so the fixture doesn't depend on the boundary.
+    fn inline_parts(
+        &self,
+        request_data: &RequestData,
+        external_bodies: Option<&ExternalBodies>,
+    ) -> Option<Vec<multipart::Part>> {
+        if external_bodies.is_some_and(|external_bodies| {
+            external_bodies.is_external(request_data.content_type(), &request_data.body)
+        }) {
+            return None;
+        }
+
+        multipart::parse(
+            &request_data.body,
+            &multipart::boundary(&request_data.headers)?,
+        )
+        .filter(|parts| !parts.is_empty())
+    }
+
+    /// Rebuilds a `multipart/form-data` request body from its sections, with the boundary of the
+    /// recorded `Content-Type`.
+    fn multipart_body(parts: &str, request_headers: &HashMap<String, String>) -> String {
+        let parts = PART_REGEX
+            .captures_iter(parts)
+            .map(|captures| {
+                let part = &captures["part"];
+                let part = part
+                    .strip_prefix("\r\n")
+                    .or_else(|| part.strip_prefix('\n'))
+                    .unwrap_or(part);
+                let part = part
+                    .strip_suffix("\r\n")
+                    .or_else(|| part.strip_suffix('\n'))
+                    .unwrap_or(part);
+                let (headers, body) = part
+                    .split_once("\r\n\r\n")
+                    .or_else(|| part.split_once("\n\n"))
+                    .unwrap_or((part, ""));
+
+                multipart::Part {
+                    headers: headers.lines().collect::<Vec<_>>().join("\r\n"),
+                    body: String::from(body),
+                }
+            })
+            .collect::<Vec<_>>();
+        let boundary = multipart::boundary(request_headers)
+            .unwrap_or_else(|| String::from(multipart::BOUNDARY));
+
+        multipart::build(&parts, &boundary)
+    }
+
+    fn is_readable_form(&self, request_headers: &HashMap<String, String>) -> bool {
+        self.readable_form_bodies
+            && util::content_type(request_headers).is_some_and(|content_type| {
+                content_type
+                    .split(';')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+            })
+    }
+
+    /// Returns the info string of the code fence for a body with the given content type, which is
+    /// empty unless the language is written or if it's unknown.
+    fn body_language(&self, content_type: Option<&str>) -> &'static str {
+        if !self.write_body_language {
+            return "";
+        }
+
+        Self::language(&media_type(content_type))
+    }
+
+    /// Returns the language of a media type, or an empty string if it's unknown.
+    fn language(media_type: &str) -> &'static str {
+        match media_type {
+            "application/json" => "json",
+            "application/xml" | "text/xml" => "xml",
+            "text/html" => "html",
+            "text/css" => "css",
+            "application/javascript" | "text/javascript" => "javascript",
+            "application/x-yaml" | "application/yaml" | "text/yaml" => "yaml",
+            _ if media_type.ends_with("+json") => "json",
+            _ if media_type.ends_with("+xml") => "xml",
+            _ => "",
+        }
+    }
+
+    pub(crate) fn parse_markdown(&self, markdown: &str) -> Result<Vec<InteractionData>, Error> {
         let mut data = Vec::new();
 
-        for captures in MARKDOWN_REGEX.captures_iter(&file_contents) {
-            let uri = &captures["uri"];
-            let interaction_number: u8 = captures["interaction_number"]
+        for captures in MARKDOWN_REGEX.captures_iter(markdown) {
+            let uri = self.restored_uri(&captures["uri"]);
+            let interaction_number: usize = captures["interaction_number"]
                 .parse()
                 .map_err(|_| Error::InvalidInteractionNumber)?;
             let request_headers_part = &captures["request_headers_part"];
-            let request_body_part = &captures["request_body_part"];
             let status_code = captures["status_code"]
                 .parse()
                 .map_err(|_| Error::InvalidStatusCode)?;
             let method = &captures["http_method"];
+            let http_version = captures.name("http_version").map(|m| m.as_str().into());
+            let reason_phrase = captures.name("reason_phrase").map(|m| m.as_str().into());
+            let response_time = captures
+                .name("response_time")
+                .and_then(|m| m.as_str().parse().ok())
+                .map(Duration::from_millis);
             let response_headers_part = &captures["response_headers_part"];
-            let response_body_part = &captures["response_body_part"];
-
             let response_headers = Self::parse_headers(response_headers_part);
             let request_headers = Self::parse_headers(request_headers_part);
 
+            let response_body = match captures.name("response_body_file") {
+                Some(body_file) => self.read_body_file(body_file.as_str())?,
+                None => self.read_body(
+                    &captures["response_body_part"],
+                    &response_headers,
+                    interaction_number,
+                )?,
+            };
+
+            let request_body = match captures.name("request_body_file") {
+                Some(body_file) => self.read_body_file(body_file.as_str())?,
+                None if captures.name("request_body_parts").is_some() => {
+                    Self::multipart_body(&captures["request_body_parts"], &request_headers)
+                }
+                None if self.is_readable_form(&request_headers) => self
+                    .body_part(&captures["request_body_part"])
+                    .lines()
+                    .map(str::trim)
+                    .filter(|pair| !pair.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("&"),
+                None => self.read_body(
+                    &captures["request_body_part"],
+                    &request_headers,
+                    interaction_number,
+                )?,
+            };
+
             data.push(InteractionData {
                 interaction_number,
                 request_data: RequestData {
-                    body: request_body_part.into(),
+                    body: request_body,
                     method: method.into(),
+                    http_version,
                     headers: request_headers,
                     uri: uri.into(),
                 },
                 response_data: ResponseData {
                     status_code,
+                    reason_phrase,
                     headers: response_headers,
-                    body: response_body_part.into(),
+                    body: response_body,
                 },
+                response_time,
             });
         }
 
         if data.is_empty() {
-            Err(Box::new(Error::InvalidMarkdownFormat))
+            Err(Error::InvalidMarkdownFormat)
         } else {
             Ok(data)
         }
     }
 
-    fn save_interactions(
+    pub(crate) fn write_markdown<W: Write>(
         &self,
+        writer: &mut W,
         interactions: &[InteractionData],
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let mut file = File::create(&self.markdown_path)?;
+    ) -> io::Result<()> {
+        self.write_interactions(writer, interactions, None)
+    }
+
+    fn write_interactions<W: Write>(
+        &self,
+        writer: &mut W,
+        interactions: &[InteractionData],
+        external_bodies: Option<&ExternalBodies>,
+    ) -> io::Result<()> {
         for interaction in interactions.iter() {
             write!(
-                file,
-                "## Interaction {}: {} {}\r\n\r\n",
+                writer,
+                "## Interaction {}: {} {}",
                 interaction.interaction_number,
                 interaction.request_data.method,
-                interaction.request_data.uri
+                self.templated_uri(&interaction.request_data.uri)
             )?;
+            match &interaction.request_data.http_version {
+                Some(http_version) if self.write_http_version => {
+                    write!(writer, " {}\r\n\r\n", http_version)?
+                }
+                _ => write!(writer, "\r\n\r\n")?,
+            }
+            match interaction.response_time {
+                Some(response_time) if self.write_response_time => write!(
+                    writer,
+                    "Response time: {} ms\r\n\r\n",
+                    response_time.as_millis()
+                )?,
+                _ => {}
+            }
             write!(
-                file,
+                writer,
                 "### Request headers recorded for playback:\r\n\r\n```\r\n"
             )?;
 
-            let mut header_names = interaction.request_data.headers.keys().collect::<Vec<_>>();
-            header_names.sort();
+            let header_names = self.ordered_header_names(&interaction.request_data.headers);
             for header_name in header_names {
-                write!(
-                    file,
-                    "{}: {}\r\n",
-                    header_name,
-                    interaction.request_data.headers.get(header_name).unwrap()
-                )?;
+                for header_value in
+                    util::header_values(interaction.request_data.headers.get(header_name).unwrap())
+                {
+                    write!(writer, "{}: {}\r\n", header_name, header_value)?;
+                }
             }
-            write!(file, "```\r\n\r\n")?;
+            write!(writer, "```\r\n\r\n")?;
 
+            write!(writer, "### Request body recorded for playback ():\r\n\r\n")?;
+            if let Some(parts) = self.inline_parts(&interaction.request_data, external_bodies) {
+                for (index, part) in parts.iter().enumerate() {
+                    write!(
+                        writer,
+                        "#### Part {}:\r\n\r\n```\r\n{}\r\n\r\n{}\r\n```\r\n\r\n",
+                        index, part.headers, part.body
+                    )?;
+                }
+            } else {
+                self.write_body(
+                    writer,
+                    &format!("{}.req", interaction.interaction_number),
+                    interaction.request_data.content_type(),
+                    &interaction.request_data.body,
+                    external_bodies,
+                    || {
+                        if self.is_readable_form(&interaction.request_data.headers) {
+                            Cow::Owned(interaction.request_data.body.replace('&', "\r\n"))
+                        } else {
+                            Cow::Borrowed(&interaction.request_data.body)
+                        }
+                    },
+                )?;
+            }
             write!(
-                file,
-                "### Request body recorded for playback ():\r\n\r\n```\r\n{}\r\n```\r\n\r\n",
-                &interaction.request_data.body,
-            )?;
-            write!(
-                file,
+                writer,
                 "### Response headers recorded for playback:\r\n\r\n```\r\n"
             )?;
 
-            let mut header_names = interaction.response_data.headers.keys().collect::<Vec<_>>();
-            header_names.sort();
+            let header_names = self.ordered_header_names(&interaction.response_data.headers);
             for header_name in header_names {
-                writeln!(
-                    file,
-                    "{}: {}",
-                    header_name,
-                    interaction.response_data.headers.get(header_name).unwrap()
-                )?;
+                for header_value in
+                    util::header_values(interaction.response_data.headers.get(header_name).unwrap())
+                {
+                    writeln!(writer, "{}: {}", header_name, header_value)?;
+                }
             }
-            write!(file, "```\r\n\r\n")?;
+            write!(writer, "```\r\n\r\n")?;
             write!(
-                file,
-                "### Response body recorded for playback ({}: {}):\r\n\r\n```\r\n{}\r\n```\r\n\r\n",
+                writer,
+                "### Response body recorded for playback ({}{}: {}):\r\n\r\n",
                 interaction.response_data.status_code,
                 interaction
                     .response_data
-                    .headers
-                    .get("content-type")
-                    .unwrap_or(&String::from("")),
-                &interaction.response_data.body
+                    .reason_phrase
+                    .as_ref()
+                    .map(|reason_phrase| format!(" {}", reason_phrase))
+                    .unwrap_or_default(),
+                // the parentheses are left empty if there is no content type
+                interaction.response_data.content_type().unwrap_or_default(),
             )?;
+            if self.is_streamed(interaction) {
+                let (path, partial_path) = self.streamed_body_paths(interaction.interaction_number);
+                if partial_path.exists() {
+                    fs::rename(&partial_path, &path)?;
+                }
+
+                Self::write_body_link(writer, &path)?;
+            } else {
+                self.write_body(
+                    writer,
+                    &format!("{}.resp", interaction.interaction_number),
+                    interaction.response_data.content_type(),
+                    &interaction.response_data.body,
+                    external_bodies,
+                    || Cow::Borrowed(&interaction.response_data.body),
+                )?;
+            }
         }
 
         Ok(())
     }
 
-    fn check_data_unchanged(
+    /// Writes a body in a code block, or to a file that's linked if it's an external body.
+    fn write_body<'a, W: Write, F: FnOnce() -> Cow<'a, str>>(
+        &self,
+        writer: &mut W,
+        file_suffix: &str,
+        content_type: Option<&str>,
+        body: &str,
+        external_bodies: Option<&ExternalBodies>,
+        inline_body: F,
+    ) -> io::Result<()> {
+        match external_bodies {
+            Some(external_bodies) if external_bodies.is_external(content_type, body) => {
+                let path = self.body_file_path(file_suffix, content_type);
+                fs::write(&path, body)?;
+
+                Self::write_body_link(writer, &path)
+            }
+            _ => write!(
+                writer,
+                "```{}\r\n{}\r\n```\r\n\r\n",
+                self.body_language(content_type),
+                inline_body()
+            ),
+        }
+    }
+
+    fn write_body_link<W: Write>(writer: &mut W, path: &Path) -> io::Result<()> {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        write!(writer, "[{}]({})\r\n\r\n", file_name, file_name)
+    }
+}
+
+impl InteractionManager for MarkdownInteractionManager {
+    fn load_interactions(
+        &self,
+    ) -> Result<Vec<InteractionData>, Box<dyn std::error::Error + Send + Sync>> {
+        let bytes = fs::read(&self.markdown_path).map_err(|e| self.read_error(e))?;
+        let file_contents = self.decode_markdown(bytes, 0)?;
+
+        Ok(self.parse_markdown(&file_contents)?)
+    }
+
+    fn save_interactions(
         &self,
         interactions: &[InteractionData],
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let markdown_data = self.load_interactions()?;
+        let mut file = File::create(&self.markdown_path)?;
+        self.write_interactions(&mut file, interactions, self.external_bodies.as_ref())?;
 
-        for (interaction_data, markdown_data) in interactions.iter().zip(markdown_data.iter()) {
-            let markdown_request_body =
-                markdown_data.request_data.body.trim().replace("\r\n", "\n");
-            let markdown_response_body = markdown_data
-                .response_data
-                .body
-                .trim()
-                .replace("\r\n", "\n");
-            let new_request_body = interaction_data
-                .request_data
-                .body
-                .trim()
-                .replace("\r\n", "\n");
-            let new_response_body = interaction_data
-                .response_data
-                .body
-                .trim()
-                .replace("\r\n", "\n");
+        Ok(())
+    }
 
-            if let Some((difference, location)) =
-                Self::find_difference(&markdown_request_body, &new_request_body)
-                    .map(|d| (d, MarkdownsDifferenceLocation::Request))
-                    .or_else(|| {
-                        Self::find_difference(&markdown_response_body, &new_response_body)
-                            .map(|d| (d, MarkdownsDifferenceLocation::Response))
-                    })
-            {
-                return Err(Box::new(Error::MarkdownsDiffer(
-                    MarkdownsDifferenceType::Body(difference),
-                    location,
-                )));
-            }
+    /// Streams the body to a file next to the markdown file, e.g. `weather.0.resp.body`, which is
+    /// linked from the markdown like the bodies written by
+    /// [`with_external_bodies`](MarkdownInteractionManager::with_external_bodies).
+    fn response_body_writer(
+        &self,
+        interaction_number: usize,
+    ) -> Result<Option<Box<dyn Write + Send>>, Box<dyn std::error::Error + Send + Sync>> {
+        let (_, partial_path) = self.streamed_body_paths(interaction_number);
+        let file = File::create(partial_path)?;
+        self.streamed_bodies
+            .lock()
+            .unwrap()
+            .insert(interaction_number);
 
-            if let Some((difference, location)) = Self::check_headers(
-                &markdown_data.request_data.headers,
-                &interaction_data.request_data.headers,
-            )
-            .map(|d| (d, MarkdownsDifferenceLocation::Request))
-            .or_else(|| {
-                Self::check_headers(
-                    &markdown_data.response_data.headers,
-                    &interaction_data.response_data.headers,
-                )
-                .map(|d| (d, MarkdownsDifferenceLocation::Response))
-            }) {
-                return Err(Box::new(Error::MarkdownsDiffer(
-                    MarkdownsDifferenceType::Header(difference),
-                    location,
-                )));
+        Ok(Some(Box::new(file)))
+    }
+
+    /// Writes the bodies next to the markdown file, e.g. `weather.0.req.bin` and
+    /// `weather.0.resp.bin` for `weather.md`.
+    fn save_raw_bodies(
+        &self,
+        interaction_number: usize,
+        request_body: &[u8],
+        response_body: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let raw_body_path = |suffix: &str| {
+            self.markdown_path
+                .with_extension(format!("{}.{}", interaction_number, suffix))
+        };
+
+        fs::write(raw_body_path("req.bin"), request_body)?;
+        fs::write(raw_body_path("resp.bin"), response_body)?;
+
+        Ok(())
+    }
+
+    fn check_data_unchanged(
+        &self,
+        interactions: &[InteractionData],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let markdown_data = self.load_interactions()?;
+
+        // the streamed bodies are compared with the ones of the previous recording before they
+        // replace them
+        let mut interactions = interactions.to_vec();
+        for interaction in interactions.iter_mut() {
+            if self.is_streamed(interaction) {
+                let (_, partial_path) = self.streamed_body_paths(interaction.interaction_number);
+                let file_name = partial_path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy();
+                interaction.response_data.body = self.read_body_file(&file_name)?;
             }
         }
 
+        Self::compare_interactions(&markdown_data, &interactions, self.strict_body_comparison())?;
+
         Ok(())
     }
+
+    fn configure(&self, configuration: &ServirtiumConfiguration) {
+        *self.configured.lock().unwrap() = ConfiguredSettings {
+            strict_body_comparison: configuration.strict_body_comparison(),
+            non_utf8_body_policy: configuration.non_utf8_body_policy(),
+        };
+    }
+
+    /// The linked body files aren't included, see
+    /// [`with_external_bodies`](MarkdownInteractionManager::with_external_bodies).
+    fn fixture_paths(&self) -> Vec<PathBuf> {
+        vec![self.markdown_path.clone()]
+    }
 }
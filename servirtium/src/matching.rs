@@ -0,0 +1,391 @@
+use crate::{util, InteractionData, RequestData};
+use std::{collections::HashSet, fmt};
+
+/// What the playback server does when no recorded interaction matches a request, unless a
+/// default response is configured with `ServirtiumConfiguration::set_playback_default_response`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoMatchBehavior {
+    /// Fails the test with `Error::NoMatchingInteractionCandidates`.
+    #[default]
+    Error,
+    /// Closes the connection without sending a response, to simulate a transport failure.
+    CloseConnection,
+}
+
+/// How a recorded interaction compares to a request that no interaction matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CandidateMatch {
+    pub interaction_number: usize,
+    /// The method and the URI of the recorded request.
+    pub signature: String,
+    pub method: bool,
+    pub uri: bool,
+    /// `None` if the `Vary` headers aren't matched.
+    pub vary_headers: Option<bool>,
+    /// `None` if the request bodies aren't matched.
+    pub body: Option<bool>,
+    /// Whether the interaction hasn't been replayed yet.
+    pub unused: bool,
+}
+
+/// Writes the candidates as a table with a row per recorded interaction.
+pub(crate) fn write_candidates(
+    f: &mut fmt::Formatter<'_>,
+    candidates: &[CandidateMatch],
+) -> fmt::Result {
+    let yes_no = |matches: bool| if matches { "yes" } else { "no" };
+    let width = candidates
+        .iter()
+        .map(|candidate| candidate.signature.len())
+        .chain(Some("recorded request".len()))
+        .max()
+        .unwrap_or_default();
+
+    write!(
+        f,
+        "\n  #   | {:width$} | method | uri | vary headers | body | unused",
+        "recorded request",
+        width = width
+    )?;
+    for candidate in candidates {
+        write!(
+            f,
+            "\n  {:<3} | {:width$} | {:6} | {:3} | {:12} | {:4} | {}",
+            candidate.interaction_number,
+            candidate.signature,
+            yes_no(candidate.method),
+            yes_no(candidate.uri),
+            candidate.vary_headers.map_or("-", yes_no),
+            candidate.body.map_or("-", yes_no),
+            yes_no(candidate.unused),
+            width = width
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Controls how incoming requests are matched against the recorded ones in playback mode. The
+/// recorded URIs are never changed, the normalization is only applied for the comparison.
+#[derive(Debug, Clone)]
+pub struct MatchConfiguration {
+    ignore_trailing_slash: bool,
+    ignore_path_case: bool,
+    match_vary_headers: bool,
+    ignored_query_params: HashSet<String>,
+    match_path_patterns: bool,
+    match_head_with_get: bool,
+    match_request_body: bool,
+}
+
+impl Default for MatchConfiguration {
+    fn default() -> Self {
+        Self {
+            ignore_trailing_slash: false,
+            ignore_path_case: false,
+            match_vary_headers: true,
+            ignored_query_params: HashSet::new(),
+            match_path_patterns: false,
+            match_head_with_get: false,
+            match_request_body: false,
+        }
+    }
+}
+
+impl MatchConfiguration {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_ignore_trailing_slash(&mut self, value: bool) -> &mut Self {
+        self.ignore_trailing_slash = value;
+        self
+    }
+
+    pub fn ignore_trailing_slash(&self) -> bool {
+        self.ignore_trailing_slash
+    }
+
+    /// Compares the paths case-insensitively. The query string is always case-sensitive.
+    pub fn set_ignore_path_case(&mut self, value: bool) -> &mut Self {
+        self.ignore_path_case = value;
+        self
+    }
+
+    pub fn ignore_path_case(&self) -> bool {
+        self.ignore_path_case
+    }
+
+    /// Whether the request headers named in the `Vary` header of the recorded response are
+    /// compared too, so content negotiation fixtures replay the right variant. It's enabled by
+    /// default. Interactions without `Vary` are matched as usual.
+    pub fn set_match_vary_headers(&mut self, value: bool) -> &mut Self {
+        self.match_vary_headers = value;
+        self
+    }
+
+    pub fn match_vary_headers(&self) -> bool {
+        self.match_vary_headers
+    }
+
+    /// Ignores the given query parameters when comparing the URIs, e.g. cache busters. They are
+    /// still saved in the recorded URIs. Every occurrence of a repeated parameter is ignored, and
+    /// the other parameters are compared in order, repeated keys like `?tag=a&tag=b` included.
+    pub fn match_ignoring_query_params<S: Into<String>, I: IntoIterator<Item = S>>(
+        &mut self,
+        params: I,
+    ) -> &mut Self {
+        self.ignored_query_params
+            .extend(params.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn ignored_query_params(&self) -> &HashSet<String> {
+        &self.ignored_query_params
+    }
+
+    /// Treats the path segments of the recorded URIs in braces as parameters that match any
+    /// segment, e.g. `/users/{id}` matches `/users/1` and `/users/2`. An interaction with a pattern
+    /// isn't used up when it's replayed, it answers all the requests it matches, but unused
+    /// interactions are still preferred.
+    pub fn set_match_path_patterns(&mut self, value: bool) -> &mut Self {
+        self.match_path_patterns = value;
+        self
+    }
+
+    pub fn match_path_patterns(&self) -> bool {
+        self.match_path_patterns
+    }
+
+    /// Answers `HEAD` requests with the headers of a recorded `GET` interaction and an empty body,
+    /// so fixtures recorded with `GET` requests also serve clients that check a resource first.
+    /// The interaction isn't used up, it's still replayed for the `GET` request that follows.
+    pub fn set_match_head_with_get(&mut self, value: bool) -> &mut Self {
+        self.match_head_with_get = value;
+        self
+    }
+
+    pub fn match_head_with_get(&self) -> bool {
+        self.match_head_with_get
+    }
+
+    /// Also compares the request bodies, ignoring the leading and trailing whitespace. With the
+    /// `json` feature, JSON bodies are compared as parsed values, so the order of the keys and the
+    /// formatting don't matter. Other bodies are compared as strings.
+    pub fn set_match_request_body(&mut self, value: bool) -> &mut Self {
+        self.match_request_body = value;
+        self
+    }
+
+    pub fn match_request_body(&self) -> bool {
+        self.match_request_body
+    }
+
+    pub fn matches_interaction(&self, recorded: &InteractionData, incoming: &RequestData) -> bool {
+        self.matches(&recorded.request_data, incoming)
+            && (!self.match_vary_headers || Self::vary_headers_match(recorded, incoming))
+    }
+
+    /// Explains which parts of the recorded interaction match the incoming request, to report why
+    /// no interaction was replayed.
+    pub fn explain_match(
+        &self,
+        recorded: &InteractionData,
+        incoming: &RequestData,
+        used: bool,
+    ) -> CandidateMatch {
+        CandidateMatch {
+            interaction_number: recorded.interaction_number,
+            signature: format!(
+                "{} {}",
+                recorded.request_data.method, recorded.request_data.uri
+            ),
+            method: self.methods_match(&recorded.request_data.method, &incoming.method),
+            uri: self.uris_match(&recorded.request_data.uri, &incoming.uri),
+            vary_headers: if self.match_vary_headers {
+                Some(Self::vary_headers_match(recorded, incoming))
+            } else {
+                None
+            },
+            body: if self.match_request_body {
+                Some(Self::bodies_match(&recorded.request_data, incoming))
+            } else {
+                None
+            },
+            unused: !used,
+        }
+    }
+
+    fn vary_headers_match(recorded: &InteractionData, incoming: &RequestData) -> bool {
+        let vary = match util::find_header(&recorded.response_data.headers, "vary") {
+            Some(vary) => vary,
+            None => return true,
+        };
+
+        vary.split([',', '\n'])
+            .map(str::trim)
+            .filter(|name| !name.is_empty() && *name != "*")
+            .all(|name| {
+                util::find_header(&recorded.request_data.headers, name)
+                    == util::find_header(&incoming.headers, name)
+            })
+    }
+
+    /// Checks that a recorded `304 Not Modified` response answers a conditional request with the
+    /// same `If-None-Match` and `If-Modified-Since` headers as the recorded request. Other
+    /// responses always match.
+    pub fn conditional_headers_match(recorded: &InteractionData, incoming: &RequestData) -> bool {
+        const CONDITIONAL_HEADERS: [&str; 2] = ["if-none-match", "if-modified-since"];
+
+        if recorded.response_data.status_code != 304 {
+            return true;
+        }
+
+        let recorded_conditions = CONDITIONAL_HEADERS
+            .iter()
+            .filter_map(|name| {
+                util::find_header(&recorded.request_data.headers, name).map(|value| (name, value))
+            })
+            .collect::<Vec<_>>();
+
+        if recorded_conditions.is_empty() {
+            return CONDITIONAL_HEADERS
+                .iter()
+                .any(|name| util::find_header(&incoming.headers, name).is_some());
+        }
+
+        recorded_conditions
+            .iter()
+            .all(|(name, value)| util::find_header(&incoming.headers, name) == Some(*value))
+    }
+
+    pub fn matches(&self, recorded: &RequestData, incoming: &RequestData) -> bool {
+        self.methods_match(&recorded.method, &incoming.method)
+            && self.uris_match(&recorded.uri, &incoming.uri)
+            && (!self.match_request_body || Self::bodies_match(recorded, incoming))
+    }
+
+    /// Compares the bodies as JSON values if both requests have a JSON content type and valid
+    /// JSON bodies, and as trimmed strings otherwise.
+    pub fn bodies_match(recorded: &RequestData, incoming: &RequestData) -> bool {
+        #[cfg(feature = "json")]
+        if let (Ok(recorded), Ok(incoming)) = (
+            recorded.json::<serde_json::Value>(),
+            incoming.json::<serde_json::Value>(),
+        ) {
+            return recorded == incoming;
+        }
+
+        recorded.body.trim() == incoming.body.trim()
+    }
+
+    pub fn methods_match(&self, recorded: &str, incoming: &str) -> bool {
+        recorded.eq_ignore_ascii_case(incoming) || self.is_head_with_get(recorded, incoming)
+    }
+
+    /// Whether a `HEAD` request is answered with a recorded `GET` interaction, see
+    /// [`set_match_head_with_get`](Self::set_match_head_with_get).
+    pub(crate) fn is_head_with_get(&self, recorded: &str, incoming: &str) -> bool {
+        self.match_head_with_get
+            && recorded.eq_ignore_ascii_case("GET")
+            && incoming.eq_ignore_ascii_case("HEAD")
+    }
+
+    /// Matches a request against a signature like `"GET /path"`. A signature without a method
+    /// matches requests with any method.
+    pub fn matches_signature(&self, signature: &str, request: &RequestData) -> bool {
+        match signature.trim().find(' ') {
+            Some(index) => {
+                let (method, uri) = signature.trim().split_at(index);
+                self.methods_match(method, &request.method)
+                    && self.uris_match(uri.trim(), &request.uri)
+            }
+            None => self.uris_match(signature.trim(), &request.uri),
+        }
+    }
+
+    pub fn uris_match(&self, recorded: &str, incoming: &str) -> bool {
+        let (recorded_path, recorded_query) = self.normalize_uri(recorded);
+        let (incoming_path, incoming_query) = self.normalize_uri(incoming);
+
+        let paths_match = if self.match_path_patterns && recorded_path.contains('{') {
+            Self::path_matches_pattern(&recorded_path, &incoming_path)
+        } else {
+            recorded_path == incoming_path
+        };
+
+        paths_match && recorded_query == incoming_query
+    }
+
+    /// Whether the recorded URI is a pattern, see
+    /// [`set_match_path_patterns`](Self::set_match_path_patterns).
+    pub(crate) fn is_path_pattern(&self, recorded: &str) -> bool {
+        self.match_path_patterns && self.normalize_uri(recorded).0.contains('{')
+    }
+
+    fn path_matches_pattern(pattern: &str, path: &str) -> bool {
+        let pattern_segments = pattern.split('/').collect::<Vec<_>>();
+        let path_segments = path.split('/').collect::<Vec<_>>();
+
+        pattern_segments.len() == path_segments.len()
+            && pattern_segments
+                .iter()
+                .zip(path_segments)
+                .all(|(pattern_segment, path_segment)| {
+                    let is_parameter =
+                        pattern_segment.starts_with('{') && pattern_segment.ends_with('}');
+
+                    if is_parameter {
+                        !path_segment.is_empty()
+                    } else {
+                        *pattern_segment == path_segment
+                    }
+                })
+    }
+
+    fn normalize_uri(&self, uri: &str) -> (String, String) {
+        // recorded URIs can be absolute, see `ServirtiumConfiguration::set_recorded_base_url`
+        let uri = util::path_and_query(uri);
+        let (path, query) = match uri.find('?') {
+            Some(index) => (&uri[..index], &uri[index..]),
+            None => (uri, ""),
+        };
+
+        let path = if self.ignore_trailing_slash {
+            path.trim_end_matches('/')
+        } else {
+            path
+        };
+
+        let path = if self.ignore_path_case {
+            path.to_lowercase()
+        } else {
+            String::from(path)
+        };
+
+        (path, self.normalize_query(query))
+    }
+
+    fn normalize_query(&self, query: &str) -> String {
+        if self.ignored_query_params.is_empty() {
+            return String::from(query);
+        }
+
+        // the parameters aren't collected into a map, a repeated key like `tag=a&tag=b` carries
+        // several values whose order matters to the API
+        let params = query
+            .trim_start_matches('?')
+            .split('&')
+            .filter(|param| {
+                let name = param.split('=').next().unwrap_or_default();
+                !param.is_empty() && !self.ignored_query_params.contains(name)
+            })
+            .collect::<Vec<_>>();
+
+        if params.is_empty() {
+            String::new()
+        } else {
+            format!("?{}", params.join("&"))
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{
+    interaction_manager::InteractionManager, InteractionData, MarkdownInteractionManager,
+    ServirtiumConfiguration,
+};
+use std::sync::{Arc, Mutex};
+
+/// Keeps interactions in memory instead of a file. Clones share the same storage, so a clone
+/// kept by the test can inspect what was recorded after the test session has finished.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryInteractionManager {
+    interactions: Arc<Mutex<Vec<InteractionData>>>,
+    strict_body_comparison: bool,
+    /// Set by `ServirtiumConfiguration::set_strict_body_comparison`, which takes precedence.
+    configured_strict_body_comparison: Arc<Mutex<Option<bool>>>,
+}
+
+impl MemoryInteractionManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_interactions(interactions: Vec<InteractionData>) -> Self {
+        Self {
+            interactions: Arc::new(Mutex::new(interactions)),
+            strict_body_comparison: false,
+            configured_strict_body_comparison: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Compares the bodies byte for byte when checking whether the data has changed, instead of
+    /// ignoring the leading and trailing whitespace and the line endings. The setting of
+    /// `ServirtiumConfiguration::set_strict_body_comparison` takes precedence.
+    pub fn with_strict_body_comparison(mut self, strict_body_comparison: bool) -> Self {
+        self.strict_body_comparison = strict_body_comparison;
+        self
+    }
+
+    pub fn interactions(&self) -> Vec<InteractionData> {
+        self.interactions.lock().unwrap().clone()
+    }
+}
+
+impl InteractionManager for MemoryInteractionManager {
+    fn load_interactions(
+        &self,
+    ) -> Result<Vec<InteractionData>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.interactions())
+    }
+
+    fn save_interactions(
+        &self,
+        interactions: &[InteractionData],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        *self.interactions.lock().unwrap() = interactions.to_vec();
+
+        Ok(())
+    }
+
+    fn check_data_unchanged(
+        &self,
+        interactions: &[InteractionData],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        MarkdownInteractionManager::compare_interactions(
+            &self.interactions(),
+            interactions,
+            self.configured_strict_body_comparison
+                .lock()
+                .unwrap()
+                .unwrap_or(self.strict_body_comparison),
+        )?;
+
+        Ok(())
+    }
+
+    fn configure(&self, configuration: &ServirtiumConfiguration) {
+        *self.configured_strict_body_comparison.lock().unwrap() =
+            configuration.strict_body_comparison();
+    }
+}
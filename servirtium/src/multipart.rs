@@ -0,0 +1,102 @@
+use crate::{util, RequestData};
+use std::collections::HashMap;
+
+pub(crate) const BOUNDARY: &str = "servirtium-boundary";
+
+/// A part of a `multipart/form-data` body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Part {
+    /// The header lines of the part, e.g. its `Content-Disposition`, separated by `\r\n`.
+    pub(crate) headers: String,
+    pub(crate) body: String,
+}
+
+/// Returns the boundary of a `multipart/form-data` request, or `None` if it isn't one.
+pub(crate) fn boundary(headers: &HashMap<String, String>) -> Option<String> {
+    let content_type = util::content_type(headers)?;
+
+    if !content_type
+        .trim_start()
+        .to_ascii_lowercase()
+        .starts_with("multipart/form-data")
+    {
+        return None;
+    }
+
+    content_type
+        .split(';')
+        .filter_map(|parameter| parameter.trim().split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, boundary)| boundary.trim().trim_matches('"').to_owned())
+        .filter(|boundary| !boundary.is_empty())
+}
+
+/// Splits a multipart body into its parts, or returns `None` if it doesn't end with the closing
+/// delimiter. The preamble and the epilogue are left out.
+pub(crate) fn parse(body: &str, boundary: &str) -> Option<Vec<Part>> {
+    // the first delimiter may start the body, the others start after the line break of a part
+    let body = format!("\r\n{}", body);
+    let delimiter = format!("\r\n--{}", boundary);
+    let mut segments = body.split(delimiter.as_str()).skip(1);
+    let mut parts = Vec::new();
+
+    loop {
+        let segment = segments.next()?;
+        if segment.starts_with("--") {
+            return Some(parts);
+        }
+
+        // the delimiter line may end with whitespace before its line break
+        let part = &segment[segment.find("\r\n")? + 2..];
+        let (headers, body) = match part.strip_prefix("\r\n") {
+            Some(body) => ("", body),
+            None => part.split_once("\r\n\r\n")?,
+        };
+
+        parts.push(Part {
+            headers: String::from(headers),
+            body: String::from(body),
+        });
+    }
+}
+
+/// Builds a multipart body from its parts.
+pub(crate) fn build(parts: &[Part], boundary: &str) -> String {
+    let mut body = String::new();
+
+    for part in parts {
+        body.push_str(&format!("--{}\r\n", boundary));
+        if !part.headers.is_empty() {
+            body.push_str(&format!("{}\r\n", part.headers));
+        }
+        body.push_str(&format!("\r\n{}\r\n", part.body));
+    }
+    body.push_str(&format!("--{}--\r\n", boundary));
+
+    body
+}
+
+/// Rebuilds the body of a `multipart/form-data` request with a fixed boundary. Clients usually
+/// generate a random boundary for every request, which would make the recorded body differ on
+/// every run and keep a replayed request from matching the recorded one. The preamble and the
+/// epilogue are dropped, and the body is left as it is if its parts can't be parsed.
+pub(crate) fn normalize_boundary(request_data: &mut RequestData) {
+    let boundary = match boundary(&request_data.headers) {
+        Some(boundary) => boundary,
+        None => return,
+    };
+    let parts = match parse(&request_data.body, &boundary) {
+        Some(parts) => parts,
+        None => return,
+    };
+
+    if let Some((_, content_type)) = request_data
+        .headers
+        .iter_mut()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+    {
+        *content_type = content_type.replace(&boundary, BOUNDARY);
+    }
+    request_data.body = build(&parts, BOUNDARY);
+    util::update_content_length(&mut request_data.headers, &request_data.body);
+}
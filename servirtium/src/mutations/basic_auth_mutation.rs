@@ -0,0 +1,29 @@
+use super::HeadersMutation;
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub struct ScrubBasicAuthMutation {
+    placeholder: String,
+}
+
+impl ScrubBasicAuthMutation {
+    pub fn new<S: Into<String>>(placeholder: S) -> Self {
+        Self {
+            placeholder: placeholder.into(),
+        }
+    }
+}
+
+impl HeadersMutation for ScrubBasicAuthMutation {
+    fn mutate(&self, headers: &mut HashMap<String, String>) {
+        for (header_name, header_value) in headers.iter_mut() {
+            let is_basic_auth = header_value
+                .get(..6)
+                .is_some_and(|scheme| scheme.eq_ignore_ascii_case("basic "));
+
+            if header_name.eq_ignore_ascii_case("authorization") && is_basic_auth {
+                *header_value = format!("Basic {}", self.placeholder);
+            }
+        }
+    }
+}
@@ -45,3 +45,48 @@ impl BodyMutation for BodyReplaceRegexMutation {
             .into();
     }
 }
+
+/// Replaces only the given capture group of every match, keeping the rest of the match, e.g. the
+/// pattern `"token":\s*"(?P<token>[^"]*)"` with the group `token` turns `"token": "abc"` into
+/// `"token": "MASKED"`. The group is either a name or a number, `1` being the first group.
+#[derive(Debug)]
+pub struct BodyMaskRegexGroupMutation {
+    pattern: Regex,
+    group: String,
+    mask: String,
+}
+
+impl BodyMaskRegexGroupMutation {
+    pub fn new<S1: Into<String>, S2: Into<String>>(pattern: Regex, group: S1, mask: S2) -> Self {
+        BodyMaskRegexGroupMutation {
+            pattern,
+            group: group.into(),
+            mask: mask.into(),
+        }
+    }
+}
+
+impl BodyMutation for BodyMaskRegexGroupMutation {
+    fn mutate(&self, body: &mut String) {
+        let group_index = self.group.parse::<usize>().ok();
+        let mut masked = String::with_capacity(body.len());
+        let mut last_end = 0;
+
+        for captures in self.pattern.captures_iter(body) {
+            let group = match group_index {
+                Some(index) => captures.get(index),
+                None => captures.name(&self.group),
+            };
+
+            // groups that didn't take part in the match are left alone
+            if let Some(group) = group {
+                masked.push_str(&body[last_end..group.start()]);
+                masked.push_str(&self.mask);
+                last_end = group.end();
+            }
+        }
+
+        masked.push_str(&body[last_end..]);
+        *body = masked;
+    }
+}
@@ -0,0 +1,64 @@
+use super::BodyMutation;
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Replaces the values of the given keys anywhere in a JSON body with a mask. Bodies that aren't
+/// valid JSON or don't contain any of the keys are left untouched, otherwise the body is
+/// serialized again without the original formatting.
+#[derive(Debug)]
+pub struct JsonMaskKeysMutation {
+    keys: HashSet<String>,
+    mask: String,
+}
+
+impl JsonMaskKeysMutation {
+    pub fn new<S: Into<String>, I: IntoIterator<Item = S>>(keys: I) -> Self {
+        JsonMaskKeysMutation {
+            keys: keys.into_iter().map(Into::into).collect(),
+            mask: String::from("MASKED"),
+        }
+    }
+
+    pub fn with_mask<S: Into<String>>(mut self, mask: S) -> Self {
+        self.mask = mask.into();
+        self
+    }
+
+    fn mask_value(&self, value: &mut Value) -> bool {
+        match value {
+            Value::Object(object) => {
+                let mut masked = false;
+                for (key, value) in object.iter_mut() {
+                    if self.keys.contains(key) {
+                        *value = Value::String(self.mask.clone());
+                        masked = true;
+                    } else {
+                        masked |= self.mask_value(value);
+                    }
+                }
+                masked
+            }
+            Value::Array(values) => {
+                let mut masked = false;
+                for value in values.iter_mut() {
+                    masked |= self.mask_value(value);
+                }
+                masked
+            }
+            _ => false,
+        }
+    }
+}
+
+impl BodyMutation for JsonMaskKeysMutation {
+    fn mutate(&self, body: &mut String) {
+        let mut value = match serde_json::from_str::<Value>(body) {
+            Ok(value) => value,
+            Err(_) => return,
+        };
+
+        if self.mask_value(&mut value) {
+            *body = value.to_string();
+        }
+    }
+}
@@ -1,13 +1,35 @@
 mod add_header_mutation;
+mod basic_auth_mutation;
 mod body_replace_mutation;
+#[cfg(feature = "json")]
+mod json_mask_mutation;
+mod redact_cookies_mutation;
 mod remove_headers_mutation;
+mod url_rewrite_mutation;
+#[cfg(feature = "pretty-xml")]
+mod xml_pretty_print_mutation;
 
 use crate::{RequestData, ResponseData};
 use add_header_mutation::AddHeaderMutation;
-use body_replace_mutation::{BodyReplaceMutation, BodyReplaceRegexMutation};
+use basic_auth_mutation::ScrubBasicAuthMutation;
+use body_replace_mutation::{
+    BodyMaskRegexGroupMutation, BodyReplaceMutation, BodyReplaceRegexMutation,
+};
+#[cfg(feature = "json")]
+pub use json_mask_mutation::JsonMaskKeysMutation;
+use redact_cookies_mutation::RedactCookiesMutation;
 use regex::Regex;
-use remove_headers_mutation::{RemoveHeadersMutation, RemoveHeadersRegexMutation};
-use std::{collections::HashMap, fmt::Debug};
+use remove_headers_mutation::{
+    RemoveHeadersMutation, RemoveHeadersRegexMutation, RetainHeadersMutation,
+};
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+};
+pub use url_rewrite_mutation::UrlRewriteMutation;
+#[cfg(feature = "pretty-xml")]
+pub use xml_pretty_print_mutation::XmlPrettyPrintMutation;
 
 pub trait BodyMutation: Debug {
     fn mutate(&self, body: &mut String);
@@ -17,56 +39,139 @@ pub trait HeadersMutation: Debug {
     fn mutate(&self, headers: &mut HashMap<String, String>);
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum MutationType {
-    Body(Box<dyn BodyMutation + Send + Sync>),
-    Headers(Box<dyn HeadersMutation + Send + Sync>),
+    Body(Arc<dyn BodyMutation + Send + Sync>),
+    Headers(Arc<dyn HeadersMutation + Send + Sync>),
+    Conditional(Arc<ConditionalMutation>),
+}
+
+impl MutationType {
+    fn mutate(&self, headers: &mut HashMap<String, String>, body: &mut String) {
+        match self {
+            MutationType::Headers(hm) => {
+                hm.mutate(headers);
+            }
+            MutationType::Body(bm) => {
+                bm.mutate(body);
+            }
+            MutationType::Conditional(cm) => {
+                cm.mutate(headers, body);
+            }
+        }
+    }
+}
+
+type Predicate = dyn Fn(&HashMap<String, String>, &str) -> bool + Send + Sync;
+
+/// Applies the inner mutations only if the predicate holds for the headers and the body as they
+/// are before the first inner mutation, see [`MutationsBuilder::when`].
+pub struct ConditionalMutation {
+    predicate: Box<Predicate>,
+    mutations: Vec<MutationType>,
+}
+
+impl ConditionalMutation {
+    fn mutate(&self, headers: &mut HashMap<String, String>, body: &mut String) {
+        if (self.predicate)(headers, body) {
+            for mutation in &self.mutations {
+                mutation.mutate(headers, body);
+            }
+        }
+    }
+}
+
+impl Debug for ConditionalMutation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConditionalMutation")
+            .field("mutations", &self.mutations)
+            .finish()
+    }
+}
+
+/// A mutation that gets the whole request, e.g. to set a header based on the body. It can be
+/// added with the `add_full_*_request_mutation` methods of `ServirtiumConfiguration`.
+pub trait FullRequestMutation: Debug {
+    fn mutate(&self, request_data: &mut RequestData);
+}
+
+/// A mutation that gets the whole response, e.g. to set a header based on the status code. It can
+/// be added with the `add_full_*_response_mutation` methods of `ServirtiumConfiguration`.
+pub trait FullResponseMutation: Debug {
+    fn mutate(&self, response_data: &mut ResponseData);
+}
+
+#[derive(Debug)]
+enum RequestMutationType {
+    Parts(MutationType),
+    Full(Arc<dyn FullRequestMutation + Send + Sync>),
 }
 
 #[derive(Debug)]
 pub struct RequestMutation {
-    mutation_type: MutationType,
+    mutation_type: RequestMutationType,
 }
 
 impl RequestMutation {
     fn from_mutation_type(mutation_type: MutationType) -> Self {
-        Self { mutation_type }
+        Self {
+            mutation_type: RequestMutationType::Parts(mutation_type),
+        }
+    }
+
+    pub fn from_full<M: FullRequestMutation + Send + Sync + 'static>(mutation: M) -> Self {
+        Self {
+            mutation_type: RequestMutationType::Full(Arc::new(mutation)),
+        }
     }
 
     pub fn mutate(&self, request_data: &mut RequestData) {
         match &self.mutation_type {
-            MutationType::Headers(hm) => {
-                hm.mutate(&mut request_data.headers);
-            }
-            MutationType::Body(bm) => {
-                bm.mutate(&mut request_data.body);
+            RequestMutationType::Parts(mutation_type) => {
+                mutation_type.mutate(&mut request_data.headers, &mut request_data.body)
             }
+            RequestMutationType::Full(mutation) => mutation.mutate(request_data),
         }
     }
 }
 
+#[derive(Debug)]
+enum ResponseMutationType {
+    Parts(MutationType),
+    Full(Arc<dyn FullResponseMutation + Send + Sync>),
+}
+
 #[derive(Debug)]
 pub struct ResponseMutation {
-    mutation_type: MutationType,
+    mutation_type: ResponseMutationType,
 }
 
 impl ResponseMutation {
     fn from_mutation_type(mutation_type: MutationType) -> Self {
-        Self { mutation_type }
+        Self {
+            mutation_type: ResponseMutationType::Parts(mutation_type),
+        }
+    }
+
+    pub fn from_full<M: FullResponseMutation + Send + Sync + 'static>(mutation: M) -> Self {
+        Self {
+            mutation_type: ResponseMutationType::Full(Arc::new(mutation)),
+        }
     }
 
     pub fn mutate(&self, response_data: &mut ResponseData) {
         match &self.mutation_type {
-            MutationType::Headers(hm) => {
-                hm.mutate(&mut response_data.headers);
-            }
-            MutationType::Body(bm) => {
-                bm.mutate(&mut response_data.body);
+            ResponseMutationType::Parts(mutation_type) => {
+                mutation_type.mutate(&mut response_data.headers, &mut response_data.body)
             }
+            ResponseMutationType::Full(mutation) => mutation.mutate(response_data),
         }
     }
 }
 
+/// Collects body and header mutations. Mutations are applied in the order they were added,
+/// regardless of their kind, so a header mutation added after a body mutation always sees the
+/// already mutated body. The same builder can produce both request and response mutations.
 pub struct MutationsBuilder {
     mutations: Vec<MutationType>,
 }
@@ -92,6 +197,13 @@ impl MutationsBuilder {
         self.add_headers_mutation(RemoveHeadersRegexMutation::new(patterns))
     }
 
+    pub fn retain_headers<S: Into<String>, I: IntoIterator<Item = S>>(
+        &mut self,
+        headers: I,
+    ) -> &mut Self {
+        self.add_headers_mutation(RetainHeadersMutation::new(headers))
+    }
+
     pub fn add_header<S1: Into<String>, S2: Into<String>>(
         &mut self,
         header_name: S1,
@@ -100,6 +212,19 @@ impl MutationsBuilder {
         self.add_headers_mutation(AddHeaderMutation::new(header_name, header_value))
     }
 
+    pub fn scrub_basic_auth<S: Into<String>>(&mut self, placeholder: S) -> &mut Self {
+        self.add_headers_mutation(ScrubBasicAuthMutation::new(placeholder))
+    }
+
+    /// Replaces the values of the given cookies with `REDACTED` in `Cookie` and `Set-Cookie`
+    /// headers, keeping the rest of the headers.
+    pub fn redact_cookies<S: Into<String>, I: IntoIterator<Item = S>>(
+        &mut self,
+        cookie_names: I,
+    ) -> &mut Self {
+        self.add_headers_mutation(RedactCookiesMutation::new(cookie_names))
+    }
+
     pub fn body_replace<S1: Into<String>, S2: Into<String>>(
         &mut self,
         text: S1,
@@ -116,12 +241,83 @@ impl MutationsBuilder {
         self.add_body_mutation(BodyReplaceRegexMutation::new(pattern, replacement))
     }
 
+    /// Replaces a capture group of every match with `mask`, keeping the rest of the match. The
+    /// group is a name or a number, e.g. to mask a token but keep the key and the quotes:
+    ///
+    /// ```
+    /// # use regex::Regex;
+    /// # use servirtium::ServirtiumConfiguration;
+    /// # fn configure(config: &mut ServirtiumConfiguration) {
+    /// config.add_record_response_mutations(|builder| {
+    ///     builder.body_mask_regex_group(
+    ///         Regex::new(r#""token":\s*"(?P<token>[^"]*)""#).unwrap(),
+    ///         "token",
+    ///         "REDACTED",
+    ///     )
+    /// });
+    /// # }
+    /// ```
+    pub fn body_mask_regex_group<S1: Into<String>, S2: Into<String>>(
+        &mut self,
+        pattern: Regex,
+        group: S1,
+        mask: S2,
+    ) -> &mut Self {
+        self.add_body_mutation(BodyMaskRegexGroupMutation::new(pattern, group, mask))
+    }
+
+    /// Rewrites the absolute URLs starting with `from` to start with `to`, see
+    /// [`UrlRewriteMutation`].
+    pub fn rewrite_urls<S1: Into<String>, S2: Into<String>>(
+        &mut self,
+        from: S1,
+        to: S2,
+    ) -> &mut Self {
+        self.add_body_mutation(UrlRewriteMutation::new(from, to))
+    }
+
+    /// Masks the values of the given keys anywhere in JSON bodies, see [`JsonMaskKeysMutation`].
+    #[cfg(feature = "json")]
+    pub fn json_mask_keys<S: Into<String>, I: IntoIterator<Item = S>>(
+        &mut self,
+        keys: I,
+    ) -> &mut Self {
+        self.add_body_mutation(JsonMaskKeysMutation::new(keys))
+    }
+
+    /// Indents XML bodies, see [`XmlPrettyPrintMutation`].
+    #[cfg(feature = "pretty-xml")]
+    pub fn pretty_print_xml(&mut self) -> &mut Self {
+        self.add_body_mutation(XmlPrettyPrintMutation::new())
+    }
+
+    /// Adds the mutations added by `func` so that they are only applied if `predicate` holds for
+    /// the headers and the body, e.g. to scrub only JSON bodies.
+    pub fn when<
+        P: Fn(&HashMap<String, String>, &str) -> bool + Send + Sync + 'static,
+        F: FnOnce(&mut MutationsBuilder) -> &mut MutationsBuilder,
+    >(
+        &mut self,
+        predicate: P,
+        func: F,
+    ) -> &mut Self {
+        let mut mutations = MutationsBuilder::new();
+        let _ = func(&mut mutations);
+
+        self.mutations
+            .push(MutationType::Conditional(Arc::new(ConditionalMutation {
+                predicate: Box::new(predicate),
+                mutations: mutations.mutations,
+            })));
+        self
+    }
+
     pub fn add_headers_mutation<HM: HeadersMutation + Send + Sync + 'static>(
         &mut self,
         mutation: HM,
     ) -> &mut Self {
         self.mutations
-            .push(MutationType::Headers(Box::new(mutation)));
+            .push(MutationType::Headers(Arc::new(mutation)));
         self
     }
 
@@ -129,10 +325,26 @@ impl MutationsBuilder {
         &mut self,
         mutation: BM,
     ) -> &mut Self {
-        self.mutations.push(MutationType::Body(Box::new(mutation)));
+        self.mutations.push(MutationType::Body(Arc::new(mutation)));
         self
     }
 
+    pub fn response_mutations(&self) -> Vec<ResponseMutation> {
+        self.mutations
+            .iter()
+            .cloned()
+            .map(ResponseMutation::from_mutation_type)
+            .collect()
+    }
+
+    pub fn request_mutations(&self) -> Vec<RequestMutation> {
+        self.mutations
+            .iter()
+            .cloned()
+            .map(RequestMutation::from_mutation_type)
+            .collect()
+    }
+
     pub fn into_response_mutations(self) -> Vec<ResponseMutation> {
         self.mutations
             .into_iter()
@@ -0,0 +1,65 @@
+use super::HeadersMutation;
+use crate::util;
+use std::collections::{HashMap, HashSet};
+
+/// Replaces the values of the given cookies in `Cookie` and `Set-Cookie` headers, keeping the
+/// other cookies and the attributes of `Set-Cookie` as they are. Cookie names are case-sensitive.
+#[derive(Debug)]
+pub struct RedactCookiesMutation {
+    cookie_names: HashSet<String>,
+    mask: String,
+}
+
+impl RedactCookiesMutation {
+    pub fn new<S: Into<String>, I: IntoIterator<Item = S>>(cookie_names: I) -> Self {
+        Self {
+            cookie_names: cookie_names.into_iter().map(Into::into).collect(),
+            mask: String::from("REDACTED"),
+        }
+    }
+
+    fn redact_pair(&self, pair: &str) -> String {
+        match pair.split_once('=') {
+            Some((name, _)) if self.cookie_names.contains(name.trim()) => {
+                format!("{}={}", name, self.mask)
+            }
+            _ => String::from(pair),
+        }
+    }
+
+    /// A `Cookie` header holds `name=value` pairs separated by `; `.
+    fn redact_cookie(&self, value: &str) -> String {
+        value
+            .split(';')
+            .map(|pair| self.redact_pair(pair))
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+
+    /// A `Set-Cookie` header holds a single `name=value` pair followed by its attributes.
+    fn redact_set_cookie(&self, value: &str) -> String {
+        match value.split_once(';') {
+            Some((pair, attributes)) => format!("{};{}", self.redact_pair(pair), attributes),
+            None => self.redact_pair(value),
+        }
+    }
+}
+
+impl HeadersMutation for RedactCookiesMutation {
+    fn mutate(&self, headers: &mut HashMap<String, String>) {
+        for (header_name, header_value) in headers.iter_mut() {
+            let redact = if header_name.eq_ignore_ascii_case("cookie") {
+                Self::redact_cookie
+            } else if header_name.eq_ignore_ascii_case("set-cookie") {
+                Self::redact_set_cookie
+            } else {
+                continue;
+            };
+
+            *header_value = util::header_values(header_value)
+                .map(|value| redact(self, value))
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use super::HeadersMutation;
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug)]
 pub struct RemoveHeadersMutation {
@@ -55,3 +55,25 @@ impl HeadersMutation for RemoveHeadersRegexMutation {
         }
     }
 }
+
+#[derive(Debug)]
+pub struct RetainHeadersMutation {
+    headers: HashSet<String>,
+}
+
+impl RetainHeadersMutation {
+    pub fn new<S: Into<String>, I: IntoIterator<Item = S>>(headers: I) -> Self {
+        Self {
+            headers: headers
+                .into_iter()
+                .map(|e| e.into().to_lowercase())
+                .collect(),
+        }
+    }
+}
+
+impl HeadersMutation for RetainHeadersMutation {
+    fn mutate(&self, headers: &mut HashMap<String, String>) {
+        headers.retain(|header_name, _| self.headers.contains(&header_name.to_lowercase()));
+    }
+}
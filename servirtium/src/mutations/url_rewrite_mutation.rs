@@ -0,0 +1,27 @@
+use super::BodyMutation;
+
+/// Replaces the absolute URLs starting with one base URL with the same URLs starting with another
+/// one, e.g. pagination links of the destination API with links to the Servirtium server.
+#[derive(Debug)]
+pub struct UrlRewriteMutation {
+    from: String,
+    to: String,
+}
+
+impl UrlRewriteMutation {
+    pub fn new<S1: Into<String>, S2: Into<String>>(from: S1, to: S2) -> Self {
+        // the trailing slash of a domain name would otherwise stop the URLs from matching
+        UrlRewriteMutation {
+            from: from.into().trim_end_matches('/').into(),
+            to: to.into().trim_end_matches('/').into(),
+        }
+    }
+}
+
+impl BodyMutation for UrlRewriteMutation {
+    fn mutate(&self, body: &mut String) {
+        if !self.from.is_empty() && body.contains(&self.from) {
+            *body = body.replace(&self.from, &self.to);
+        }
+    }
+}
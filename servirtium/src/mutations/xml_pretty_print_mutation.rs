@@ -0,0 +1,21 @@
+use super::BodyMutation;
+use crate::util;
+
+/// Indents XML bodies so they are easier to review in the markdown. Other bodies are left
+/// untouched.
+#[derive(Debug, Default)]
+pub struct XmlPrettyPrintMutation;
+
+impl XmlPrettyPrintMutation {
+    pub fn new() -> Self {
+        XmlPrettyPrintMutation
+    }
+}
+
+impl BodyMutation for XmlPrettyPrintMutation {
+    fn mutate(&self, body: &mut String) {
+        if let Some(pretty_body) = util::pretty_print_xml(body) {
+            *body = pretty_body;
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use crate::{
+    error::Error, test_session, RequestData, ResponseData, ServirtiumConfiguration,
+    ServirtiumServer,
+};
+use std::sync::Mutex;
+
+/// Plays back the recorded interactions to requests made directly by the code under test, without
+/// starting the Servirtium server or going through TCP. It's meant for fast unit tests of the way
+/// requests are built, and unlike a [`TestSession`](crate::TestSession) several handles can be
+/// used at the same time.
+///
+/// The configuration is used the same way as in playback mode, including the stubs, the matching
+/// and the playback response mutations, whatever its mode is.
+#[derive(Debug)]
+pub struct PlaybackHandle {
+    server: Mutex<ServirtiumServer>,
+}
+
+impl PlaybackHandle {
+    pub fn new(configuration: ServirtiumConfiguration) -> Self {
+        test_session::register_fixtures(&*configuration.interaction_manager());
+        configuration
+            .interaction_manager()
+            .configure(&configuration);
+
+        let mut server = ServirtiumServer::new();
+        server.configuration = Some(configuration);
+
+        Self {
+            server: Mutex::new(server),
+        }
+    }
+
+    /// Returns the response recorded for the request, loading the recording on the first call.
+    pub fn playback_response(&self, request_data: &RequestData) -> Result<ResponseData, Error> {
+        self.server.lock()?.playback_response(request_data.clone())
+    }
+
+    /// Checks the playback the same way [`TestSession::after_test`](crate::TestSession::after_test)
+    /// does, e.g. that an interaction has been replayed if that's required.
+    pub fn finish(self) -> Result<(), Error> {
+        let server = self.server.into_inner()?;
+        let config = server.configuration.as_ref().ok_or(Error::NotConfigured)?;
+
+        if config.require_playback_used() && server.used_interaction_count() == 0 {
+            return Err(Error::PlaybackUnused);
+        }
+
+        if config.require_exact_interaction_count() {
+            server.check_exact_interaction_count()?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{util, ResponseData};
+
+/// Answers a `Range: bytes=...` request with the requested part of a full recorded response. Only
+/// single ranges are supported, other requests get the full response. A range that doesn't
+/// overlap the body is answered with `416 Range Not Satisfiable`.
+pub(crate) fn apply_range(range: &str, response_data: &mut ResponseData) {
+    if response_data.status_code != 200 {
+        return;
+    }
+
+    let length = response_data.body.len();
+    let (start, end) = match parse_range(range, length) {
+        Some(Ok(bounds)) => bounds,
+        Some(Err(())) => {
+            response_data.status_code = 416;
+            response_data.body.clear();
+            response_data
+                .headers
+                .insert(String::from("content-range"), format!("bytes */{}", length));
+            return;
+        }
+        None => return,
+    };
+
+    // the body is a string, so it can only be split at character boundaries
+    if !response_data.body.is_char_boundary(start) || !response_data.body.is_char_boundary(end + 1)
+    {
+        return;
+    }
+
+    response_data.status_code = 206;
+    response_data.body = response_data.body[start..=end].to_owned();
+    response_data.headers.insert(
+        String::from("content-range"),
+        format!("bytes {}-{}/{}", start, end, length),
+    );
+    util::update_content_length(&mut response_data.headers, &response_data.body);
+}
+
+/// Returns `None` if the range can't be served and `Some(Err(()))` if it can't be satisfied.
+fn parse_range(range: &str, length: usize) -> Option<Result<(usize, usize), ()>> {
+    let range = range.trim().strip_prefix("bytes=")?;
+    if range.contains(',') {
+        return None;
+    }
+
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let bounds = if start.is_empty() {
+        let suffix_length: usize = end.parse().ok()?;
+        if suffix_length == 0 || length == 0 {
+            return Some(Err(()));
+        }
+        (length.saturating_sub(suffix_length), length - 1)
+    } else {
+        let start: usize = start.parse().ok()?;
+        let end = if end.is_empty() {
+            usize::MAX
+        } else {
+            end.parse().ok()?
+        };
+        if start > end {
+            return None;
+        }
+        if start >= length {
+            return Some(Err(()));
+        }
+        (start, end.min(length - 1))
+    };
+
+    Some(Ok(bounds))
+}
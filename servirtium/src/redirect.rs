@@ -0,0 +1,177 @@
+use crate::{error::Error, http_client::HttpClient, util, RequestData, ResponseData};
+use hyper::Uri;
+
+/// The most redirects followed for a single request before giving up, e.g. because of a loop.
+const MAX_REDIRECTS: usize = 10;
+
+/// What happens when the destination API answers with a redirect in record mode, see
+/// `ServirtiumConfiguration::set_redirect_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RedirectPolicy {
+    /// Records the redirect as it is, so the client follows it through Servirtium, if at all.
+    #[default]
+    Record,
+    /// Follows the redirects to the origin of the destination API and records the final response
+    /// for the original request. The redirects to other origins are recorded as they are.
+    FollowSameOrigin,
+    /// Follows all the redirects, including the ones to other origins, and records the final
+    /// response for the original request.
+    FollowAll,
+}
+
+/// Follows the redirects the policy allows, starting from the response to `request_data`, and
+/// returns the final response. If the raw body of the first response is given, the raw body of
+/// the final one is returned as well. The followed responses are never streamed.
+pub(crate) async fn follow(
+    http_client: &(dyn HttpClient + Send + Sync),
+    policy: RedirectPolicy,
+    domain_name: &str,
+    request_data: &RequestData,
+    mut response: (ResponseData, Option<Vec<u8>>),
+) -> Result<(ResponseData, Option<Vec<u8>>), Error> {
+    if policy == RedirectPolicy::Record {
+        return Ok(response);
+    }
+
+    let domain_origin = origin(domain_name).ok_or(Error::InvalidDomainName)?;
+    let mut current_origin = domain_origin.clone();
+    let mut request_data = request_data.clone();
+    let mut redirects = 0;
+
+    loop {
+        let location = match location(&response.0) {
+            Some(location) => location,
+            None => return Ok(response),
+        };
+        let (target_origin, target_uri) = resolve(&current_origin, &request_data.uri, location)
+            .ok_or_else(|| Error::InvalidRedirect(String::from(location)))?;
+
+        let same_origin = target_origin.eq_ignore_ascii_case(&domain_origin);
+        if policy == RedirectPolicy::FollowSameOrigin && !same_origin {
+            return Ok(response);
+        }
+
+        if redirects == MAX_REDIRECTS {
+            return Err(Error::TooManyRedirects(format!(
+                "{} {}",
+                request_data.method, request_data.uri
+            )));
+        }
+        redirects += 1;
+
+        redirect_request(
+            &mut request_data,
+            response.0.status_code,
+            !target_origin.eq_ignore_ascii_case(&current_origin),
+        );
+        request_data.uri = target_uri;
+        if let Some(host) = target_origin
+            .parse::<Uri>()
+            .ok()
+            .as_ref()
+            .and_then(Uri::host)
+        {
+            util::remove_header(&mut request_data.headers, "host");
+            request_data
+                .headers
+                .insert(String::from("host"), String::from(host));
+        }
+        current_origin = target_origin;
+
+        response = match response.1 {
+            Some(_) => {
+                let (response_data, raw_body) = http_client
+                    .make_raw_request(&current_origin, &request_data)
+                    .await?;
+                (response_data, Some(raw_body))
+            }
+            None => (
+                http_client
+                    .make_request(&current_origin, &request_data)
+                    .await?,
+                None,
+            ),
+        };
+    }
+}
+
+/// Returns the `Location` of a redirect, or `None` if the response isn't one.
+fn location(response_data: &ResponseData) -> Option<&str> {
+    match response_data.status_code {
+        301 | 302 | 303 | 307 | 308 => util::find_header(&response_data.headers, "location"),
+        _ => None,
+    }
+}
+
+/// Returns the scheme and the authority of a URL, e.g. `https://example.com:8443`.
+fn origin(url: &str) -> Option<String> {
+    let uri = url.parse::<Uri>().ok()?;
+
+    Some(format!("{}://{}", uri.scheme_str()?, uri.authority()?))
+}
+
+/// Resolves the location of a redirect against the request it answers, and returns the origin
+/// and the path and query to request next.
+fn resolve(current_origin: &str, current_uri: &str, location: &str) -> Option<(String, String)> {
+    let location = location.trim();
+
+    if location.starts_with("//") {
+        let scheme = current_origin.split("://").next()?;
+        return resolve(
+            current_origin,
+            current_uri,
+            &format!("{}:{}", scheme, location),
+        );
+    }
+
+    if location.contains("://") {
+        let uri = location.parse::<Uri>().ok()?;
+        let path_and_query = uri.path_and_query().map_or("/", |p| p.as_str());
+
+        return Some((origin(location)?, String::from(path_and_query)));
+    }
+
+    let path = if location.starts_with('/') {
+        String::from(location)
+    } else {
+        // a relative path replaces the last segment of the current path
+        let current_path = util::path_and_query(current_uri)
+            .split('?')
+            .next()
+            .unwrap_or_default();
+        let directory = &current_path[..current_path.rfind('/').map_or(0, |index| index + 1)];
+        format!(
+            "{}{}",
+            if directory.is_empty() { "/" } else { directory },
+            location
+        )
+    };
+
+    Some((String::from(current_origin), path))
+}
+
+/// Turns the request into the one to send to the location of a redirect with the given status.
+fn redirect_request(request_data: &mut RequestData, status_code: u16, cross_origin: bool) {
+    // like browsers, `303 See Other` and the `POST` requests redirected by `301` and `302` are
+    // followed with a `GET` without a body, while `307` and `308` keep the method and the body
+    let changes_to_get = match status_code {
+        303 => !request_data.method.eq_ignore_ascii_case("HEAD"),
+        301 | 302 => request_data.method.eq_ignore_ascii_case("POST"),
+        _ => false,
+    };
+
+    if changes_to_get {
+        request_data.method = String::from("GET");
+        request_data.body.clear();
+        for header_name in ["content-length", "content-type", "transfer-encoding"] {
+            util::remove_header(&mut request_data.headers, header_name);
+        }
+    }
+
+    // the credentials of the destination API aren't sent to other origins
+    if cross_origin {
+        for header_name in ["authorization", "cookie"] {
+            util::remove_header(&mut request_data.headers, header_name);
+        }
+    }
+}
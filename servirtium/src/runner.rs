@@ -1,33 +1,47 @@
-use crate::{error::Error, util, RequestData, ServirtiumServer, TestSession};
+use crate::{charset, error::Error, util, RequestData, ServirtiumServer, TestSession};
 use hyper::{
-    body,
+    body::{self, Bytes},
+    ext::ReasonPhrase,
     service::{make_service_fn, service_fn},
     Body, Request, Response, Server,
 };
-use std::{convert::Infallible, net::SocketAddr, sync::Once, thread};
-use tokio::runtime::Runtime;
+use std::{
+    convert::{Infallible, TryFrom},
+    net::{SocketAddr, TcpListener},
+    sync::OnceLock,
+    thread,
+};
+use tokio::{runtime::Runtime, time};
 
-static INITIALIZE_SERVIRTIUM: Once = Once::new();
+static SERVIRTIUM_ADDRESS: OnceLock<SocketAddr> = OnceLock::new();
 
-pub(crate) fn start_once() {
-    INITIALIZE_SERVIRTIUM.call_once(|| {
+/// Starts the server on the given address unless it's already running, and returns the address it
+/// listens on, which is the address of the first call with the port picked by the OS if it was 0.
+pub(crate) fn start_once(address: SocketAddr) -> SocketAddr {
+    *SERVIRTIUM_ADDRESS.get_or_init(|| {
         let mut server_instance = ServirtiumServer::instance();
 
+        // binding before the thread starts makes sure the test can connect as soon as it begins
+        let listener = TcpListener::bind(address).expect("Couldn't bind the Servirtium server");
+        let address = listener.local_addr().unwrap_or(address);
+
         server_instance.join_handle = Some(thread::spawn(move || {
             Runtime::new().unwrap().block_on(async {
-                let addr = SocketAddr::from(([127, 0, 0, 1], 61417));
-
-                let server = Server::bind(&addr).serve(make_service_fn(|_| async {
-                    Ok::<_, Infallible>(service_fn(|req| async move {
-                        match handle_request(req).await {
-                            Ok(response) => Ok(response),
-                            Err(err) => {
-                                TestSession::set_error(err);
-                                Ok::<Response<Body>, Infallible>(Response::new(Body::empty()))
+                let server = Server::from_tcp(listener)
+                    .expect("Couldn't start the Servirtium server")
+                    .serve(make_service_fn(|_| async {
+                        Ok::<_, Infallible>(service_fn(|req| async move {
+                            match handle_request(req).await {
+                                Ok(response) => Ok(response),
+                                // returning an error makes hyper drop the connection without a response
+                                Err(Error::ConnectionClosed) => Err(Error::ConnectionClosed),
+                                Err(err) => {
+                                    TestSession::set_error(err);
+                                    Ok::<Response<Body>, Error>(Response::new(Body::empty()))
+                                }
                             }
-                        }
-                    }))
-                }));
+                        }))
+                    }));
 
                 if let Err(e) = server.await {
                     eprintln!("Servirtium Server error: {}", e);
@@ -36,39 +50,87 @@ pub(crate) fn start_once() {
         }));
 
         server_instance.release_instance();
-    });
+
+        address
+    })
+}
+
+/// Returns the address the server listens on, or `None` if it hasn't been started.
+pub(crate) fn running_address() -> Option<SocketAddr> {
+    SERVIRTIUM_ADDRESS.get().copied()
 }
 
 async fn handle_request(mut request: Request<Body>) -> Result<Response<Body>, Error> {
-    let mut instance = ServirtiumServer::instance();
-    let request_data = read_request_data(&mut request).await?;
+    let (request_data, raw_body) = read_request_data(&mut request).await?;
 
-    let response_data = instance.handle_request(request_data).await?;
+    // the instance must be released even if the request fails, otherwise the next test hangs
+    let mut instance = ServirtiumServer::instance();
+    let result = instance.handle_request(request_data, &raw_body).await;
+    let delay = instance.playback_delay.take();
     instance.release_instance();
+    let mut response_data = result?;
+
+    // waiting after the release lets the other requests be answered in the meantime
+    if let Some(delay) = delay {
+        time::sleep(delay).await;
+    }
+
+    let decoded_length = response_data.body.len();
+    let body = charset::encode(&response_data.headers, response_data.body);
+    if body.len() != decoded_length {
+        util::set_content_length(&mut response_data.headers, body.len());
+    }
 
     let mut response_builder = Response::builder().status(response_data.status_code);
 
+    // a phrase that isn't valid in a status line, e.g. from an edited recording, is left out
+    if let Some(reason_phrase) = response_data
+        .reason_phrase
+        .as_deref()
+        .and_then(|reason_phrase| ReasonPhrase::try_from(reason_phrase.as_bytes()).ok())
+    {
+        response_builder = response_builder.extension(reason_phrase);
+    }
+
     util::put_headers(
         response_builder.headers_mut().ok_or(Error::InvalidBody)?,
         &response_data.headers,
     )?;
 
-    Ok(response_builder.body(response_data.body.into())?)
+    Ok(response_builder.body(body.into())?)
 }
 
-async fn read_request_data(request: &mut Request<Body>) -> Result<RequestData, Error> {
+async fn read_request_data(request: &mut Request<Body>) -> Result<(RequestData, Bytes), Error> {
     let method = request.method().to_string();
     let uri = request.uri().to_string();
+    let http_version = format!("{:?}", request.version());
     let headers = util::extract_headers(request.headers());
 
-    let body = body::to_bytes(request.body_mut())
-        .await
-        .map_err(|_| Error::InvalidBody)?;
+    let timeout = {
+        let instance = ServirtiumServer::instance();
+        let timeout = instance
+            .configuration
+            .as_ref()
+            .and_then(|configuration| configuration.server_request_timeout());
+        instance.release_instance();
+        timeout
+    };
 
-    Ok(RequestData {
+    let body = match timeout {
+        Some(timeout) => time::timeout(timeout, body::to_bytes(request.body_mut()))
+            .await
+            .map_err(|_| Error::RequestTimeout(format!("{} {}", method, uri)))?,
+        None => body::to_bytes(request.body_mut()).await,
+    }
+    .map_err(|_| Error::InvalidBody)?;
+
+    let request_data = RequestData {
         method,
         uri,
+        http_version: Some(http_version),
+        body: charset::decode(&headers, &body),
         headers,
-        body: String::from_utf8_lossy(&body).into(),
-    })
+    };
+
+    Ok((request_data, body))
 }
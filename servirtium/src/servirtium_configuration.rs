@@ -1,10 +1,52 @@
 use crate::{
-    http_client::HttpClient,
+    http_client::{self, BlockingHttpClient, BlockingHttpClientAdapter, HttpClient},
     interaction_manager::InteractionManager,
-    mutations::{MutationsBuilder, RequestMutation, ResponseMutation},
-    ReqwestHttpClient, ServirtiumMode,
+    mutations::{
+        FullRequestMutation, FullResponseMutation, MutationsBuilder, RequestMutation,
+        ResponseMutation,
+    },
+    InteractionData, MatchConfiguration, NoMatchBehavior, NonUtf8BodyPolicy, RedirectPolicy,
+    ReqwestHttpClient, ResponseData, ServirtiumMode,
 };
-use std::sync::Arc;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+/// The headers removed by `scrub_common_volatile_headers`.
+const COMMON_VOLATILE_HEADERS: &[&str] = &[
+    "date",
+    "age",
+    "set-cookie",
+    "x-request-id",
+    "x-correlation-id",
+    "x-amzn-requestid",
+    "x-amzn-trace-id",
+    "x-amz-request-id",
+    "x-amz-id-2",
+    "x-runtime",
+    "x-served-by",
+    "x-timer",
+    "server-timing",
+    "traceparent",
+    "tracestate",
+];
+
+lazy_static! {
+    static ref GLOBAL_DEFAULTS: RwLock<Option<Box<GlobalDefaults>>> = RwLock::new(None);
+    /// The header families removed by `scrub_common_volatile_headers`: Cloudflare and CloudFront,
+    /// the CDN caches and the B3 tracing headers.
+    static ref COMMON_VOLATILE_HEADER_PATTERNS: Vec<Regex> =
+        ["(?i)^cf-", "(?i)^x-amz-cf-", "(?i)^x-cache", "(?i)^x-b3-"]
+            .iter()
+            .map(|pattern| Regex::new(pattern).unwrap())
+            .collect();
+}
 
 #[derive(Debug)]
 pub struct ServirtiumConfiguration {
@@ -13,9 +55,76 @@ pub struct ServirtiumConfiguration {
     fail_if_markdown_changed: bool,
     interaction_manager: Arc<dyn InteractionManager + Send + Sync>,
     http_client: Option<Arc<dyn HttpClient + Send + Sync>>,
+    /// Used when no HTTP client is set. It's built once, so all the recorded requests share its
+    /// connection pool, and rebuilt when one of its settings changes.
+    default_http_client: Arc<dyn HttpClient + Send + Sync>,
     record_request_mutations: Vec<RequestMutation>,
+    saved_request_mutations: Vec<RequestMutation>,
     record_response_mutations: Vec<ResponseMutation>,
     playback_response_mutations: Vec<ResponseMutation>,
+    stubs: Vec<Stub>,
+    match_configuration: MatchConfiguration,
+    no_match_behavior: NoMatchBehavior,
+    playback_default_response: Option<ResponseData>,
+    expected_interactions: Option<Vec<String>>,
+    expected_interaction_count: Option<usize>,
+    expected_request_headers: Vec<String>,
+    record_streaming_threshold: Option<usize>,
+    on_record: Option<Callback<OnRecord>>,
+    on_recording_changed: Option<Callback<OnRecordingChanged>>,
+    on_playback_response: Option<Callback<OnPlaybackResponse>>,
+    dedupe_interactions: bool,
+    danger_accept_invalid_certs: bool,
+    allowed_path_prefixes: Option<Vec<String>>,
+    allowed_record_methods: Option<Vec<String>>,
+    record_host_header: bool,
+    record_accept_encoding: Option<String>,
+    record_auth: Option<String>,
+    event_stream_limits: (usize, Duration),
+    non_utf8_body_policy: Option<NonUtf8BodyPolicy>,
+    strict_body_comparison: Option<bool>,
+    serve_ranges: bool,
+    playback_reason_phrases: bool,
+    playback_response_times: bool,
+    redirect_policy: RedirectPolicy,
+    #[cfg(feature = "json-schema")]
+    response_schemas: Vec<(Regex, jsonschema::Validator)>,
+    dump_raw: bool,
+    require_playback_used: bool,
+    require_exact_interaction_count: bool,
+    rewrite_absolute_urls: bool,
+    recorded_base_url: Option<String>,
+    server_request_timeout: Option<Duration>,
+    server_address: SocketAddr,
+    session_timeout: Option<Duration>,
+    first_interaction_number: usize,
+    response_overrides: HashMap<usize, ResponseOverride>,
+}
+
+type OnRecord = dyn Fn(&mut InteractionData) + Send + Sync;
+type OnRecordingChanged = dyn Fn(&str) + Send + Sync;
+type OnPlaybackResponse = dyn Fn(&mut ResponseData) + Send + Sync;
+type GlobalDefaults = dyn Fn(&mut ServirtiumConfiguration) + Send + Sync;
+
+struct Callback<F: ?Sized>(Box<F>);
+
+impl<F: ?Sized> Debug for Callback<F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Callback")
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ResponseOverride {
+    pub(crate) status_code: Option<u16>,
+    pub(crate) body: Option<String>,
+}
+
+#[derive(Debug)]
+struct Stub {
+    method: String,
+    uri: String,
+    response_data: ResponseData,
 }
 
 impl ServirtiumConfiguration {
@@ -29,12 +138,87 @@ impl ServirtiumConfiguration {
             fail_if_markdown_changed: false,
             interaction_manager: interaction_manager.into(),
             http_client: None,
+            default_http_client: default_http_client(
+                false,
+                (
+                    http_client::DEFAULT_EVENT_STREAM_MAX_EVENTS,
+                    http_client::DEFAULT_EVENT_STREAM_TIMEOUT,
+                ),
+            ),
             record_request_mutations: Vec::new(),
+            saved_request_mutations: Vec::new(),
             playback_response_mutations: Vec::new(),
             record_response_mutations: Vec::new(),
+            stubs: Vec::new(),
+            match_configuration: MatchConfiguration::new(),
+            no_match_behavior: NoMatchBehavior::default(),
+            playback_default_response: None,
+            expected_interactions: None,
+            expected_interaction_count: None,
+            expected_request_headers: Vec::new(),
+            dedupe_interactions: false,
+            danger_accept_invalid_certs: false,
+            allowed_path_prefixes: None,
+            allowed_record_methods: None,
+            record_host_header: true,
+            record_accept_encoding: None,
+            record_auth: None,
+            serve_ranges: false,
+            playback_reason_phrases: false,
+            playback_response_times: false,
+            redirect_policy: RedirectPolicy::default(),
+            #[cfg(feature = "json-schema")]
+            response_schemas: Vec::new(),
+            dump_raw: false,
+            require_playback_used: false,
+            require_exact_interaction_count: false,
+            rewrite_absolute_urls: false,
+            recorded_base_url: None,
+            server_request_timeout: None,
+            server_address: SocketAddr::from(([127, 0, 0, 1], 61417)),
+            session_timeout: None,
+            first_interaction_number: 0,
+            response_overrides: HashMap::new(),
+            event_stream_limits: (
+                http_client::DEFAULT_EVENT_STREAM_MAX_EVENTS,
+                http_client::DEFAULT_EVENT_STREAM_TIMEOUT,
+            ),
+            record_streaming_threshold: None,
+            non_utf8_body_policy: None,
+            strict_body_comparison: None,
+            on_record: None,
+            on_recording_changed: None,
+            on_playback_response: None,
+        }
+    }
+
+    /// Creates a configuration with the defaults registered with `set_global_defaults` applied.
+    /// The attribute macros use it before calling the configuration function of the test.
+    pub fn with_global_defaults(
+        mode: ServirtiumMode,
+        interaction_manager: Box<dyn InteractionManager + Send + Sync>,
+    ) -> Self {
+        let mut configuration = Self::new(mode, interaction_manager);
+
+        if let Some(global_defaults) = GLOBAL_DEFAULTS.read().unwrap().as_ref() {
+            global_defaults(&mut configuration);
         }
+
+        configuration
+    }
+
+    /// Registers a function that configures every test of the process, e.g. with the mutations
+    /// or the domain name all the tests share. The configuration function of a test runs after it
+    /// and can override the defaults. Replaces the previously registered function.
+    pub fn set_global_defaults<F: Fn(&mut ServirtiumConfiguration) + Send + Sync + 'static>(
+        global_defaults: F,
+    ) {
+        *GLOBAL_DEFAULTS.write().unwrap() = Some(Box::new(global_defaults));
     }
 
+    /// Fails record tests whose interactions differ from the previous recording. The previous
+    /// recording is kept when the test fails, so it isn't replaced without a review. Recording
+    /// again without this option saves the new interactions.
     pub fn set_fail_if_markdown_changed(&mut self, value: bool) {
         self.fail_if_markdown_changed = value;
     }
@@ -59,16 +243,472 @@ impl ServirtiumConfiguration {
         self.interaction_manager.clone()
     }
 
+    pub fn set_interaction_manager(
+        &mut self,
+        interaction_manager: Box<dyn InteractionManager + Send + Sync>,
+    ) {
+        self.interaction_manager = interaction_manager.into();
+    }
+
+    pub fn set_match_configuration(&mut self, match_configuration: MatchConfiguration) {
+        self.match_configuration = match_configuration;
+    }
+
+    pub fn match_configuration(&self) -> &MatchConfiguration {
+        &self.match_configuration
+    }
+
+    pub fn match_configuration_mut(&mut self) -> &mut MatchConfiguration {
+        &mut self.match_configuration
+    }
+
+    pub fn set_no_match_behavior(&mut self, no_match_behavior: NoMatchBehavior) {
+        self.no_match_behavior = no_match_behavior;
+    }
+
+    pub fn no_match_behavior(&self) -> NoMatchBehavior {
+        self.no_match_behavior
+    }
+
+    /// Answers the playback requests that no interaction matches with this response, e.g. a `404`
+    /// with a JSON error body, so the test can check how the client handles it. It takes
+    /// precedence over the [`NoMatchBehavior`], which only applies without a default response.
+    /// Like stubs, it's sent as it is, without the playback response mutations.
+    pub fn set_playback_default_response(&mut self, response_data: ResponseData) {
+        self.playback_default_response = Some(response_data);
+    }
+
+    pub fn playback_default_response(&self) -> Option<&ResponseData> {
+        self.playback_default_response.as_ref()
+    }
+
+    /// Makes the record mode fail if the client doesn't make exactly these requests in this
+    /// order. Every request is given as `"METHOD URI"`, or just `"URI"` to accept any method.
+    pub fn expect_interactions<S: Into<String>, I: IntoIterator<Item = S>>(&mut self, requests: I) {
+        self.expected_interactions = Some(requests.into_iter().map(Into::into).collect());
+    }
+
+    pub fn expected_interactions(&self) -> Option<&[String]> {
+        self.expected_interactions.as_deref()
+    }
+
+    /// Makes the record mode fail if the client doesn't make exactly this number of requests.
+    pub fn expect_interaction_count(&mut self, count: usize) {
+        self.expected_interaction_count = Some(count);
+    }
+
+    pub fn expected_interaction_count(&self) -> Option<usize> {
+        self.expected_interaction_count
+    }
+
+    /// Makes the record mode fail if any of the saved requests lacks one of these headers, e.g. a
+    /// required `X-Api-Version`. The headers are compared case-insensitively after the saved
+    /// request mutations, so a header removed by them counts as missing while a masked one doesn't.
+    pub fn expect_request_headers<S: Into<String>, I: IntoIterator<Item = S>>(
+        &mut self,
+        headers: I,
+    ) {
+        self.expected_request_headers
+            .extend(headers.into_iter().map(Into::into));
+    }
+
+    pub fn expected_request_headers(&self) -> &[String] {
+        &self.expected_request_headers
+    }
+
+    /// Records only the first of the identical requests (same method, URI and body) and replays
+    /// it for all of them, which keeps the markdown small for clients that poll.
+    pub fn set_dedupe_interactions(&mut self, value: bool) {
+        self.dedupe_interactions = value;
+    }
+
+    pub fn dedupe_interactions(&self) -> bool {
+        self.dedupe_interactions
+    }
+
+    /// The `host` header sent to the destination API is saved with the request by default. It can
+    /// be left out of the markdown, it is still sent to the destination API either way.
+    pub fn set_record_host_header(&mut self, value: bool) {
+        self.record_host_header = value;
+    }
+
+    pub fn record_host_header(&self) -> bool {
+        self.record_host_header
+    }
+
+    /// Replaces the `Accept-Encoding` header of the requests forwarded to the destination API,
+    /// e.g. with `identity`, so the recorded responses don't depend on the encodings the client
+    /// happens to accept. The header is left as it is by default.
+    pub fn set_record_accept_encoding(&mut self, accept_encoding: Option<String>) {
+        self.record_accept_encoding = accept_encoding;
+    }
+
+    pub fn record_accept_encoding(&self) -> Option<&str> {
+        self.record_accept_encoding.as_deref()
+    }
+
+    /// Sends this `Authorization` header, e.g. `Bearer <token>`, with the requests forwarded to
+    /// the destination API in record mode, for credentials the client under test doesn't have. The
+    /// saved requests keep the `Authorization` header the client sent, if any, instead.
+    pub fn set_record_auth<S: Into<String>>(&mut self, authorization: S) {
+        self.record_auth = Some(authorization.into());
+    }
+
+    pub fn record_auth(&self) -> Option<&str> {
+        self.record_auth.as_deref()
+    }
+
+    /// Sets whether the redirects of the destination API are followed in record mode. By default
+    /// they are recorded as they are. When one is followed, the final response is recorded for the
+    /// original request, and the `Authorization` and `Cookie` headers aren't sent to other origins.
+    pub fn set_redirect_policy(&mut self, redirect_policy: RedirectPolicy) {
+        self.redirect_policy = redirect_policy;
+    }
+
+    pub fn redirect_policy(&self) -> RedirectPolicy {
+        self.redirect_policy
+    }
+
+    /// Fails playback tests that didn't replay any recorded interaction, e.g. because the client
+    /// served every request from a cache, which would make the recording meaningless.
+    pub fn set_require_playback_used(&mut self, value: bool) {
+        self.require_playback_used = value;
+    }
+
+    pub fn require_playback_used(&self) -> bool {
+        self.require_playback_used
+    }
+
+    /// Fails playback tests unless the client made exactly one request per recorded interaction,
+    /// which catches both the interactions that are no longer needed and the requests the client
+    /// stopped making. Stubs aren't counted, and it doesn't suit deduplicated recordings or path
+    /// patterns, whose interactions answer several requests.
+    pub fn set_require_exact_interaction_count(&mut self, value: bool) {
+        self.require_exact_interaction_count = value;
+    }
+
+    pub fn require_exact_interaction_count(&self) -> bool {
+        self.require_exact_interaction_count
+    }
+
+    /// Rewrites the absolute URLs of the domain name in the response bodies sent to the client to
+    /// point at the Servirtium server, e.g. pagination links, so the client doesn't leave the
+    /// test. The URLs of the Servirtium server in the forwarded request bodies are rewritten back.
+    /// The markdown keeps the URLs of the domain name, which therefore has to be configured for
+    /// playback as well.
+    pub fn set_rewrite_absolute_urls(&mut self, value: bool) {
+        self.rewrite_absolute_urls = value;
+    }
+
+    pub fn rewrite_absolute_urls(&self) -> bool {
+        self.rewrite_absolute_urls
+    }
+
+    /// Saves the recorded request URIs as absolute URLs with the given base, e.g. a staging host,
+    /// so the fixture shows where the requests could be replayed outside of Servirtium. The
+    /// requests are still forwarded to the domain name, and the base is ignored when matching the
+    /// requests in playback mode.
+    pub fn set_recorded_base_url<S: Into<String>>(&mut self, base_url: S) {
+        self.recorded_base_url = Some(base_url.into());
+    }
+
+    pub fn recorded_base_url(&self) -> Option<&str> {
+        self.recorded_base_url.as_deref()
+    }
+
+    /// Fails the test if a client takes longer than `timeout` to send the body of a request to the
+    /// Servirtium server, instead of waiting for it forever. There is no limit by default.
+    pub fn set_server_request_timeout(&mut self, timeout: Duration) {
+        self.server_request_timeout = Some(timeout);
+    }
+
+    pub fn server_request_timeout(&self) -> Option<Duration> {
+        self.server_request_timeout
+    }
+
+    /// The address the Servirtium server listens on, `127.0.0.1:61417` by default. There is a
+    /// single server per process which is started by the first test, so the tests that run in the
+    /// same process fail with [`Error::ServerAddressConflict`](crate::Error) if they configure
+    /// another address. With the port 0 the OS picks a free one, which [`crate::start`] returns.
+    pub fn set_server_address<A: Into<SocketAddr>>(&mut self, address: A) {
+        self.server_address = address.into();
+    }
+
+    /// Changes the port of the server address and keeps its IP, e.g. `0` to let the OS pick a
+    /// free port, see [`set_server_address`](Self::set_server_address).
+    pub fn set_port(&mut self, port: u16) {
+        self.server_address.set_port(port);
+    }
+
+    pub fn server_address(&self) -> SocketAddr {
+        self.server_address
+    }
+
+    /// Limits how long `TestSession::before_test` and `after_test` wait for the previous test and
+    /// for the server, so a deadlock fails the test with `Error::ProbableDeadlock` instead of
+    /// hanging forever. The tests run one at a time, so the timeout has to cover the tests that
+    /// wait to run before this one. There is no limit by default.
+    pub fn set_session_timeout(&mut self, timeout: Duration) {
+        self.session_timeout = Some(timeout);
+    }
+
+    pub fn session_timeout(&self) -> Option<Duration> {
+        self.session_timeout
+    }
+
+    /// The number of the first interaction recorded by the test, the following ones are numbered
+    /// consecutively. It's useful to record a part of a fixture that is assembled by hand. The
+    /// `on_record` callback can also assign any number to an interaction.
+    pub fn set_first_interaction_number(&mut self, interaction_number: usize) {
+        self.first_interaction_number = interaction_number;
+    }
+
+    pub fn first_interaction_number(&self) -> usize {
+        self.first_interaction_number
+    }
+
+    /// Answers playback requests with a `Range: bytes=...` header with the requested part of the
+    /// recorded response and `206 Partial Content`, if the recorded response has the full body.
+    pub fn set_serve_ranges(&mut self, value: bool) {
+        self.serve_ranges = value;
+    }
+
+    pub fn serve_ranges(&self) -> bool {
+        self.serve_ranges
+    }
+
+    /// Sends the recorded reason phrase of the responses in playback mode, e.g.
+    /// `HTTP/1.1 200 Not Quite OK`, instead of the canonical one for the status code. Only the
+    /// phrases that differ from the canonical ones are recorded, in the markdown heading of the
+    /// response body, so they can't contain `:` or `)`.
+    pub fn set_playback_reason_phrases(&mut self, value: bool) {
+        self.playback_reason_phrases = value;
+    }
+
+    pub fn playback_reason_phrases(&self) -> bool {
+        self.playback_reason_phrases
+    }
+
+    /// Waits for the recorded response time of each interaction before sending its response in
+    /// playback mode, so a slow endpoint is as slow as when it was recorded. The response times
+    /// are recorded with [`MarkdownInteractionManager::with_response_time`] or can be written by
+    /// hand below the interaction heading, e.g. `Response time: 2000 ms`, and the interactions
+    /// without one are answered right away. [`PlaybackHandle`](crate::PlaybackHandle) never waits.
+    ///
+    /// [`MarkdownInteractionManager::with_response_time`]: crate::MarkdownInteractionManager::with_response_time
+    pub fn set_playback_response_times(&mut self, value: bool) {
+        self.playback_response_times = value;
+    }
+
+    pub fn playback_response_times(&self) -> bool {
+        self.playback_response_times
+    }
+
+    /// Validates the bodies of the responses to the requests whose URI matches the pattern
+    /// against a JSON schema, and fails the test if one doesn't conform or isn't JSON. The
+    /// responses of the destination API are validated before the record response mutations, and
+    /// the recorded ones before the playback response mutations, so both a change of the API and
+    /// a broken recording are caught. It can be called for several patterns, and a response is
+    /// validated against the schemas of all the patterns its URI matches. The schema is compiled
+    /// once, here, with its `format` assertions enabled, and an invalid one is an error.
+    #[cfg(feature = "json-schema")]
+    pub fn set_response_schema(
+        &mut self,
+        uri_pattern: Regex,
+        schema: &serde_json::Value,
+    ) -> Result<(), crate::Error> {
+        let validator = jsonschema::options()
+            .should_validate_formats(true)
+            .build(schema)
+            .map_err(|e| crate::Error::InvalidResponseSchema(e.to_string()))?;
+        self.response_schemas.push((uri_pattern, validator));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "json-schema")]
+    pub fn response_schemas<'a>(
+        &'a self,
+        uri: &'a str,
+    ) -> impl Iterator<Item = &'a jsonschema::Validator> + 'a {
+        self.response_schemas
+            .iter()
+            .filter(move |(uri_pattern, _)| uri_pattern.is_match(uri))
+            .map(|(_, schema)| schema)
+    }
+
+    /// Only forwards the requests whose path starts with one of the prefixes in record mode, the
+    /// rest fail the test instead of being recorded.
+    pub fn set_allowed_path_prefixes<S: Into<String>, I: IntoIterator<Item = S>>(
+        &mut self,
+        prefixes: I,
+    ) {
+        self.allowed_path_prefixes = Some(prefixes.into_iter().map(Into::into).collect());
+    }
+
+    pub fn allowed_path_prefixes(&self) -> Option<&[String]> {
+        self.allowed_path_prefixes.as_deref()
+    }
+
+    pub fn is_path_allowed(&self, uri: &str) -> bool {
+        let path = uri.split('?').next().unwrap_or_default();
+
+        match &self.allowed_path_prefixes {
+            Some(prefixes) => prefixes
+                .iter()
+                .any(|prefix| path.starts_with(prefix.as_str())),
+            None => true,
+        }
+    }
+
+    /// Only forwards the requests with one of the methods in record mode, e.g. `["GET", "HEAD"]`
+    /// to make sure a test never changes data of the destination API. The rest fail the test.
+    pub fn set_allowed_record_methods<S: Into<String>, I: IntoIterator<Item = S>>(
+        &mut self,
+        methods: I,
+    ) {
+        self.allowed_record_methods = Some(methods.into_iter().map(Into::into).collect());
+    }
+
+    pub fn allowed_record_methods(&self) -> Option<&[String]> {
+        self.allowed_record_methods.as_deref()
+    }
+
+    pub fn is_record_method_allowed(&self, method: &str) -> bool {
+        match &self.allowed_record_methods {
+            Some(methods) => methods
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(method)),
+            None => true,
+        }
+    }
+
     pub fn http_client(&self) -> Arc<dyn HttpClient + Send + Sync> {
         self.http_client
             .clone()
-            .unwrap_or_else(|| Arc::new(ReqwestHttpClient::new()))
+            .unwrap_or_else(|| self.default_http_client.clone())
+    }
+
+    /// Limits how much of a `text/event-stream` response the default HTTP client records, because
+    /// such responses usually never end. The body is read until it contains `max_events` events or
+    /// until `timeout` has passed, 100 events and 5 seconds by default.
+    pub fn set_record_event_stream_limits(&mut self, max_events: usize, timeout: Duration) {
+        self.event_stream_limits = (max_events, timeout);
+        self.default_http_client =
+            default_http_client(self.danger_accept_invalid_certs, self.event_stream_limits);
+    }
+
+    /// Compares the bodies byte for byte when the interaction manager checks whether the data has
+    /// changed, instead of ignoring the leading and trailing whitespace and the line endings. It
+    /// takes precedence over the setting of the interaction manager, e.g.
+    /// `MarkdownInteractionManager::with_strict_body_comparison`.
+    pub fn set_strict_body_comparison(&mut self, strict_body_comparison: bool) {
+        self.strict_body_comparison = Some(strict_body_comparison);
+    }
+
+    pub fn strict_body_comparison(&self) -> Option<bool> {
+        self.strict_body_comparison
+    }
+
+    /// Sets what happens when a body of the interactions isn't valid UTF-8, e.g. in a fixture edited
+    /// by hand, for the interaction managers that read text. It takes precedence over the policy
+    /// of the interaction manager, e.g.
+    /// `MarkdownInteractionManager::with_non_utf8_body_policy`.
+    pub fn set_non_utf8_body_policy(&mut self, non_utf8_body_policy: NonUtf8BodyPolicy) {
+        self.non_utf8_body_policy = Some(non_utf8_body_policy);
+    }
+
+    pub fn non_utf8_body_policy(&self) -> Option<NonUtf8BodyPolicy> {
+        self.non_utf8_body_policy
+    }
+
+    /// Also saves the request and response bodies as they were received, before they were decoded
+    /// as UTF-8, using `InteractionManager::save_raw_bodies`. It's a debugging aid for encoding
+    /// issues and doesn't change the playback.
+    pub fn set_dump_raw(&mut self, value: bool) {
+        self.dump_raw = value;
+    }
+
+    pub fn dump_raw(&self) -> bool {
+        self.dump_raw
+    }
+
+    /// Makes the default HTTP client accept any certificate of the destination API in record
+    /// mode, e.g. a self-signed one. It has no effect on a client set with `set_http_client`.
+    pub fn set_danger_accept_invalid_certs(&mut self, value: bool) {
+        self.danger_accept_invalid_certs = value;
+        self.default_http_client =
+            default_http_client(self.danger_accept_invalid_certs, self.event_stream_limits);
+    }
+
+    pub fn danger_accept_invalid_certs(&self) -> bool {
+        self.danger_accept_invalid_certs
     }
 
     pub fn set_http_client(&mut self, http_client: Arc<dyn HttpClient + Send + Sync>) {
         self.http_client = Some(http_client);
     }
 
+    pub fn set_blocking_http_client<C: BlockingHttpClient + Send + Sync + 'static>(
+        &mut self,
+        http_client: C,
+    ) {
+        self.set_http_client(Arc::new(BlockingHttpClientAdapter::new(http_client)));
+    }
+
+    /// Response bodies larger than the threshold are streamed into the writer provided by the
+    /// interaction manager instead of being buffered, if the manager supports it, e.g.
+    /// `MarkdownInteractionManager` writes them to files linked from the markdown. The client
+    /// under test receives an empty body for such responses while recording.
+    pub fn set_record_streaming_threshold(&mut self, threshold: Option<usize>) {
+        self.record_streaming_threshold = threshold;
+    }
+
+    pub fn record_streaming_threshold(&self) -> Option<usize> {
+        self.record_streaming_threshold
+    }
+
+    /// Sets a callback that is called with every recorded interaction after the record mutations
+    /// have been applied and before the interaction is saved.
+    pub fn set_on_record(&mut self, callback: Box<dyn Fn(&mut InteractionData) + Send + Sync>) {
+        self.on_record = Some(Callback(callback));
+    }
+
+    pub fn on_record(&self) -> Option<&OnRecord> {
+        self.on_record.as_ref().map(|callback| &*callback.0)
+    }
+
+    /// Sets a callback that is called with a summary of the differences when a test is recorded
+    /// again and its interactions differ from the previous recording. By default the summary is
+    /// printed to stderr. It isn't called if `fail_if_markdown_changed` is set, because the test
+    /// fails with the summary instead.
+    pub fn set_on_recording_changed(&mut self, callback: Box<dyn Fn(&str) + Send + Sync>) {
+        self.on_recording_changed = Some(Callback(callback));
+    }
+
+    pub fn on_recording_changed(&self) -> Option<&OnRecordingChanged> {
+        self.on_recording_changed
+            .as_ref()
+            .map(|callback| &*callback.0)
+    }
+
+    /// Sets a callback that can change a played back response right before it's sent, after the
+    /// mutations, the overrides, the ranges and the removal of the body of `HEAD` responses, e.g.
+    /// to add a header computed from the final body. The `Content-Length` header isn't updated
+    /// after the callback. Stubs aren't passed to it.
+    pub fn set_on_playback_response(
+        &mut self,
+        callback: Box<dyn Fn(&mut ResponseData) + Send + Sync>,
+    ) {
+        self.on_playback_response = Some(Callback(callback));
+    }
+
+    pub fn on_playback_response(&self) -> Option<&OnPlaybackResponse> {
+        self.on_playback_response
+            .as_ref()
+            .map(|callback| &*callback.0)
+    }
+
     pub fn add_record_request_mutations<
         F: FnOnce(&mut MutationsBuilder) -> &mut MutationsBuilder,
     >(
@@ -93,6 +733,98 @@ impl ServirtiumConfiguration {
             .extend(mutations.into_response_mutations());
     }
 
+    /// Unlike the record request mutations these are applied after the request has been forwarded
+    /// to the destination API, so they only change what is saved.
+    pub fn add_saved_request_mutations<
+        F: FnOnce(&mut MutationsBuilder) -> &mut MutationsBuilder,
+    >(
+        &mut self,
+        func: F,
+    ) {
+        let mut mutations = MutationsBuilder::new();
+        let _ = func(&mut mutations);
+        self.saved_request_mutations
+            .extend(mutations.into_request_mutations());
+    }
+
+    pub fn add_full_record_request_mutation<M: FullRequestMutation + Send + Sync + 'static>(
+        &mut self,
+        mutation: M,
+    ) {
+        self.record_request_mutations
+            .push(RequestMutation::from_full(mutation));
+    }
+
+    pub fn add_full_saved_request_mutation<M: FullRequestMutation + Send + Sync + 'static>(
+        &mut self,
+        mutation: M,
+    ) {
+        self.saved_request_mutations
+            .push(RequestMutation::from_full(mutation));
+    }
+
+    pub fn add_full_record_response_mutation<M: FullResponseMutation + Send + Sync + 'static>(
+        &mut self,
+        mutation: M,
+    ) {
+        self.record_response_mutations
+            .push(ResponseMutation::from_full(mutation));
+    }
+
+    pub fn add_full_playback_response_mutation<M: FullResponseMutation + Send + Sync + 'static>(
+        &mut self,
+        mutation: M,
+    ) {
+        self.playback_response_mutations
+            .push(ResponseMutation::from_full(mutation));
+    }
+
+    /// Replaces the credentials of `Authorization: Basic` headers with a placeholder in the saved
+    /// requests while still sending the real ones to the destination API. On playback the request
+    /// headers are only matched if the recorded response names them in `Vary`, see
+    /// `MatchConfiguration::set_match_vary_headers`. A response that varies on `Authorization`
+    /// only matches requests with the placeholder then, so remove `Authorization` from its `Vary`
+    /// header with a record response mutation or disable the `Vary` matching.
+    pub fn scrub_basic_auth(&mut self) {
+        self.add_saved_request_mutations(|builder| builder.scrub_basic_auth("REDACTED"));
+    }
+
+    /// Removes volatile headers like `Date` from the saved requests, the recorded responses and
+    /// the played back responses, without changing the requests forwarded to the destination API.
+    pub fn scrub_headers<S: Into<String>, I: IntoIterator<Item = S>>(&mut self, headers: I) {
+        let headers = headers.into_iter().map(Into::into).collect::<Vec<String>>();
+
+        self.add_saved_request_mutations(|builder| builder.remove_headers(headers.clone()));
+        self.add_record_response_mutations(|builder| builder.remove_headers(headers.clone()));
+        self.add_playback_response_mutations(|builder| builder.remove_headers(headers));
+    }
+
+    /// Scrubs the headers that change with every response and make the recordings churn, the way
+    /// `scrub_headers` does: `Date`, `Age`, `Set-Cookie`, the request IDs of common servers and
+    /// AWS, the CDN headers like `CF-Ray` and `X-Cache`, and the tracing headers. Other headers
+    /// can still be scrubbed with `scrub_headers` or the mutations.
+    pub fn scrub_common_volatile_headers(&mut self) {
+        let patterns = || COMMON_VOLATILE_HEADER_PATTERNS.iter().cloned();
+
+        self.scrub_headers(COMMON_VOLATILE_HEADERS.iter().copied());
+        self.add_saved_request_mutations(|builder| builder.remove_headers_regex(patterns()));
+        self.add_record_response_mutations(|builder| builder.remove_headers_regex(patterns()));
+        self.add_playback_response_mutations(|builder| builder.remove_headers_regex(patterns()));
+    }
+
+    /// Applies the same mutations to both the recorded requests and the recorded responses.
+    pub fn add_record_mutations<F: FnOnce(&mut MutationsBuilder) -> &mut MutationsBuilder>(
+        &mut self,
+        func: F,
+    ) {
+        let mut mutations = MutationsBuilder::new();
+        let _ = func(&mut mutations);
+        self.record_request_mutations
+            .extend(mutations.request_mutations());
+        self.record_response_mutations
+            .extend(mutations.into_response_mutations());
+    }
+
     pub fn add_playback_response_mutations<
         F: FnOnce(&mut MutationsBuilder) -> &mut MutationsBuilder,
     >(
@@ -109,6 +841,10 @@ impl ServirtiumConfiguration {
         &self.record_request_mutations
     }
 
+    pub fn saved_request_mutations(&self) -> &[RequestMutation] {
+        &self.saved_request_mutations
+    }
+
     pub fn record_response_mutations(&self) -> &[ResponseMutation] {
         &self.record_response_mutations
     }
@@ -116,4 +852,60 @@ impl ServirtiumConfiguration {
     pub fn playback_response_mutations(&self) -> &[ResponseMutation] {
         &self.playback_response_mutations
     }
+
+    /// Registers a canned response for the given method and URI. Stubs are served in both record
+    /// and playback modes before falling through to the markdown or the destination API, and they
+    /// are never recorded.
+    pub fn add_stub<S1: Into<String>, S2: Into<String>>(
+        &mut self,
+        method: S1,
+        uri: S2,
+        response_data: ResponseData,
+    ) {
+        self.stubs.push(Stub {
+            method: method.into().to_uppercase(),
+            uri: uri.into(),
+            response_data,
+        });
+    }
+
+    pub fn stub(&self, method: &str, uri: &str) -> Option<&ResponseData> {
+        self.stubs
+            .iter()
+            .find(|stub| stub.method.eq_ignore_ascii_case(method) && stub.uri == uri)
+            .map(|stub| &stub.response_data)
+    }
+
+    /// Replays the interaction with the given number with another status code, so the same
+    /// markdown can drive the error handling tests. Only used in playback mode.
+    pub fn override_response_status(&mut self, interaction_number: usize, status_code: u16) {
+        self.response_overrides
+            .entry(interaction_number)
+            .or_default()
+            .status_code = Some(status_code);
+    }
+
+    /// Replays the interaction with the given number with another body. Only used in playback
+    /// mode.
+    pub fn override_response_body<S: Into<String>>(&mut self, interaction_number: usize, body: S) {
+        self.response_overrides
+            .entry(interaction_number)
+            .or_default()
+            .body = Some(body.into());
+    }
+
+    pub(crate) fn response_override(&self, interaction_number: usize) -> Option<&ResponseOverride> {
+        self.response_overrides.get(&interaction_number)
+    }
+}
+
+fn default_http_client(
+    accept_invalid_certs: bool,
+    (event_stream_max_events, event_stream_timeout): (usize, Duration),
+) -> Arc<dyn HttpClient + Send + Sync> {
+    Arc::new(
+        ReqwestHttpClient::new()
+            .with_danger_accept_invalid_certs(accept_invalid_certs)
+            .with_event_stream_limits(event_stream_max_events, event_stream_timeout),
+    )
 }
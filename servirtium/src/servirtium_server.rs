@@ -1,16 +1,16 @@
 use crate::{
-    error::Error, servirtium_configuration::ServirtiumConfiguration, InteractionData, RequestData,
-    ResponseData,
-};
-use hyper::{
-    header::{HeaderName, HeaderValue},
-    HeaderMap, Response, Uri,
+    error::Error, multipart, mutations::BodyMutation, range, redirect, runner,
+    servirtium_configuration::ServirtiumConfiguration, util, InteractionData, MatchConfiguration,
+    NoMatchBehavior, RequestData, ResponseData, UrlRewriteMutation,
 };
+use hyper::{Response, Uri};
 use lazy_static::lazy_static;
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::HashSet,
     sync::{self, Mutex},
     thread,
+    time::{Duration, Instant},
 };
 use sync::{Arc, Condvar};
 use thread::JoinHandle;
@@ -33,18 +33,29 @@ pub struct ServirtiumServer {
     error: Option<Error>,
     pub(crate) interactions: Vec<InteractionData>,
     markdown_data: Option<Vec<InteractionData>>,
-    interaction_number: u8,
+    used_interactions: HashSet<usize>,
+    /// The number of requests answered in playback mode, other than by stubs.
+    playback_request_count: usize,
+    /// How long to wait before sending the last playback response, see
+    /// [`ServirtiumConfiguration::set_playback_response_times`].
+    pub(crate) playback_delay: Option<Duration>,
+    recorded_interaction_count: usize,
+    pub(crate) recording_paused: bool,
 }
 
 impl ServirtiumServer {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         ServirtiumServer {
             configuration: None,
             join_handle: None,
             error: None,
             interactions: Vec::new(),
             markdown_data: None,
-            interaction_number: 0,
+            used_interactions: HashSet::new(),
+            playback_request_count: 0,
+            playback_delay: None,
+            recorded_interaction_count: 0,
+            recording_paused: false,
         }
     }
 
@@ -60,6 +71,24 @@ impl ServirtiumServer {
         instance
     }
 
+    /// Like [`instance`](Self::instance), but gives up and returns `None` if the instance isn't
+    /// released within `timeout`.
+    pub(crate) fn instance_within(timeout: Duration) -> Option<Self> {
+        let (mutex, condvar) = &*SERVIRTIUM_INSTANCE.clone();
+        let (mut mutex, wait) = condvar
+            .wait_timeout_while(mutex.lock().unwrap(), timeout, |option| option.is_none())
+            .unwrap();
+
+        if wait.timed_out() {
+            return None;
+        }
+
+        let instance = mutex.take().unwrap();
+        condvar.notify_one();
+
+        Some(instance)
+    }
+
     pub(crate) fn release_instance(self) {
         let (mutex, condvar) = &*SERVIRTIUM_INSTANCE.clone();
         *mutex.lock().unwrap() = Some(self);
@@ -69,28 +98,108 @@ impl ServirtiumServer {
     pub(crate) async fn handle_request(
         &mut self,
         request: RequestData,
+        raw_request_body: &[u8],
     ) -> Result<ResponseData, Error> {
-        match self.configuration.as_ref().unwrap().interaction_mode() {
-            ServirtiumMode::Playback => self.handle_playback(),
-            ServirtiumMode::Record => self.handle_record(request).await,
+        let config = self.configuration.as_ref().unwrap();
+
+        if let Some(stub_response) = config.stub(&request.method, &request.uri) {
+            return Ok(stub_response.clone());
         }
+
+        match config.interaction_mode() {
+            ServirtiumMode::Playback => self.handle_playback(request),
+            ServirtiumMode::Record => self.handle_record(request, raw_request_body).await,
+        }
+    }
+
+    /// Answers a request with a stub or a recorded interaction, without going through hyper, see
+    /// [`PlaybackHandle`](crate::PlaybackHandle).
+    pub(crate) fn playback_response(
+        &mut self,
+        request: RequestData,
+    ) -> Result<ResponseData, Error> {
+        let config = self.configuration.as_ref().ok_or(Error::NotConfigured)?;
+
+        if let Some(stub_response) = config.stub(&request.method, &request.uri) {
+            return Ok(stub_response.clone());
+        }
+
+        self.handle_playback(request)
     }
 
-    fn handle_playback(&mut self) -> Result<ResponseData, Error> {
+    fn handle_playback(&mut self, mut request: RequestData) -> Result<ResponseData, Error> {
+        self.playback_request_count += 1;
+        // the boundary is normalized like the recorded one, so the bodies can be matched
+        multipart::normalize_boundary(&mut request);
         let config = self.configuration.as_mut().unwrap();
         let interaction_manager = config.interaction_manager().clone();
 
         if self.markdown_data.is_none() {
-            self.markdown_data = Some(
-                interaction_manager
-                    .load_interactions()
-                    .map_err(|e| Error::InteractionManager(e))?,
-            );
-        } else {
-            self.interaction_number += 1;
+            self.markdown_data = Some(interaction_manager.load_interactions()?);
         }
 
-        let playback_data = &self.markdown_data.as_ref().unwrap()[self.interaction_number as usize];
+        // replay the first recorded interaction that matches and hasn't been replayed yet
+        let match_configuration = config.match_configuration();
+        let used_interactions = &self.used_interactions;
+        let interactions = self.markdown_data.as_ref().unwrap();
+        let find_match = |include_used: bool| {
+            let candidates = || {
+                interactions
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, interaction)| {
+                        (!used_interactions.contains(index)
+                            || (include_used && Self::is_reusable(config, interaction, &request)))
+                            && match_configuration.matches_interaction(interaction, &request)
+                    })
+            };
+
+            // a recorded 304 is only the right answer for a matching conditional request, but it's
+            // still replayed if nothing else has been recorded for the request
+            candidates()
+                .find(|(_, interaction)| {
+                    MatchConfiguration::conditional_headers_match(interaction, &request)
+                })
+                .or_else(|| candidates().next())
+        };
+
+        let found = find_match(false).or_else(|| find_match(true));
+
+        if found.is_none() {
+            if let Some(default_response) = config.playback_default_response() {
+                return Ok(default_response.clone());
+            }
+        }
+
+        let (index, playback_data) = found.ok_or_else(|| match config.no_match_behavior() {
+            NoMatchBehavior::Error => Error::NoMatchingInteractionCandidates {
+                request: request.to_string(),
+                candidates: interactions
+                    .iter()
+                    .enumerate()
+                    .map(|(index, interaction)| {
+                        match_configuration.explain_match(
+                            interaction,
+                            &request,
+                            used_interactions.contains(&index),
+                        )
+                    })
+                    .collect(),
+            },
+            NoMatchBehavior::CloseConnection => Error::ConnectionClosed,
+        })?;
+        let head_with_get = match_configuration
+            .is_head_with_get(&playback_data.request_data.method, &request.method);
+        if !head_with_get {
+            self.used_interactions.insert(index);
+        }
+
+        #[cfg(feature = "json-schema")]
+        Self::validate_response_schemas(config, &request, &playback_data.response_data)?;
+
+        if config.playback_response_times() {
+            self.playback_delay = playback_data.response_time;
+        }
 
         let mut response_data = playback_data.response_data.clone();
 
@@ -99,96 +208,369 @@ impl ServirtiumServer {
             mutation.mutate(&mut response_data);
         }
 
-        let mut response_builder = Response::builder();
+        if config.rewrite_absolute_urls() {
+            if let Some(domain_name) = config.domain_name() {
+                UrlRewriteMutation::new(domain_name, Self::server_base_url(config, &request))
+                    .mutate(&mut response_data.body);
+            }
+        }
+
+        if let Some(response_override) = config.response_override(playback_data.interaction_number)
+        {
+            if let Some(status_code) = response_override.status_code {
+                response_data.status_code = status_code;
+                response_data.reason_phrase = None;
+            }
+            if let Some(body) = &response_override.body {
+                response_data.body = body.clone();
+            }
+        }
+
+        util::update_content_length(&mut response_data.headers, &response_data.body);
+
+        if config.serve_ranges() {
+            if let Some(range) = util::find_header(&request.headers, "range") {
+                range::apply_range(range, &mut response_data);
+            }
+        }
+
+        Self::set_body_length(&mut response_data);
+
+        if !config.playback_reason_phrases() {
+            response_data.reason_phrase = None;
+        }
+
+        // the headers of a `GET` answering a `HEAD` request still describe the full body
+        if request.method.eq_ignore_ascii_case("HEAD") {
+            response_data.body.clear();
+        }
 
-        if let Some(headers_mut) = response_builder.headers_mut() {
-            Self::put_headers(headers_mut, Self::filter_headers(&response_data.headers))?;
+        if let Some(on_playback_response) = config.on_playback_response() {
+            on_playback_response(&mut response_data);
         }
 
         Ok(response_data)
     }
 
+    /// Whether an interaction can be replayed again, because deduplicated recordings keep a single
+    /// interaction for all the identical requests, a pattern answers a family of requests and a
+    /// `HEAD` request only needs the headers of a `GET` interaction.
+    fn is_reusable(
+        config: &ServirtiumConfiguration,
+        interaction: &InteractionData,
+        request: &RequestData,
+    ) -> bool {
+        let match_configuration = config.match_configuration();
+
+        config.dedupe_interactions()
+            || match_configuration.is_path_pattern(&interaction.request_data.uri)
+            || match_configuration
+                .is_head_with_get(&interaction.request_data.method, &request.method)
+    }
+
     async fn handle_record(
         &mut self,
         mut request_data: RequestData,
+        raw_request_body: &[u8],
     ) -> Result<ResponseData, Error> {
         let config = self.configuration.as_mut().unwrap();
 
+        if !config.is_path_allowed(&request_data.uri) {
+            return Err(Error::PathNotAllowed(request_data.uri));
+        }
+
+        if !config.is_record_method_allowed(&request_data.method) {
+            return Err(Error::MethodNotAllowed(request_data.to_string()));
+        }
+
         let http_client = config.http_client();
+        // the host header is replaced with the one of the destination API
+        let server_base_url = Self::server_base_url(config, &request_data);
 
         Self::add_host_header(&mut request_data, config)?;
+        // hyper has already answered the expectation with `100 Continue` to read the whole body,
+        // so it mustn't make the destination API wait for the body again
+        request_data.headers.remove("expect");
+        if let Some(accept_encoding) = config.record_accept_encoding() {
+            request_data
+                .headers
+                .retain(|name, _| !name.eq_ignore_ascii_case("accept-encoding"));
+            request_data.headers.insert(
+                String::from("accept-encoding"),
+                String::from(accept_encoding),
+            );
+        }
+
+        // the header the client sent, if any, is put back before the request is saved
+        let client_authorization = match config.record_auth() {
+            Some(authorization) => {
+                let client_authorization =
+                    util::remove_header(&mut request_data.headers, "authorization");
+                request_data
+                    .headers
+                    .insert(String::from("authorization"), String::from(authorization));
+                Some(client_authorization)
+            }
+            None => None,
+        };
 
         // Mutate the request according to the configuration
         for mutation in config.record_request_mutations() {
             mutation.mutate(&mut request_data);
         }
 
-        let mut response_data = http_client
-            .make_request(
-                config.domain_name().ok_or(Error::NotConfigured)?,
-                &request_data,
-            )
-            .await?;
+        let domain_name = config.domain_name().ok_or(Error::NotConfigured)?;
+        if config.rewrite_absolute_urls() {
+            UrlRewriteMutation::new(&server_base_url, domain_name).mutate(&mut request_data.body);
+            util::update_content_length(&mut request_data.headers, &request_data.body);
+        }
+        let interaction_number =
+            config.first_interaction_number() + self.recorded_interaction_count;
+        // interactions aren't saved while the recording is paused, so nothing is written for them
+        let recording = !self.recording_paused;
+        let body_writer = match config.record_streaming_threshold() {
+            Some(threshold) if recording => config
+                .interaction_manager()
+                .response_body_writer(interaction_number)?
+                .map(|writer| (threshold, writer)),
+            _ => None,
+        };
+
+        let streaming = body_writer.is_some();
+        let start = Instant::now();
+        // streamed bodies are written as they were received anyway
+        let response = match body_writer {
+            Some((threshold, writer)) => {
+                let response_data = http_client
+                    .make_streaming_request(domain_name, &request_data, threshold, writer)
+                    .await?;
+                (response_data, None)
+            }
+            None if config.dump_raw() && recording => {
+                let (response_data, raw_response_body) = http_client
+                    .make_raw_request(domain_name, &request_data)
+                    .await?;
+                (response_data, Some(raw_response_body))
+            }
+            None => (
+                http_client.make_request(domain_name, &request_data).await?,
+                None,
+            ),
+        };
+        let (mut response_data, raw_response_body) = redirect::follow(
+            &*http_client,
+            config.redirect_policy(),
+            domain_name,
+            &request_data,
+            response,
+        )
+        .await?;
+        let response_time = start.elapsed();
+
+        if let Some(raw_response_body) = raw_response_body {
+            config.interaction_manager().save_raw_bodies(
+                interaction_number,
+                raw_request_body,
+                &raw_response_body,
+            )?;
+        }
+
+        // hyper skips interim responses like `100 Continue` (e.g. after `Expect: 100-continue`)
+        // and returns the final one, but a custom client could still return them
+        let status_code = response_data.status_code;
+        if (100..200).contains(&status_code) && status_code != 101 {
+            return Err(Error::InterimResponse(status_code));
+        }
+
+        #[cfg(feature = "json-schema")]
+        Self::validate_response_schemas(config, &request_data, &response_data)?;
 
         // Mutate the response according to the configuration to write it to markdown
         for mutation in config.record_response_mutations() {
             mutation.mutate(&mut response_data);
         }
 
-        let interaction_data = InteractionData {
-            interaction_number: self.interaction_number,
+        // the body may have been changed by the mutations or by the lossy UTF-8 conversion, but a
+        // streamed body isn't in the response data, so its length is kept
+        if !(streaming && response_data.body.is_empty()) {
+            util::update_content_length(&mut response_data.headers, &response_data.body);
+        }
+
+        // Mutate the request that has already been forwarded before writing it to markdown
+        multipart::normalize_boundary(&mut request_data);
+        if !config.record_host_header() {
+            request_data.headers.remove("host");
+        }
+        // the credentials of the record client never end up in the recording
+        if let Some(client_authorization) = client_authorization {
+            util::remove_header(&mut request_data.headers, "authorization");
+            if let Some((name, value)) = client_authorization {
+                request_data.headers.insert(name, value);
+            }
+        }
+        for mutation in config.saved_request_mutations() {
+            mutation.mutate(&mut request_data);
+        }
+        util::update_content_length(&mut request_data.headers, &request_data.body);
+        if let Some(recorded_base_url) = config.recorded_base_url() {
+            request_data.uri = util::join_url(recorded_base_url, &request_data.uri);
+        }
+
+        let mut interaction_data = InteractionData {
+            interaction_number,
             request_data,
             response_data,
+            response_time: Some(response_time),
         };
 
+        if let Some(on_record) = config.on_record() {
+            on_record(&mut interaction_data);
+        }
+
         let mut response_builder =
             Response::builder().status(interaction_data.response_data.status_code);
 
         if let Some(header_map) = response_builder.headers_mut() {
-            Self::put_headers(header_map, &interaction_data.response_data.headers)?;
+            util::put_headers(header_map, &interaction_data.response_data.headers)?;
         }
 
         let mut response_data = interaction_data.response_data.clone();
-        self.interactions.push(interaction_data);
+
+        let is_duplicate = config.dedupe_interactions()
+            && self.interactions.iter().any(|recorded| {
+                recorded.request_data.body == interaction_data.request_data.body
+                    && config
+                        .match_configuration()
+                        .matches(&recorded.request_data, &interaction_data.request_data)
+            });
+        if recording && !is_duplicate {
+            self.interactions.push(interaction_data);
+            self.recorded_interaction_count += 1;
+        }
 
         // Now mutate the actual response sent to the caller
         for mutation in config.playback_response_mutations() {
             mutation.mutate(&mut response_data);
         }
 
+        if config.rewrite_absolute_urls() {
+            UrlRewriteMutation::new(domain_name, server_base_url).mutate(&mut response_data.body);
+        }
+
+        util::update_content_length(&mut response_data.headers, &response_data.body);
+
         Ok(response_data)
     }
 
-    fn put_headers<'a, I: IntoIterator<Item = (&'a String, &'a String)>>(
-        header_map: &mut HeaderMap<HeaderValue>,
-        headers: I,
-    ) -> Result<(), Error> {
-        for (key, value) in headers {
-            let header_name = HeaderName::from_lowercase(key.to_lowercase().as_bytes())?;
-            let header_value = HeaderValue::from_str(value)?;
-            header_map.append(header_name, header_value);
+    /// Chunked framing isn't replayed, the whole body is sent at once, so the `Transfer-Encoding`
+    /// header of a chunked response is replaced with a `Content-Length` header. The responses that
+    /// had neither header, e.g. from HTTP/1.0 servers that close the connection after the body, get
+    /// one as well, since the whole body is known when it's played back.
+    fn set_body_length(response_data: &mut ResponseData) {
+        let is_chunked = util::find_header(&response_data.headers, "transfer-encoding")
+            .is_some_and(|value| value.to_ascii_lowercase().contains("chunked"));
+        if is_chunked {
+            response_data
+                .headers
+                .retain(|key, _| !key.eq_ignore_ascii_case("transfer-encoding"));
         }
 
-        Ok(())
+        // informational, `204 No Content` and `304 Not Modified` responses never have a body
+        let has_body = !matches!(response_data.status_code, 100..=199 | 204 | 304);
+        if has_body && util::content_length(&response_data.headers).is_none() {
+            response_data.headers.insert(
+                String::from("content-length"),
+                response_data.body.len().to_string(),
+            );
+        }
+    }
+
+    pub(crate) fn used_interaction_count(&self) -> usize {
+        self.used_interactions.len()
     }
 
-    fn filter_headers<'a>(
-        headers: &'a HashMap<String, String>,
-    ) -> impl Iterator<Item = (&'_ String, &'_ String)> + 'a {
-        headers
-            .iter()
-            // Transfer-Encoding: chunked shouldn't be included in local tests because all the data is
-            // written immediately and reqwest panics because of that
-            .filter(|(key, value)| *key != "Transfer-Encoding" || *value != "chunked")
+    /// Checks that the playback got a request per recorded interaction, see
+    /// [`ServirtiumConfiguration::set_require_exact_interaction_count`].
+    pub(crate) fn check_exact_interaction_count(&self) -> Result<(), Error> {
+        let config = self.configuration.as_ref().ok_or(Error::NotConfigured)?;
+        let recorded = match &self.markdown_data {
+            Some(markdown_data) => Cow::Borrowed(markdown_data),
+            None => Cow::Owned(config.interaction_manager().load_interactions()?),
+        };
+
+        if recorded.len() == self.playback_request_count
+            && self.used_interactions.len() == recorded.len()
+        {
+            return Ok(());
+        }
+
+        Err(Error::InteractionCountMismatch {
+            recorded: recorded.len(),
+            requested: self.playback_request_count,
+            unused: recorded
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !self.used_interactions.contains(index))
+                .map(|(_, interaction)| {
+                    format!(
+                        "{}: {} {}",
+                        interaction.interaction_number,
+                        interaction.request_data.method,
+                        interaction.request_data.uri
+                    )
+                })
+                .collect(),
+        })
     }
 
     pub(crate) fn reset(&mut self) {
         self.interactions.clear();
-        self.interaction_number = 0;
+        self.recorded_interaction_count = 0;
+        self.recording_paused = false;
         self.markdown_data = None;
+        self.used_interactions.clear();
+        self.playback_request_count = 0;
+        self.playback_delay = None;
         self.error = None;
     }
 
+    /// Fails if the body of the response doesn't conform to the schemas set for the URI of the
+    /// request, see [`ServirtiumConfiguration::set_response_schema`].
+    #[cfg(feature = "json-schema")]
+    fn validate_response_schemas(
+        config: &ServirtiumConfiguration,
+        request_data: &RequestData,
+        response_data: &ResponseData,
+    ) -> Result<(), Error> {
+        for schema in config.response_schemas(&request_data.uri) {
+            serde_json::from_str(&response_data.body)
+                .map_err(|e| format!("the body isn't JSON: {}", e))
+                .and_then(|body| {
+                    schema.validate(&body).map_err(|e| {
+                        let path = e.instance_path().to_string();
+                        format!("{}: {}", if path.is_empty() { "/" } else { &path }, e)
+                    })
+                })
+                .map_err(|reason| Error::ResponseSchemaViolation {
+                    request: format!("{} {}", request_data.method, request_data.uri),
+                    reason,
+                })?;
+        }
+
+        Ok(())
+    }
+
+    fn server_base_url(config: &ServirtiumConfiguration, request_data: &RequestData) -> String {
+        // HTTP/1.0 requests and the ones played back by a handle may not have a host header
+        match util::find_header(&request_data.headers, "host") {
+            Some(host) => format!("http://{}", host),
+            None => format!(
+                "http://{}",
+                runner::running_address().unwrap_or_else(|| config.server_address())
+            ),
+        }
+    }
+
     fn add_host_header(
         request_data: &mut RequestData,
         config: &ServirtiumConfiguration,
@@ -0,0 +1,93 @@
+use crate::{
+    error::Error, interaction_manager::InteractionManager, InteractionData,
+    MarkdownInteractionManager, ServirtiumConfiguration,
+};
+use std::{
+    fmt::{self, Debug, Formatter},
+    io::{self, Read, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+/// Reads the markdown from a reader and writes it to a writer instead of a file, e.g. stdin and
+/// stdout. The reader is read to the end the first time the interactions are loaded, and every
+/// save writes all the interactions to the writer again.
+pub struct StreamInteractionManager {
+    reader: Mutex<Option<Box<dyn Read + Send>>>,
+    markdown: Mutex<Option<String>>,
+    writer: Mutex<Box<dyn Write + Send>>,
+    format: MarkdownInteractionManager,
+}
+
+impl StreamInteractionManager {
+    pub fn new<R: Read + Send + 'static, W: Write + Send + 'static>(reader: R, writer: W) -> Self {
+        Self {
+            reader: Mutex::new(Some(Box::new(reader))),
+            markdown: Mutex::new(None),
+            writer: Mutex::new(Box::new(writer)),
+            format: MarkdownInteractionManager::new(PathBuf::new()),
+        }
+    }
+
+    pub fn from_reader<R: Read + Send + 'static>(reader: R) -> Self {
+        Self::new(reader, io::sink())
+    }
+
+    pub fn to_writer<W: Write + Send + 'static>(writer: W) -> Self {
+        Self::new(io::empty(), writer)
+    }
+}
+
+impl Debug for StreamInteractionManager {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "StreamInteractionManager")
+    }
+}
+
+impl InteractionManager for StreamInteractionManager {
+    fn load_interactions(
+        &self,
+    ) -> Result<Vec<InteractionData>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut markdown = self.markdown.lock().map_err(Error::from)?;
+
+        if markdown.is_none() {
+            let mut contents = String::new();
+            if let Some(mut reader) = self.reader.lock().map_err(Error::from)?.take() {
+                reader.read_to_string(&mut contents)?;
+            }
+            *markdown = Some(contents);
+        }
+
+        Ok(self.format.parse_markdown(markdown.as_ref().unwrap())?)
+    }
+
+    fn save_interactions(
+        &self,
+        interactions: &[InteractionData],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut writer = self.writer.lock().map_err(Error::from)?;
+        self.format.write_markdown(&mut *writer, interactions)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    fn check_data_unchanged(
+        &self,
+        interactions: &[InteractionData],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let stored_interactions = self.load_interactions()?;
+
+        MarkdownInteractionManager::compare_interactions(
+            &stored_interactions,
+            interactions,
+            self.format.strict_body_comparison(),
+        )?;
+
+        Ok(())
+    }
+
+    fn configure(&self, configuration: &ServirtiumConfiguration) {
+        self.format.configure(configuration);
+    }
+}
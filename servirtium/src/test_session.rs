@@ -1,21 +1,44 @@
-use crate::{error::Error, runner, ServirtiumConfiguration, ServirtiumMode, ServirtiumServer};
+use crate::{
+    error::Error, runner, util, InteractionData, InteractionManager, ServirtiumConfiguration,
+    ServirtiumMode, ServirtiumServer,
+};
 use lazy_static::lazy_static;
-use std::sync::{Arc, Condvar, Mutex};
+use std::{
+    collections::BTreeSet,
+    env,
+    fs::OpenOptions,
+    io::Write,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, ThreadId},
+    time::Duration,
+};
 
 lazy_static! {
     static ref TEST_SESSION: TestSession = TestSession::new();
+    static ref REFERENCED_FIXTURES: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
 }
 
+/// The environment variable naming a file every fixture path is appended to the first time a test
+/// of the process references it.
+const FIXTURE_LIST_VARIABLE: &str = "SERVIRTIUM_FIXTURE_LIST";
+
 pub struct TestSession {
-    lock: Arc<(Mutex<bool>, Condvar)>,
+    /// The thread running the current test, if any, so nested tests fail instead of waiting for
+    /// themselves.
+    lock: Arc<(Mutex<Option<ThreadId>>, Condvar)>,
     error: Mutex<Option<Error>>,
+    /// The session timeout of the running test, kept for `after_test`.
+    timeout: Mutex<Option<Duration>>,
 }
 
 impl TestSession {
     fn new() -> Self {
         Self {
-            lock: Arc::new((Mutex::new(false), Condvar::new())),
+            lock: Arc::new((Mutex::new(None), Condvar::new())),
             error: Mutex::new(None),
+            timeout: Mutex::new(None),
         }
     }
 
@@ -23,18 +46,52 @@ impl TestSession {
         *TEST_SESSION.error.lock().unwrap() = Some(error);
     }
 
+    /// Waits for the previous test to finish and configures the server for the test. It panics if
+    /// the session timeout of the configuration passes while it waits, see
+    /// [`ServirtiumConfiguration::set_session_timeout`], and if the thread is already running a
+    /// test, e.g. because a helper called by the test starts another one.
     pub fn before_test(configuration: ServirtiumConfiguration) {
-        TEST_SESSION.enter_test();
-        runner::start_once();
+        let timeout = configuration.session_timeout();
+
+        if let Err(e) = TEST_SESSION.enter_test(timeout) {
+            panic!("Servirtium Error: {}", e);
+        }
+        *TEST_SESSION.timeout.lock().unwrap() = timeout;
+        register_fixtures(&*configuration.interaction_manager());
+        configuration
+            .interaction_manager()
+            .configure(&configuration);
 
-        let mut server = ServirtiumServer::instance();
+        let configured = configuration.server_address();
+        let running = runner::start_once(configured);
+        let any_port = configured.port() == 0 && configured.ip() == running.ip();
+        if running != configured && !any_port {
+            Self::set_error(Error::ServerAddressConflict {
+                configured,
+                running,
+            });
+        }
+
+        let mut server = match Self::server_instance() {
+            Ok(server) => server,
+            Err(e) => {
+                TEST_SESSION.exit_test();
+                panic!("Servirtium Error: {}", e);
+            }
+        };
 
         server.configuration = Some(configuration);
         server.release_instance();
     }
 
     pub fn after_test() -> Result<(), Error> {
-        let mut instance = ServirtiumServer::instance();
+        let mut instance = match Self::server_instance() {
+            Ok(instance) => instance,
+            Err(e) => {
+                TEST_SESSION.exit_test();
+                return Err(e);
+            }
+        };
 
         let func = || {
             if let Some(err) = TEST_SESSION.error.lock().unwrap().take() {
@@ -46,16 +103,35 @@ impl TestSession {
 
             if config.interaction_mode() == ServirtiumMode::Record {
                 if !instance.interactions.is_empty() {
-                    interaction_manager
-                        .save_interactions(&instance.interactions)
-                        .map_err(|e| Error::InteractionManager(e))?;
-                }
+                    // the previous recording has to be compared before it's overwritten
+                    let changes =
+                        Self::recording_changes(&*interaction_manager, &instance.interactions);
+
+                    // a failing test keeps the previous recording, see `set_fail_if_markdown_changed`
+                    if let Some(changes) = &changes {
+                        if config.fail_if_markdown_changed() {
+                            return Err(Error::RecordingChanged(changes.clone()));
+                        }
+                    }
 
-                if config.fail_if_markdown_changed() {
-                    interaction_manager
-                        .check_data_unchanged(&instance.interactions)
-                        .map_err(|e| Error::InteractionManager(e))?;
+                    interaction_manager.save_interactions(&instance.interactions)?;
+
+                    if let Some(changes) = changes {
+                        match config.on_recording_changed() {
+                            Some(on_recording_changed) => on_recording_changed(&changes),
+                            None => eprintln!(
+                                "Servirtium: the recorded interactions differ from the previous recording: {}",
+                                changes
+                            ),
+                        }
+                    }
                 }
+
+                Self::check_expected_interactions(config, &instance.interactions)?;
+            } else if config.require_playback_used() && instance.used_interaction_count() == 0 {
+                return Err(Error::PlaybackUnused);
+            } else if config.require_exact_interaction_count() {
+                instance.check_exact_interaction_count()?;
             }
 
             Ok(())
@@ -71,18 +147,163 @@ impl TestSession {
         result
     }
 
-    fn enter_test(&self) {
+    /// Summarizes how the interactions differ from the ones recorded before, or returns `None` if
+    /// they are the same or if there is no previous recording.
+    fn recording_changes(
+        interaction_manager: &(dyn InteractionManager + Send + Sync),
+        interactions: &[InteractionData],
+    ) -> Option<String> {
+        let previous_interactions = interaction_manager
+            .load_interactions()
+            .ok()
+            .filter(|previous_interactions| !previous_interactions.is_empty())?;
+
+        if previous_interactions.len() != interactions.len() {
+            return Some(format!(
+                "{} interactions were recorded instead of {}",
+                interactions.len(),
+                previous_interactions.len()
+            ));
+        }
+
+        interaction_manager
+            .check_data_unchanged(interactions)
+            .err()
+            .map(|e| e.to_string())
+    }
+
+    fn check_expected_interactions(
+        config: &ServirtiumConfiguration,
+        interactions: &[InteractionData],
+    ) -> Result<(), Error> {
+        let actual = || {
+            interactions
+                .iter()
+                .map(|interaction| {
+                    format!(
+                        "{} {}",
+                        interaction.request_data.method, interaction.request_data.uri
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        if let Some(expected) = config.expected_interactions() {
+            let matches = expected.len() == interactions.len()
+                && expected
+                    .iter()
+                    .zip(interactions)
+                    .all(|(signature, interaction)| {
+                        config
+                            .match_configuration()
+                            .matches_signature(signature, &interaction.request_data)
+                    });
+
+            if !matches {
+                return Err(Error::UnexpectedInteractions {
+                    expected: expected.to_vec(),
+                    actual: actual(),
+                });
+            }
+        }
+
+        if let Some(count) = config.expected_interaction_count() {
+            if count != interactions.len() {
+                return Err(Error::UnexpectedInteractions {
+                    expected: vec![format!("{} interactions", count)],
+                    actual: actual(),
+                });
+            }
+        }
+
+        for interaction in interactions {
+            let request_data = &interaction.request_data;
+            let missing = config
+                .expected_request_headers()
+                .iter()
+                .filter(|name| util::find_header(&request_data.headers, name).is_none())
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if !missing.is_empty() {
+                return Err(Error::MissingRequestHeaders {
+                    request: format!("{} {}", request_data.method, request_data.uri),
+                    missing,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Keeps forwarding the requests of the running test in record mode without saving them, e.g.
+    /// for setup calls that don't belong in the fixture, until `resume_recording` is called. The
+    /// skipped interactions don't take up interaction numbers, so the saved ones stay numbered
+    /// consecutively. It has no effect in playback mode.
+    pub fn pause_recording() {
+        Self::set_recording_paused(true);
+    }
+
+    pub fn resume_recording() {
+        Self::set_recording_paused(false);
+    }
+
+    fn set_recording_paused(paused: bool) {
+        let mut instance = ServirtiumServer::instance();
+        instance.recording_paused = paused;
+        instance.release_instance();
+    }
+
+    /// Gets the server instance, giving up after the session timeout of the running test.
+    fn server_instance() -> Result<ServirtiumServer, Error> {
+        match *TEST_SESSION.timeout.lock().unwrap() {
+            Some(timeout) => ServirtiumServer::instance_within(timeout).ok_or_else(|| {
+                Error::ProbableDeadlock(String::from("the Servirtium server instance"))
+            }),
+            None => Ok(ServirtiumServer::instance()),
+        }
+    }
+
+    /// Marks a test as running on the current thread once the previous one has finished, unless
+    /// that takes longer than `timeout` or the current thread is the one running it.
+    fn enter_test(&self, timeout: Option<Duration>) -> Result<(), Error> {
         let (lock, cond) = &*self.lock.clone();
-        let mut is_test_running = cond
-            .wait_while(lock.lock().unwrap(), |is_test_running| *is_test_running)
-            .unwrap();
-        *is_test_running = true;
+        let current_thread = thread::current().id();
+        let running_thread = lock.lock().unwrap();
+
+        // the thread would wait for itself forever
+        if *running_thread == Some(current_thread) {
+            return Err(Error::NestedTest);
+        }
+
+        let mut running_thread = match timeout {
+            Some(timeout) => {
+                let (running_thread, wait) = cond
+                    .wait_timeout_while(running_thread, timeout, |running_thread| {
+                        running_thread.is_some()
+                    })
+                    .unwrap();
+
+                if wait.timed_out() {
+                    return Err(Error::ProbableDeadlock(String::from(
+                        "the previous test to finish",
+                    )));
+                }
+                running_thread
+            }
+            None => cond
+                .wait_while(running_thread, |running_thread| running_thread.is_some())
+                .unwrap(),
+        };
+        *running_thread = Some(current_thread);
+
+        Ok(())
     }
 
     fn exit_test(&self) {
         let (lock, cond) = &*self.lock.clone();
-        let mut is_test_running = lock.lock().unwrap();
-        *is_test_running = false;
+        let mut running_thread = lock.lock().unwrap();
+        *running_thread = None;
 
         cond.notify_one();
     }
@@ -93,3 +314,127 @@ impl Default for TestSession {
         Self::new()
     }
 }
+
+/// Calls [`TestSession::after_test`] when dropped, including during unwinding, so the session is
+/// cleaned up without wrapping the test in `catch_unwind`.
+pub struct TestSessionGuard {
+    _private: (),
+}
+
+impl TestSessionGuard {
+    pub fn new() -> Self {
+        Self { _private: () }
+    }
+}
+
+impl Default for TestSessionGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TestSessionGuard {
+    fn drop(&mut self) {
+        if let Err(e) = TestSession::after_test() {
+            // panicking while already unwinding would abort the test process
+            if thread::panicking() {
+                eprintln!("Servirtium Error: {}", e);
+            } else {
+                panic!("Servirtium Error: {}", e);
+            }
+        }
+    }
+}
+
+/// Returns the fixture files referenced by the tests that have started in this process so far,
+/// sorted and without duplicates, see [`InteractionManager::fixture_paths`]. The paths are the
+/// ones given to the interaction managers, so they're usually relative to the package directory.
+///
+/// Tests run in parallel in threads of their own, so to find the fixtures no test uses anymore, set
+/// the `SERVIRTIUM_FIXTURE_LIST` environment variable to a file, e.g.
+/// `SERVIRTIUM_FIXTURE_LIST=target/fixtures.txt cargo test`. Each referenced fixture is then
+/// appended to it on a line of its own, and the fixtures that are missing from it are orphaned.
+pub fn referenced_fixtures() -> Vec<PathBuf> {
+    REFERENCED_FIXTURES
+        .lock()
+        .unwrap()
+        .iter()
+        .cloned()
+        .collect()
+}
+
+pub(crate) fn register_fixtures(interaction_manager: &dyn InteractionManager) {
+    let mut referenced_fixtures = REFERENCED_FIXTURES.lock().unwrap();
+    let fixture_list = env::var_os(FIXTURE_LIST_VARIABLE);
+
+    for path in interaction_manager.fixture_paths() {
+        if !referenced_fixtures.insert(path.clone()) {
+            continue;
+        }
+
+        if let Some(fixture_list) = &fixture_list {
+            let appended = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(fixture_list)
+                .and_then(|mut file| writeln!(file, "{}", path.display()));
+
+            if let Err(e) = appended {
+                eprintln!(
+                    "Servirtium: couldn't append {} to {}: {}",
+                    path.display(),
+                    FIXTURE_LIST_VARIABLE,
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Starts a test without the attribute macros: it waits for the previous test, configures the
+/// server with [`TestSession::before_test`] and returns a guard that calls
+/// [`TestSession::after_test`] when it's dropped.
+///
+/// ```no_run
+/// use servirtium::{MarkdownInteractionManager, ServirtiumConfiguration, ServirtiumMode};
+///
+/// let mut config = ServirtiumConfiguration::new(
+///     ServirtiumMode::Playback,
+///     Box::new(MarkdownInteractionManager::new("test_recordings/todos.md")),
+/// );
+/// config.set_domain_name("https://example.com");
+///
+/// let server = servirtium::start(config);
+/// // point the client at server.base_url(), e.g. http://127.0.0.1:61417
+/// ```
+pub fn start(configuration: ServirtiumConfiguration) -> ServerGuard {
+    let configured = configuration.server_address();
+    TestSession::before_test(configuration);
+
+    ServerGuard {
+        address: runner::start_once(configured),
+        _session: TestSessionGuard::new(),
+    }
+}
+
+/// Returned by [`start`], it knows the address of the server, which is useful when the configured
+/// port is 0 and the OS picks one.
+pub struct ServerGuard {
+    address: SocketAddr,
+    _session: TestSessionGuard,
+}
+
+impl ServerGuard {
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The URL the client under test has to send its requests to, without a trailing slash.
+    pub fn base_url(&self) -> String {
+        if self.address.ip().is_unspecified() {
+            format!("http://localhost:{}", self.address.port())
+        } else {
+            format!("http://{}", self.address)
+        }
+    }
+}
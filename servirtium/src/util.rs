@@ -6,12 +6,45 @@ use hyper::{
 use std::collections::HashMap;
 
 pub fn extract_headers(header_map: &HeaderMap) -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+
     // it currently ignores header values with opaque characters
-    header_map
+    for (key, value) in header_map
         .iter()
-        .map(|(k, v)| (String::from(k.as_str()), v.to_str()))
-        .filter_map(|(key, value)| value.ok().map(|v| (key, String::from(v))))
-        .collect::<HashMap<_, _>>()
+        .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.as_str(), v)))
+    {
+        append_header_value(&mut headers, key, value);
+    }
+
+    headers
+}
+
+/// Inserts the header value, joining it with the existing values of the header with `\n`, which
+/// can't be a part of a header value.
+pub fn append_header_value(headers: &mut HashMap<String, String>, key: &str, value: &str) {
+    headers
+        .entry(String::from(key))
+        .and_modify(|existing| {
+            existing.push('\n');
+            existing.push_str(value);
+        })
+        .or_insert_with(|| String::from(value));
+}
+
+/// Removes the header with the given name regardless of its case, and returns its name and value.
+pub fn remove_header(
+    headers: &mut HashMap<String, String>,
+    name: &str,
+) -> Option<(String, String)> {
+    let key = headers
+        .keys()
+        .find(|key| key.eq_ignore_ascii_case(name))?
+        .clone();
+    headers.remove_entry(&key)
+}
+
+pub fn header_values(value: &str) -> impl Iterator<Item = &str> {
+    value.split('\n')
 }
 
 pub fn put_headers<'a, I: IntoIterator<Item = (&'a String, &'a String)>>(
@@ -20,9 +53,88 @@ pub fn put_headers<'a, I: IntoIterator<Item = (&'a String, &'a String)>>(
 ) -> Result<(), Error> {
     for (key, value) in headers {
         let header_name = HeaderName::from_lowercase(key.to_lowercase().as_bytes())?;
-        let header_value = HeaderValue::from_str(value)?;
-        header_map.append(header_name, header_value);
+        for value in header_values(value) {
+            header_map.append(header_name.clone(), HeaderValue::from_str(value)?);
+        }
     }
 
     Ok(())
 }
+
+/// Finds a header value by its case-insensitive name.
+pub fn find_header<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Returns the first value of the `Content-Type` header.
+pub fn content_type(headers: &HashMap<String, String>) -> Option<&str> {
+    find_header(headers, "content-type").and_then(|value| header_values(value).next())
+}
+
+pub fn content_length(headers: &HashMap<String, String>) -> Option<usize> {
+    find_header(headers, "content-length").and_then(|value| value.trim().parse().ok())
+}
+
+pub fn update_content_length(headers: &mut HashMap<String, String>, body: &str) {
+    set_content_length(headers, body.len());
+}
+
+/// Sets the `Content-Length` header to `length` bytes if it's present, chunked bodies don't have
+/// one.
+pub fn set_content_length(headers: &mut HashMap<String, String>, length: usize) {
+    for (key, value) in headers.iter_mut() {
+        if key.eq_ignore_ascii_case("content-length") {
+            *value = length.to_string();
+        }
+    }
+}
+
+/// Removes the scheme and the authority of absolute URIs, e.g. `https://example.com/users` becomes
+/// `/users`. Other URIs are returned as they are.
+pub fn path_and_query(uri: &str) -> &str {
+    match uri.find("://") {
+        Some(scheme_end) => {
+            let authority_start = scheme_end + "://".len();
+            uri[authority_start..]
+                .find('/')
+                .map_or("/", |path_start| &uri[authority_start + path_start..])
+        }
+        None => uri,
+    }
+}
+
+/// Joins the domain name and the URI with exactly one slash between them.
+pub fn join_url(domain_name: &str, uri: &str) -> String {
+    format!(
+        "{}/{}",
+        domain_name.trim_end_matches('/'),
+        uri.trim_start_matches('/')
+    )
+}
+
+/// Indents the elements of an XML document, ignoring the whitespace between them. Returns `None`
+/// if the text isn't well-formed XML.
+#[cfg(feature = "pretty-xml")]
+pub fn pretty_print_xml(text: &str) -> Option<String> {
+    use quick_xml::{events::Event, Reader, Writer};
+
+    if !text.trim_start().starts_with('<') {
+        return None;
+    }
+
+    let mut reader = Reader::from_str(text);
+    reader.config_mut().trim_text(true);
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+
+    loop {
+        match reader.read_event().ok()? {
+            Event::Eof => break,
+            event => writer.write_event(event).ok()?,
+        }
+    }
+
+    String::from_utf8(writer.into_inner()).ok()
+}
@@ -0,0 +1,103 @@
+use hyper::{body, Body, Client, Request};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+use tokio::runtime::Runtime;
+
+pub const SERVIRTIUM_URL: &str = "http://localhost:61417";
+
+#[derive(Debug)]
+pub struct Response {
+    pub status_code: u16,
+    pub headers: HashMap<String, Vec<String>>,
+    pub body: String,
+}
+
+pub fn get(path: &str) -> Response {
+    request("GET", path, &[], "")
+}
+
+pub fn request(method: &str, path: &str, headers: &[(&str, &str)], body: &str) -> Response {
+    try_request(method, path, headers, body).unwrap()
+}
+
+pub fn try_request(
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> Result<Response, hyper::Error> {
+    Runtime::new().unwrap().block_on(async {
+        let mut builder = Request::builder()
+            .method(method)
+            .uri(format!("{}{}", SERVIRTIUM_URL, path));
+
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+
+        let response = Client::new()
+            .request(builder.body(Body::from(body.to_owned())).unwrap())
+            .await?;
+
+        let status_code = response.status().as_u16();
+        let mut headers = HashMap::<String, Vec<String>>::new();
+        for (name, value) in response.headers() {
+            headers
+                .entry(name.as_str().into())
+                .or_default()
+                .push(value.to_str().unwrap().into());
+        }
+        let body = body::to_bytes(response.into_body()).await?;
+
+        Ok(Response {
+            status_code,
+            headers,
+            body: String::from_utf8_lossy(&body).into(),
+        })
+    })
+}
+
+/// Sends a request with `Expect: 100-continue` and only sends the body once the interim response
+/// has been received, like curl does for large uploads. Returns the interim response and the raw
+/// final response.
+pub fn post_expecting_continue(path: &str, body: &str) -> (String, String) {
+    let mut stream = TcpStream::connect("localhost:61417").unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\nContent-Length: {}\r\n\r\n",
+        path,
+        body.len()
+    )
+    .unwrap();
+
+    let mut buffer = [0; 4096];
+    let read = stream.read(&mut buffer).unwrap();
+    let interim = String::from_utf8_lossy(&buffer[..read]).into_owned();
+
+    stream.write_all(body.as_bytes()).unwrap();
+    let read = stream.read(&mut buffer).unwrap();
+    let response = String::from_utf8_lossy(&buffer[..read]).into_owned();
+
+    (interim, response)
+}
+
+/// Sends the given bytes to the Servirtium server as they are, e.g. an incomplete request, and
+/// returns the raw response.
+pub fn send_raw(request: &str) -> String {
+    let mut stream = TcpStream::connect("localhost:61417").unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut buffer = [0; 4096];
+    let read = stream.read(&mut buffer).unwrap();
+    String::from_utf8_lossy(&buffer[..read]).into_owned()
+}
@@ -1,13 +1,229 @@
+#[cfg(test)]
+mod client;
+#[cfg(test)]
+mod upstream;
+
 #[cfg(test)]
 mod tests {
+    use crate::{client, upstream};
+    use hyper::{Body, Request, Response};
     use servirtium::servirtium_playback_test;
-    use servirtium::{servirtium_record_test, ServirtiumConfiguration};
+    use servirtium::{
+        servirtium_record_test, BlockingHttpClient, EmbeddedInteractionManager,
+        EncryptedInteractionManager, ExternalBodies, FullRequestMutation, FullResponseMutation,
+        GzipInteractionManager, HeaderOrder, HttpClient, InteractionData, InteractionManager,
+        JsonlInteractionManager, MarkdownInteractionManager, MatchConfiguration,
+        MemoryInteractionManager, MutationsBuilder, NoMatchBehavior, NonUtf8BodyPolicy,
+        PlaybackHandle, RedirectPolicy, RequestData, RequestMutation, ReqwestHttpClient,
+        ResponseData, ServirtiumConfiguration, ServirtiumMode, StreamInteractionManager,
+        TestSession,
+    };
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
 
     fn configure_servirtium(config: &mut ServirtiumConfiguration) {
         config.set_domain_name("test");
     }
 
-    #[servirtium_playback_test("test1.md", "test")]
+    fn configure_stubs(config: &mut ServirtiumConfiguration) {
+        config.set_domain_name("http://localhost:1");
+        config.add_stub(
+            "get",
+            "/token",
+            ResponseData {
+                status_code: 201,
+                reason_phrase: None,
+                headers: HashMap::new(),
+                body: String::from("stubbed"),
+            },
+        );
+    }
+
+    fn hello_world(_: Request<Body>) -> Response<Body> {
+        Response::builder()
+            .header("content-length", "11")
+            .body(Body::from("hello world"))
+            .unwrap()
+    }
+
+    fn echo_path(request: Request<Body>) -> Response<Body> {
+        Response::new(Body::from(request.uri().path().to_owned()))
+    }
+
+    fn echo_query(request: Request<Body>) -> Response<Body> {
+        Response::new(Body::from(
+            request.uri().query().unwrap_or_default().to_owned(),
+        ))
+    }
+
+    fn echo_authorization(request: Request<Body>) -> Response<Body> {
+        let authorization = request.headers()["authorization"].to_str().unwrap();
+        Response::new(Body::from(authorization.to_owned()))
+    }
+
+    fn redirecting(request: Request<Body>) -> Response<Body> {
+        let (status_code, location) = match request.uri().path() {
+            "/old" => (302, "/new"),
+            "/form" => (303, "/new"),
+            "/away" => (302, "http://127.0.0.1:61538/elsewhere"),
+            path => {
+                return Response::new(Body::from(format!("{} {}", request.method(), path)));
+            }
+        };
+
+        Response::builder()
+            .status(status_code)
+            .header("location", location)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn echo_request_id(request: Request<Body>) -> Response<Body> {
+        let request_id = request.headers()["x-request-id"].to_str().unwrap();
+        Response::new(Body::from(request_id.to_owned()))
+    }
+
+    fn echo_host(request: Request<Body>) -> Response<Body> {
+        let host = request.headers()["host"].to_str().unwrap();
+        Response::new(Body::from(host.to_owned()))
+    }
+
+    fn echo_accept_encoding(request: Request<Body>) -> Response<Body> {
+        let accept_encoding = request.headers()["accept-encoding"].to_str().unwrap();
+        Response::new(Body::from(accept_encoding.to_owned()))
+    }
+
+    fn paginated(_: Request<Body>) -> Response<Body> {
+        Response::new(Body::from(
+            r#"{"next": "http://127.0.0.1:61522/users?page=2"}"#,
+        ))
+    }
+
+    fn echo_expect(request: Request<Body>) -> Response<Body> {
+        let expect = request
+            .headers()
+            .get("expect")
+            .map_or("none", |value| value.to_str().unwrap());
+        Response::new(Body::from(expect.to_owned()))
+    }
+
+    fn latin1_body(_: Request<Body>) -> Response<Body> {
+        Response::new(Body::from(&b"caf\xe9"[..]))
+    }
+
+    fn latin1_text(_: Request<Body>) -> Response<Body> {
+        Response::builder()
+            .header("content-type", "text/plain; charset=ISO-8859-1")
+            .header("content-length", "10")
+            .body(Body::from(&b"caf\xe9 cr\xe8me"[..]))
+            .unwrap()
+    }
+
+    fn chunked_hello_world(_: Request<Body>) -> Response<Body> {
+        let (mut sender, body) = Body::channel();
+        // without a length hyper sends the body in chunks
+        sender.try_send_data("hello world".into()).unwrap();
+
+        Response::new(body)
+    }
+
+    fn endless_events(_: Request<Body>) -> Response<Body> {
+        let (mut sender, body) = Body::channel();
+        sender
+            .try_send_data("data: 1\n\ndata: 2\n\ndata: 3\n\n".into())
+            .unwrap();
+        // the stream never ends while the sender is alive
+        std::mem::forget(sender);
+
+        Response::builder()
+            .header("content-type", "text/event-stream")
+            .body(body)
+            .unwrap()
+    }
+
+    fn slow_hello_world(request: Request<Body>) -> Response<Body> {
+        std::thread::sleep(Duration::from_millis(50));
+        hello_world(request)
+    }
+
+    #[derive(Debug)]
+    struct FakeBlockingClient;
+
+    impl BlockingHttpClient for FakeBlockingClient {
+        fn make_request(
+            &self,
+            url: &str,
+            request_data: &RequestData,
+        ) -> Result<ResponseData, servirtium::Error> {
+            std::thread::sleep(Duration::from_millis(10));
+
+            Ok(ResponseData {
+                status_code: 200,
+                reason_phrase: None,
+                headers: HashMap::new(),
+                body: format!("{}{}", url, request_data.uri),
+            })
+        }
+    }
+
+    fn configure_content_length(config: &mut ServirtiumConfiguration) {
+        config.set_domain_name(upstream::serve(61501, hello_world));
+        config.add_record_response_mutations(|builder| builder.body_replace("world", "servirtium"));
+        config.set_on_record(Box::new(|interaction| {
+            assert_eq!(interaction.response_data.body, "hello servirtium");
+            interaction.response_data.body.push('!');
+        }));
+    }
+
+    fn interaction(method: &str, uri: &str, response_body: &str) -> InteractionData {
+        InteractionData {
+            interaction_number: 0,
+            request_data: RequestData {
+                uri: String::from(uri),
+                method: String::from(method),
+                http_version: None,
+                headers: HashMap::new(),
+                body: String::new(),
+            },
+            response_data: ResponseData {
+                status_code: 200,
+                reason_phrase: None,
+                headers: HashMap::new(),
+                body: String::from(response_body),
+            },
+            response_time: None,
+        }
+    }
+
+    fn playback_configuration(interactions: Vec<InteractionData>) -> ServirtiumConfiguration {
+        ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(MemoryInteractionManager::with_interactions(interactions)),
+        )
+    }
+
+    fn configure_cookies(config: &mut ServirtiumConfiguration) {
+        let mut login = interaction("POST", "/login", "");
+        login
+            .response_data
+            .headers
+            .insert(String::from("set-cookie"), String::from("a=1\nb=2"));
+
+        config.set_interaction_manager(Box::new(MemoryInteractionManager::with_interactions(
+            vec![login],
+        )));
+    }
+
+    fn configure_polling(config: &mut ServirtiumConfiguration) {
+        config.set_interaction_manager(Box::new(MemoryInteractionManager::with_interactions(
+            vec![interaction("GET", "/", "polled")],
+        )));
+    }
+
+    #[servirtium_playback_test("test1.md", "https://example.com")]
     fn simple_playback_test() {
         println!("this test does nothing...");
     }
@@ -16,4 +232,3035 @@ mod tests {
     fn simple_record_test() {
         println!("this test does nothing...");
     }
+
+    #[servirtium_playback_test("test_no_catch.md", configure_stubs, no_catch)]
+    fn no_catch_playback_test() {
+        assert_eq!(client::get("/token").body, "stubbed");
+    }
+
+    #[servirtium_playback_test("test_overrides.md", configure_polling, dedupe_interactions = true)]
+    fn attribute_overrides_are_applied() {
+        let response = client::get("/");
+
+        assert_eq!(response.body, "polled");
+        assert_eq!(client::get("/").body, "polled");
+    }
+
+    #[servirtium_playback_test("test_any_port.md", configure_polling, port = 0)]
+    fn any_port_can_be_set_with_an_override() {
+        assert_eq!(client::get("/").body, "polled");
+    }
+
+    #[test]
+    fn configuration_functions_are_checked_at_the_argument() {
+        let cases = trybuild::TestCases::new();
+        cases.pass("ui/valid_configuration_function.rs");
+        cases.compile_fail("ui/wrong_configuration_function.rs");
+    }
+
+    #[servirtium_playback_test("test_stubs.md", configure_stubs)]
+    fn stub_is_served_without_markdown() {
+        let response = client::get("/token");
+
+        assert_eq!(response.status_code, 201);
+        assert_eq!(response.body, "stubbed");
+    }
+
+    #[servirtium_record_test("../target/test_content_length.md", configure_content_length)]
+    fn content_length_follows_mutated_body() {
+        let response = client::get("/");
+
+        assert_eq!(response.body, "hello servirtium!");
+    }
+
+    #[test]
+    fn memory_manager_records_and_plays_back() {
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name(upstream::serve(61502, hello_world));
+
+        TestSession::before_test(config);
+        let recorded = client::get("/memory");
+        TestSession::after_test().unwrap();
+
+        let interactions = manager.interactions();
+        assert_eq!(interactions.len(), 1);
+        assert_eq!(interactions[0].request_data.uri, "/memory");
+
+        TestSession::before_test(ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(manager),
+        ));
+        let played_back = client::get("/memory");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(played_back.body, recorded.body);
+    }
+
+    #[test]
+    fn mutations_apply_in_insertion_order() {
+        let mut builder = MutationsBuilder::default();
+        builder
+            .body_replace("a", "b")
+            .add_header("x-test", "1")
+            .body_replace("b", "c")
+            .remove_headers(vec!["X-Test"]);
+
+        let mut request_data = RequestData {
+            uri: String::from("/"),
+            method: String::from("POST"),
+            http_version: None,
+            headers: HashMap::new(),
+            body: String::from("a"),
+        };
+        for mutation in builder.request_mutations() {
+            mutation.mutate(&mut request_data);
+        }
+
+        let mut response_data = ResponseData {
+            status_code: 200,
+            reason_phrase: None,
+            headers: HashMap::new(),
+            body: String::from("a"),
+        };
+        for mutation in builder.into_response_mutations() {
+            mutation.mutate(&mut response_data);
+        }
+
+        assert_eq!(request_data.body, "c");
+        assert!(request_data.headers.is_empty());
+        assert_eq!(response_data.body, "c");
+        assert!(response_data.headers.is_empty());
+    }
+
+    #[servirtium_playback_test("test_cookies.md", configure_cookies)]
+    fn every_set_cookie_header_is_played_back() {
+        let response = client::request("POST", "/login", &[], "");
+
+        assert_eq!(response.headers["set-cookie"], vec!["a=1", "b=2"]);
+    }
+
+    #[test]
+    fn url_is_joined_with_a_single_slash() {
+        let domain_name = upstream::serve(61503, echo_path);
+        let client = ReqwestHttpClient::new();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        for domain_name in &[domain_name.clone(), format!("{}/", domain_name)] {
+            for uri in &["/path", "path"] {
+                let request_data = RequestData {
+                    uri: String::from(*uri),
+                    method: String::from("GET"),
+                    http_version: None,
+                    headers: HashMap::new(),
+                    body: String::new(),
+                };
+
+                let response_data = runtime
+                    .block_on(client.make_request(domain_name, &request_data))
+                    .unwrap();
+
+                assert_eq!(response_data.body, "/path");
+            }
+        }
+    }
+
+    #[test]
+    fn connections_are_reused_across_recorded_requests() {
+        let (domain_name, connections) = upstream::serve_counting_connections(61524, hello_world);
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name(domain_name);
+
+        TestSession::before_test(config);
+        for path in &["/first", "/second", "/third"] {
+            let response = client::request("GET", path, &[], "");
+
+            assert_eq!(response.body, "hello world");
+        }
+        TestSession::after_test().unwrap();
+
+        assert_eq!(manager.interactions().len(), 3);
+        assert_eq!(connections.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn basic_auth_is_forwarded_but_not_saved() {
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name(upstream::serve(61504, echo_authorization));
+        config.scrub_basic_auth();
+
+        TestSession::before_test(config);
+        let response = client::request(
+            "GET",
+            "/secret",
+            &[("Authorization", "Basic dXNlcjpwYXNz")],
+            "",
+        );
+        TestSession::after_test().unwrap();
+
+        let mut interactions = manager.interactions();
+        assert_eq!(response.body, "Basic dXNlcjpwYXNz");
+        assert_eq!(
+            interactions[0].request_data.headers["authorization"],
+            "Basic REDACTED"
+        );
+
+        let play_back = |match_vary_headers: bool| {
+            let mut config =
+                ServirtiumConfiguration::new(ServirtiumMode::Playback, Box::new(manager.clone()));
+            config
+                .match_configuration_mut()
+                .set_match_vary_headers(match_vary_headers);
+
+            TestSession::before_test(config);
+            client::request(
+                "GET",
+                "/secret",
+                &[("Authorization", "Basic dXNlcjpwYXNz")],
+                "",
+            );
+            TestSession::after_test()
+        };
+
+        // the headers aren't matched unless the response varies on them
+        play_back(true).unwrap();
+
+        interactions[0]
+            .response_data
+            .headers
+            .insert(String::from("vary"), String::from("Authorization"));
+        manager.save_interactions(&interactions).unwrap();
+        assert!(play_back(true).is_err());
+        play_back(false).unwrap();
+    }
+
+    #[test]
+    fn scrubbed_headers_are_removed_everywhere() {
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name(upstream::serve(61528, echo_request_id));
+        config.scrub_headers(vec!["Date", "X-Request-Id"]);
+
+        TestSession::before_test(config);
+        let response = client::request("GET", "/", &[("X-Request-Id", "42")], "");
+        TestSession::after_test().unwrap();
+
+        let interactions = manager.interactions();
+        assert_eq!(response.body, "42");
+        assert!(!interactions[0]
+            .request_data
+            .headers
+            .contains_key("x-request-id"));
+        assert!(!interactions[0].response_data.headers.contains_key("date"));
+    }
+
+    #[test]
+    fn only_the_retained_headers_are_saved() {
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name(upstream::serve_raw(
+            61544,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nDate: Mon, 01 Jun 2020 10:00:00 GMT\r\n\
+             X-Request-Id: 42\r\ncontent-length: 2\r\n\r\nok",
+        ));
+        config.add_record_response_mutations(|builder| {
+            builder.retain_headers(vec!["CONTENT-TYPE", "Content-Length"])
+        });
+
+        TestSession::before_test(config);
+        let response = client::get("/");
+        TestSession::after_test().unwrap();
+
+        let mut saved_headers = manager.interactions()[0]
+            .response_data
+            .headers
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        saved_headers.sort();
+        assert_eq!(saved_headers, vec!["content-length", "content-type"]);
+        assert_eq!(response.body, "ok");
+    }
+
+    #[test]
+    fn recorded_uris_use_the_configured_base_url() {
+        let path = "../target/test_recorded_base_url.md";
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Record,
+            Box::new(MarkdownInteractionManager::new(path)),
+        );
+        config.set_domain_name(upstream::serve(61529, echo_path));
+        config.set_recorded_base_url("https://staging.example.com/");
+
+        TestSession::before_test(config);
+        assert_eq!(client::get("/users?page=2").body, "/users");
+        TestSession::after_test().unwrap();
+
+        let markdown = std::fs::read_to_string(path).unwrap();
+        assert!(markdown.contains("## Interaction 0: GET https://staging.example.com/users?page=2"));
+
+        TestSession::before_test(ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(MarkdownInteractionManager::new(path)),
+        ));
+        let response = client::get("/users?page=2");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "/users");
+    }
+
+    #[test]
+    fn paused_interactions_are_forwarded_but_not_saved() {
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name(upstream::serve(61530, echo_path));
+
+        TestSession::before_test(config);
+        TestSession::pause_recording();
+        let setup = client::get("/setup");
+        TestSession::resume_recording();
+        client::get("/users");
+        client::get("/orders");
+        TestSession::after_test().unwrap();
+
+        let interactions = manager.interactions();
+        assert_eq!(setup.body, "/setup");
+        assert_eq!(interactions.len(), 2);
+        assert_eq!(interactions[0].interaction_number, 0);
+        assert_eq!(interactions[0].request_data.uri, "/users");
+        assert_eq!(interactions[1].interaction_number, 1);
+        assert_eq!(interactions[1].request_data.uri, "/orders");
+    }
+
+    #[test]
+    fn interaction_summary() {
+        let interaction = InteractionData {
+            interaction_number: 2,
+            request_data: RequestData {
+                uri: String::from("/users"),
+                method: String::from("POST"),
+                http_version: None,
+                headers: HashMap::new(),
+                body: String::from("{}"),
+            },
+            response_data: ResponseData {
+                status_code: 201,
+                reason_phrase: None,
+                headers: HashMap::new(),
+                body: "x".repeat(100),
+            },
+            response_time: None,
+        };
+
+        assert_eq!(interaction.to_string(), "#2 POST /users -> 201 (100 bytes)");
+        assert_eq!(
+            format!("{:#}", interaction),
+            format!(
+                "#2 POST /users (2 bytes) \"{{}}\" -> 201 (100 bytes) \"{}...\"",
+                "x".repeat(64)
+            )
+        );
+    }
+
+    #[test]
+    fn paths_match_ignoring_trailing_slash_and_case() {
+        let interactions = vec![
+            interaction("GET", "/Users/", "first"),
+            interaction("GET", "/Users/", "second"),
+        ];
+
+        TestSession::before_test(playback_configuration(interactions.clone()));
+        let strict = client::get("/users");
+        let strict_result = TestSession::after_test();
+
+        let mut config = playback_configuration(interactions);
+        config
+            .match_configuration_mut()
+            .set_ignore_trailing_slash(true)
+            .set_ignore_path_case(true);
+        TestSession::before_test(config);
+        let first = client::get("/users");
+        let second = client::get("/USERS/");
+        TestSession::after_test().unwrap();
+
+        assert_ne!(strict.body, "first");
+        assert!(strict_result.is_err());
+        assert_eq!(first.body, "first");
+        assert_eq!(second.body, "second");
+    }
+
+    #[test]
+    fn record_fails_on_unexpected_interactions() {
+        let domain_name = upstream::serve(61505, echo_path);
+        let record = |expected: &[&str]| {
+            let mut config = ServirtiumConfiguration::new(
+                ServirtiumMode::Record,
+                Box::new(MemoryInteractionManager::new()),
+            );
+            config.set_domain_name(domain_name.clone());
+            config.expect_interactions(expected.iter().copied());
+
+            TestSession::before_test(config);
+            client::get("/a");
+            client::request("POST", "/b", &[], "");
+            TestSession::after_test()
+        };
+
+        assert!(record(&["GET /a", "/b"]).is_ok());
+        assert!(record(&["GET /a"]).is_err());
+        assert!(record(&["GET /a", "GET /b"]).is_err());
+    }
+
+    #[test]
+    fn common_volatile_headers_are_scrubbed() {
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name(upstream::serve_raw(
+            61534,
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\nx-amz-meta-owner: docs\r\n\
+             date: Mon, 01 Jun 2020 10:00:00 GMT\r\nset-cookie: session=1\r\n\
+             x-amzn-requestid: 42\r\ncf-ray: 5a1b\r\nx-cache: Hit from cloudfront\r\n\
+             x-amz-cf-pop: OSL50\r\ncontent-length: 2\r\n\r\nok",
+        ));
+        config.scrub_common_volatile_headers();
+
+        TestSession::before_test(config);
+        let response = client::request(
+            "GET",
+            "/",
+            &[("traceparent", "00-01-02-01"), ("x-b3-traceid", "1")],
+            "",
+        );
+        TestSession::after_test().unwrap();
+
+        let interactions = manager.interactions();
+        let request_headers = &interactions[0].request_data.headers;
+        let mut response_headers = interactions[0]
+            .response_data
+            .headers
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        response_headers.sort();
+
+        assert!(!request_headers.contains_key("traceparent"));
+        assert!(!request_headers.contains_key("x-b3-traceid"));
+        assert_eq!(
+            response_headers,
+            ["content-length", "content-type", "x-amz-meta-owner"]
+        );
+        assert!(!response.headers.contains_key("cf-ray"));
+        assert!(!response.headers.contains_key("set-cookie"));
+        assert_eq!(response.body, "ok");
+    }
+
+    #[test]
+    fn record_auth_is_sent_upstream_but_not_saved() {
+        let domain_name = upstream::serve(61535, echo_authorization);
+        let record = |headers: &[(&str, &str)]| {
+            let manager = MemoryInteractionManager::new();
+            let mut config =
+                ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+            config.set_domain_name(domain_name.clone());
+            config.set_record_auth("Bearer record-secret");
+
+            TestSession::before_test(config);
+            let response = client::request("GET", "/", headers, "");
+            TestSession::after_test().unwrap();
+
+            let saved_headers = manager.interactions()[0].request_data.headers.clone();
+            (response.body, saved_headers)
+        };
+
+        let (body, saved_headers) = record(&[]);
+        assert_eq!(body, "Bearer record-secret");
+        assert!(!saved_headers.contains_key("authorization"));
+
+        let (body, saved_headers) = record(&[("authorization", "Bearer client")]);
+        assert_eq!(body, "Bearer record-secret");
+        assert_eq!(saved_headers["authorization"], "Bearer client");
+    }
+
+    #[test]
+    fn record_fails_on_missing_request_headers() {
+        let domain_name = upstream::serve(61531, echo_path);
+        let record = |headers: &[(&str, &str)]| {
+            let mut config = ServirtiumConfiguration::new(
+                ServirtiumMode::Record,
+                Box::new(MemoryInteractionManager::new()),
+            );
+            config.set_domain_name(domain_name.clone());
+            config.expect_request_headers(vec!["X-Api-Version"]);
+
+            TestSession::before_test(config);
+            client::request("GET", "/a", headers, "");
+            TestSession::after_test()
+        };
+
+        assert!(record(&[("x-api-version", "2")]).is_ok());
+        assert!(matches!(
+            record(&[]),
+            Err(servirtium::Error::MissingRequestHeaders { request, missing })
+                if request == "GET /a" && missing == ["X-Api-Version"]
+        ));
+    }
+
+    #[test]
+    fn interim_responses_are_not_recorded() {
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name(upstream::serve_raw(
+            61506,
+            "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 201 Created\r\ncontent-length: 2\r\n\r\nok",
+        ));
+
+        TestSession::before_test(config);
+        let response = client::request("POST", "/upload", &[("expect", "100-continue")], "data");
+        TestSession::after_test().unwrap();
+
+        let interactions = manager.interactions();
+        assert_eq!(response.status_code, 201);
+        assert_eq!(interactions[0].response_data.status_code, 201);
+        assert_eq!(interactions[0].response_data.body, "ok");
+    }
+
+    #[test]
+    fn headers_are_written_in_the_configured_order() {
+        let mut data = interaction("GET", "/", "");
+        for (name, value) in &[
+            ("date", "today"),
+            ("content-type", "text/plain"),
+            ("via", "b\na"),
+        ] {
+            data.response_data
+                .headers
+                .insert(String::from(*name), String::from(*value));
+        }
+
+        let manager =
+            MarkdownInteractionManager::new("../target/test_header_order.md").with_header_order(
+                HeaderOrder::Leading(vec![String::from("Via"), String::from("content-type")]),
+            );
+        manager.save_interactions(&[data.clone()]).unwrap();
+
+        let markdown = std::fs::read_to_string("../target/test_header_order.md").unwrap();
+        assert!(markdown.contains("via: b\nvia: a\ncontent-type: text/plain\ndate: today\n"));
+
+        let manager = MarkdownInteractionManager::new("../target/test_header_order.md");
+        manager.save_interactions(&[data.clone()]).unwrap();
+
+        let markdown = std::fs::read_to_string("../target/test_header_order.md").unwrap();
+        assert!(markdown.contains("content-type: text/plain\ndate: today\nvia: b\nvia: a\n"));
+
+        let manager = MarkdownInteractionManager::new("../target/test_header_order.md")
+            .with_header_order(HeaderOrder::Unsorted);
+        manager.save_interactions(&[data]).unwrap();
+
+        // the repeated values keep the order they were received in even without sorting
+        let markdown = std::fs::read_to_string("../target/test_header_order.md").unwrap();
+        assert!(markdown.contains("via: b\nvia: a\n"));
+        assert!(markdown.contains("content-type: text/plain\n"));
+        assert!(markdown.contains("date: today\n"));
+    }
+
+    #[test]
+    fn har_requests_are_recorded() {
+        let domain_name = upstream::serve(61507, echo_path);
+        let har = format!(
+            r#"{{"log": {{"entries": [
+                {{"request": {{"method": "GET", "url": "{0}/first?a=1", "headers": [
+                    {{"name": ":authority", "value": "ignored"}},
+                    {{"name": "Accept", "value": "text/plain"}}
+                ]}}}},
+                {{"request": {{"method": "POST", "url": "{0}/second", "headers": [],
+                    "postData": {{"mimeType": "text/plain", "text": "body"}}}}}}
+            ]}}}}"#,
+            domain_name
+        );
+        std::fs::write("../target/test_capture.har", har).unwrap();
+
+        let manager = MemoryInteractionManager::new();
+        servirtium::record_har("../target/test_capture.har", &manager).unwrap();
+
+        let interactions = manager.interactions();
+        assert_eq!(interactions.len(), 2);
+        assert_eq!(interactions[0].request_data.uri, "/first?a=1");
+        assert_eq!(interactions[0].request_data.headers["accept"], "text/plain");
+        assert_eq!(interactions[0].response_data.body, "/first");
+        assert_eq!(interactions[1].request_data.method, "POST");
+        assert_eq!(interactions[1].request_data.body, "body");
+        assert_eq!(interactions[1].response_data.body, "/second");
+    }
+
+    #[test]
+    fn unmatched_request_reports_the_candidates() {
+        let mut update = interaction("POST", "/users/1", "");
+        update.interaction_number = 1;
+        let config = playback_configuration(vec![interaction("GET", "/users", ""), update]);
+
+        TestSession::before_test(config);
+        client::get("/users");
+        client::get("/users");
+        let error = TestSession::after_test().unwrap_err();
+
+        let report = error.to_string();
+        let rows = report.lines().skip(2).collect::<Vec<_>>();
+        assert!(report.starts_with("No recorded interaction matches the request GET /users"));
+        assert_eq!(
+            rows,
+            vec![
+                "  0   | GET /users       | yes    | yes | yes          | -    | no",
+                "  1   | POST /users/1    | no     | no  | yes          | -    | yes",
+            ]
+        );
+    }
+
+    #[test]
+    fn global_defaults_are_applied_before_the_test_configuration() {
+        // the defaults apply to the tests using the macros as well, so they only add a stub no
+        // other test requests
+        ServirtiumConfiguration::set_global_defaults(|config| {
+            config.add_stub(
+                "GET",
+                "/global-default",
+                ResponseData {
+                    status_code: 200,
+                    reason_phrase: None,
+                    headers: HashMap::new(),
+                    body: String::from("default"),
+                },
+            );
+        });
+
+        let config = ServirtiumConfiguration::with_global_defaults(
+            ServirtiumMode::Playback,
+            Box::new(MemoryInteractionManager::new()),
+        );
+
+        TestSession::before_test(config);
+        let response = client::get("/global-default");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "default");
+    }
+
+    #[test]
+    fn head_requests_are_answered_with_recorded_get_headers() {
+        let mut get = interaction("GET", "/file", "hello");
+        get.response_data
+            .headers
+            .insert(String::from("content-length"), String::from("5"));
+        let mut config = playback_configuration(vec![get.clone()]);
+        config
+            .match_configuration_mut()
+            .set_match_head_with_get(true);
+
+        // the `GET` interaction is still replayed after answering the `HEAD` request
+        TestSession::before_test(config);
+        let response = client::request("HEAD", "/file", &[], "");
+        let get_response = client::get("/file");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.headers["content-length"], vec!["5"]);
+        assert_eq!(response.body, "");
+        assert_eq!(get_response.body, "hello");
+
+        TestSession::before_test(playback_configuration(vec![get]));
+        client::request("HEAD", "/file", &[], "");
+        assert!(TestSession::after_test().is_err());
+    }
+
+    #[test]
+    fn unused_playback_fails_when_required() {
+        let recorded = || vec![interaction("GET", "/weather", "sunny")];
+
+        let mut config = playback_configuration(recorded());
+        config.set_require_playback_used(true);
+        TestSession::before_test(config);
+        let error = TestSession::after_test().unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("didn't replay any recorded interaction"));
+
+        let mut config = playback_configuration(recorded());
+        config.set_require_playback_used(true);
+        TestSession::before_test(config);
+        client::get("/weather");
+        assert!(TestSession::after_test().is_ok());
+
+        TestSession::before_test(playback_configuration(recorded()));
+        assert!(TestSession::after_test().is_ok());
+    }
+
+    #[test]
+    fn playback_fails_unless_every_interaction_is_requested_once() {
+        let play_back = |paths: &[&str]| {
+            let recorded = ["/a", "/b", "/c"]
+                .iter()
+                .enumerate()
+                .map(|(index, path)| {
+                    let mut interaction = interaction("GET", path, "");
+                    interaction.interaction_number = index;
+                    interaction
+                })
+                .collect();
+            let mut config = playback_configuration(recorded);
+            config.set_require_exact_interaction_count(true);
+            config.set_playback_default_response(interaction("GET", "/", "").response_data);
+
+            TestSession::before_test(config);
+            for path in paths {
+                client::get(path);
+            }
+            TestSession::after_test()
+        };
+
+        assert!(play_back(&["/a", "/b", "/c"]).is_ok());
+        assert!(matches!(
+            play_back(&["/a", "/b"]),
+            Err(servirtium::Error::InteractionCountMismatch { recorded: 3, requested: 2, unused })
+                if unused == ["2: GET /c"]
+        ));
+        assert!(matches!(
+            play_back(&["/a", "/b", "/c", "/d"]),
+            Err(servirtium::Error::InteractionCountMismatch { requested: 4, unused, .. })
+                if unused.is_empty()
+        ));
+    }
+
+    const EMBEDDED_MARKDOWN: &str = "## Interaction 0: GET /weather\r\n\r\n\
+        ### Request headers recorded for playback:\r\n\r\n```\r\n```\r\n\r\n\
+        ### Request body recorded for playback ():\r\n\r\n```\r\n\r\n```\r\n\r\n\
+        ### Response headers recorded for playback:\r\n\r\n```\r\n```\r\n\r\n\
+        ### Response body recorded for playback (200: text/plain):\r\n\r\n```\r\nsunny\r\n```\r\n";
+
+    #[test]
+    fn embedded_markdown_is_played_back_but_not_saved() {
+        TestSession::before_test(ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(EmbeddedInteractionManager::new(EMBEDDED_MARKDOWN)),
+        ));
+        let response = client::get("/weather");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "sunny");
+
+        let manager = EmbeddedInteractionManager::from_bytes(EMBEDDED_MARKDOWN.as_bytes());
+        let interactions = manager.load_interactions().unwrap();
+        assert!(manager.check_data_unchanged(&interactions).is_ok());
+        assert!(manager.save_interactions(&interactions).is_err());
+    }
+
+    #[test]
+    fn incomplete_requests_time_out() {
+        let mut config = playback_configuration(vec![interaction("POST", "/upload", "")]);
+        config.set_server_request_timeout(Duration::from_millis(100));
+
+        TestSession::before_test(config);
+        client::send_raw(
+            "POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nabc",
+        );
+        let error = TestSession::after_test().unwrap_err();
+
+        assert!(error
+            .to_string()
+            .contains("didn't finish sending the request POST /upload"));
+    }
+
+    #[test]
+    fn connection_close_delimited_bodies_are_played_back_with_a_length() {
+        let path = "../target/test_connection_close.md";
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Record,
+            Box::new(MarkdownInteractionManager::new(path)),
+        );
+        config.set_domain_name(upstream::serve_raw(
+            61539,
+            "HTTP/1.0 200 OK\r\ncontent-type: text/plain\r\n\r\nuntil the connection closes",
+        ));
+
+        TestSession::before_test(config);
+        let recorded = client::get("/legacy");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(recorded.body, "until the connection closes");
+        assert!(!std::fs::read_to_string(path)
+            .unwrap()
+            .to_lowercase()
+            .contains("content-length"));
+
+        TestSession::before_test(ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(MarkdownInteractionManager::new(path)),
+        ));
+        let played_back = client::send_raw("GET /legacy HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        TestSession::after_test().unwrap();
+
+        assert!(played_back.contains("content-length: 27\r\n"));
+        assert!(played_back.ends_with("\r\n\r\nuntil the connection closes"));
+    }
+
+    #[test]
+    fn custom_reason_phrases_can_be_played_back() {
+        let path = "../target/test_reason_phrase.md";
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Record,
+            Box::new(MarkdownInteractionManager::new(path)),
+        );
+        config.set_domain_name(upstream::serve_raw(
+            61532,
+            "HTTP/1.1 200 Not Quite OK\r\ncontent-length: 2\r\n\r\nok",
+        ));
+
+        TestSession::before_test(config);
+        let recorded = client::send_raw("GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        TestSession::after_test().unwrap();
+
+        let play_back = |reason_phrases: bool| {
+            let mut config = ServirtiumConfiguration::new(
+                ServirtiumMode::Playback,
+                Box::new(MarkdownInteractionManager::new(path)),
+            );
+            config.set_playback_reason_phrases(reason_phrases);
+
+            TestSession::before_test(config);
+            let response = client::send_raw("GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n");
+            TestSession::after_test().unwrap();
+            response
+        };
+
+        assert!(recorded.starts_with("HTTP/1.1 200 Not Quite OK\r\n"));
+        assert!(std::fs::read_to_string(path)
+            .unwrap()
+            .contains("### Response body recorded for playback (200 Not Quite OK: ):"));
+        assert!(play_back(true).starts_with("HTTP/1.1 200 Not Quite OK\r\n"));
+        assert!(play_back(false).starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn unmatched_requests_get_the_default_response() {
+        let mut config = playback_configuration(vec![interaction("GET", "/recorded", "recorded")]);
+        config.set_no_match_behavior(NoMatchBehavior::CloseConnection);
+        let mut not_found = ResponseData {
+            status_code: 404,
+            reason_phrase: None,
+            headers: HashMap::new(),
+            body: String::from(r#"{"error": "not found"}"#),
+        };
+        not_found.headers.insert(
+            String::from("content-type"),
+            String::from("application/json"),
+        );
+        config.set_playback_default_response(not_found);
+
+        TestSession::before_test(config);
+        let recorded = client::get("/recorded");
+        let unknown = client::get("/unknown");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(recorded.body, "recorded");
+        assert_eq!(unknown.status_code, 404);
+        assert_eq!(unknown.headers["content-type"], ["application/json"]);
+        assert_eq!(unknown.body, r#"{"error": "not found"}"#);
+    }
+
+    #[test]
+    fn unmatched_request_can_close_the_connection() {
+        let mut config = playback_configuration(vec![interaction("GET", "/recorded", "")]);
+        config.set_no_match_behavior(NoMatchBehavior::CloseConnection);
+
+        TestSession::before_test(config);
+        let response = client::try_request("GET", "/unknown", &[], "");
+        let result = TestSession::after_test();
+
+        assert!(response.is_err());
+        assert!(result.is_ok());
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Temperature {
+        city: String,
+        celsius: i32,
+    }
+
+    #[test]
+    fn bodies_are_deserialized_according_to_the_content_type() {
+        let mut json = interaction("GET", "/", r#"{"city": "Oslo", "celsius": -3}"#).response_data;
+        json.headers.insert(
+            String::from("Content-Type"),
+            String::from("application/json; charset=utf-8"),
+        );
+
+        let mut xml =
+            interaction("GET", "/", "<t><city>Oslo</city><celsius>-3</celsius></t>").response_data;
+        xml.headers
+            .insert(String::from("content-type"), String::from("text/xml"));
+
+        let expected = Temperature {
+            city: String::from("Oslo"),
+            celsius: -3,
+        };
+        assert_eq!(json.json::<Temperature>().unwrap(), expected);
+        assert_eq!(xml.xml::<Temperature>().unwrap(), expected);
+        assert!(xml.json::<Temperature>().is_err());
+    }
+
+    #[test]
+    fn vary_headers_select_the_recorded_variant() {
+        let variant = |language: &str, body: &str| {
+            let mut data = interaction("GET", "/greeting", body);
+            data.request_data
+                .headers
+                .insert(String::from("accept-language"), String::from(language));
+            data.response_data
+                .headers
+                .insert(String::from("Vary"), String::from("Accept-Language"));
+            data
+        };
+
+        let variants = vec![variant("en", "hello"), variant("fr", "salut")];
+
+        TestSession::before_test(playback_configuration(variants.clone()));
+        let response = client::request("GET", "/greeting", &[("Accept-Language", "fr")], "");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "salut");
+
+        let mut config = playback_configuration(variants);
+        config
+            .match_configuration_mut()
+            .set_match_vary_headers(false);
+
+        TestSession::before_test(config);
+        let response = client::request("GET", "/greeting", &[("Accept-Language", "fr")], "");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "hello");
+    }
+
+    #[test]
+    fn identical_requests_are_recorded_once() {
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name(upstream::serve(61508, echo_path));
+        config.set_dedupe_interactions(true);
+
+        TestSession::before_test(config);
+        client::get("/status");
+        client::get("/status");
+        client::request("POST", "/status", &[], "");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(manager.interactions().len(), 2);
+
+        let mut config = ServirtiumConfiguration::new(ServirtiumMode::Playback, Box::new(manager));
+        config.set_dedupe_interactions(true);
+
+        TestSession::before_test(config);
+        let responses = [client::get("/status"), client::get("/status")];
+        TestSession::after_test().unwrap();
+
+        assert!(responses.iter().all(|response| response.body == "/status"));
+    }
+
+    #[test]
+    fn path_patterns_match_a_family_of_requests() {
+        let mut config = playback_configuration(vec![
+            interaction("GET", "/users/{id}", "user"),
+            interaction("GET", "/users/me", "me"),
+        ]);
+        config
+            .match_configuration_mut()
+            .set_match_path_patterns(true);
+
+        // the pattern isn't used up, but an unused interaction is preferred
+        TestSession::before_test(config);
+        let responses = [
+            client::get("/users/1"),
+            client::get("/users/2"),
+            client::get("/users/me"),
+            client::get("/users/1"),
+        ];
+        TestSession::after_test().unwrap();
+
+        let bodies = responses
+            .iter()
+            .map(|r| r.body.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(bodies, vec!["user", "user", "me", "user"]);
+
+        let mut match_configuration = MatchConfiguration::new();
+        assert!(!match_configuration.uris_match("/users/{id}", "/users/1"));
+        match_configuration.set_match_path_patterns(true);
+        assert!(match_configuration.uris_match("/users/{id}?page=2", "/users/1?page=2"));
+        assert!(!match_configuration.uris_match("/users/{id}", "/users/1/orders"));
+        assert!(!match_configuration.uris_match("/users/{id}", "/users/"));
+    }
+
+    #[test]
+    fn json_request_bodies_are_matched_semantically() {
+        let json_request = |body: &str| {
+            let mut request_data = interaction("POST", "/search", "").request_data;
+            request_data.headers.insert(
+                String::from("content-type"),
+                String::from("application/json"),
+            );
+            request_data.body = String::from(body);
+            request_data
+        };
+        let mut first = interaction("POST", "/search", "first");
+        first.request_data = json_request(r#"{"query": "a", "page": 1}"#);
+        let mut second = interaction("POST", "/search", "second");
+        second.request_data = json_request(r#"{"query": "b", "page": 1}"#);
+
+        let mut config = playback_configuration(vec![first, second]);
+        config
+            .match_configuration_mut()
+            .set_match_request_body(true);
+        let handle = PlaybackHandle::new(config);
+
+        let response_data = handle
+            .playback_response(&json_request("{\n  \"page\": 1,\n  \"query\": \"b\"\n}"))
+            .unwrap();
+        assert_eq!(response_data.body, "second");
+
+        let error = handle
+            .playback_response(&json_request(r#"{"query": "c", "page": 1}"#))
+            .unwrap_err();
+        assert!(error.to_string().contains("| body |"));
+
+        let mut plain_text = interaction("POST", "/search", "").request_data;
+        plain_text.body = String::from(r#"{"page": 1, "query": "a"}"#);
+        assert!(!MatchConfiguration::bodies_match(
+            &json_request(r#"{"query": "a", "page": 1}"#),
+            &plain_text
+        ));
+    }
+
+    #[test]
+    fn missing_markdown_suggests_recording_first() {
+        let error = MarkdownInteractionManager::new("../target/test_missing.md")
+            .load_interactions()
+            .unwrap_err();
+
+        let message = error.to_string();
+        assert!(message.contains("../target/test_missing.md"));
+        assert!(message.contains("record mode"));
+    }
+
+    #[test]
+    fn json_keys_are_masked_at_any_depth() {
+        let mut builder = MutationsBuilder::default();
+        builder.json_mask_keys(vec!["token", "email"]);
+
+        let mut response_data = interaction(
+            "GET",
+            "/",
+            r#"{"token": "abc", "users": [{"email": "a@b.c", "name": "A"}, {"email": null}]}"#,
+        )
+        .response_data;
+        let mut plain_text = response_data.clone();
+        plain_text.body = String::from("token: abc");
+
+        for mutation in builder.into_response_mutations() {
+            mutation.mutate(&mut response_data);
+            mutation.mutate(&mut plain_text);
+        }
+
+        assert_eq!(
+            response_data.body,
+            r#"{"token":"MASKED","users":[{"email":"MASKED","name":"A"},{"email":"MASKED"}]}"#
+        );
+        assert_eq!(plain_text.body, "token: abc");
+    }
+
+    #[test]
+    fn saved_request_mutations_update_the_content_length() {
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name(upstream::serve(61540, hello_world));
+        config.add_saved_request_mutations(|builder| builder.json_mask_keys(vec!["api_key"]));
+
+        TestSession::before_test(config);
+        client::request(
+            "POST",
+            "/todos",
+            &[("Content-Type", "application/json")],
+            r#"{"api_key":"secret-api-key","title":"Write the docs"}"#,
+        );
+        TestSession::after_test().unwrap();
+
+        let request_data = &manager.interactions()[0].request_data;
+        assert_eq!(
+            request_data.body,
+            r#"{"api_key":"MASKED","title":"Write the docs"}"#
+        );
+        assert_eq!(
+            request_data.headers["content-length"],
+            request_data.body.len().to_string()
+        );
+    }
+
+    #[test]
+    fn only_the_capture_group_is_masked() {
+        let mut builder = MutationsBuilder::default();
+        builder
+            .body_mask_regex_group(
+                regex::Regex::new(r#""token":\s*"(?P<token>[^"]*)""#).unwrap(),
+                "token",
+                "MASKED",
+            )
+            .body_mask_regex_group(regex::Regex::new(r"secret=(\w+)").unwrap(), "1", "***");
+
+        let mut response_data = interaction(
+            "GET",
+            "/",
+            r#"{"token": "abc", "nested": {"token":"def"}, "url": "/?secret=xyz&a=1"}"#,
+        )
+        .response_data;
+        for mutation in builder.into_response_mutations() {
+            mutation.mutate(&mut response_data);
+        }
+
+        assert_eq!(
+            response_data.body,
+            r#"{"token": "MASKED", "nested": {"token":"MASKED"}, "url": "/?secret=***&a=1"}"#
+        );
+    }
+
+    #[test]
+    fn only_the_given_cookies_are_redacted() {
+        let mut builder = MutationsBuilder::default();
+        builder.redact_cookies(vec!["session"]);
+
+        let mut request_data = interaction("GET", "/", "").request_data;
+        request_data.headers.insert(
+            String::from("Cookie"),
+            String::from("theme=dark; session=abc; lang=en"),
+        );
+        let mut response_data = interaction("GET", "/", "").response_data;
+        response_data.headers.insert(
+            String::from("set-cookie"),
+            String::from("session=def; Path=/; HttpOnly\ntheme=light; Max-Age=60"),
+        );
+
+        for mutation in builder.into_request_mutations() {
+            mutation.mutate(&mut request_data);
+        }
+        let mut builder = MutationsBuilder::default();
+        builder.redact_cookies(vec!["session"]);
+        for mutation in builder.into_response_mutations() {
+            mutation.mutate(&mut response_data);
+        }
+
+        assert_eq!(
+            request_data.headers["Cookie"],
+            "theme=dark; session=REDACTED; lang=en"
+        );
+        assert_eq!(
+            response_data.headers["set-cookie"],
+            "session=REDACTED; Path=/; HttpOnly\ntheme=light; Max-Age=60"
+        );
+    }
+
+    #[test]
+    fn http_version_is_written_and_played_back() {
+        let path = "../target/test_http_version.md";
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Record,
+            Box::new(MarkdownInteractionManager::new(path).with_http_version(true)),
+        );
+        config.set_domain_name(upstream::serve(61509, echo_path));
+
+        TestSession::before_test(config);
+        client::get("/version");
+        TestSession::after_test().unwrap();
+
+        let markdown = std::fs::read_to_string(path).unwrap();
+        assert!(markdown.starts_with("## Interaction 0: GET /version HTTP/1.1\r\n"));
+
+        let interactions = MarkdownInteractionManager::new(path)
+            .load_interactions()
+            .unwrap();
+        assert_eq!(interactions[0].request_data.uri, "/version");
+        assert_eq!(
+            interactions[0].request_data.http_version.as_deref(),
+            Some("HTTP/1.1")
+        );
+
+        let without_version = markdown.replacen(" HTTP/1.1", "", 1);
+        std::fs::write(path, without_version).unwrap();
+
+        TestSession::before_test(ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(MarkdownInteractionManager::new(path)),
+        ));
+        let response = client::get("/version");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "/version");
+    }
+
+    #[test]
+    fn markdown_with_windows_line_endings_has_no_carriage_returns_in_headers() {
+        let markdown = "## Interaction 0: GET /weather
+
+### Request headers recorded for playback:
+
+```
+accept: application/json
+x-empty:
+```
+
+### Request body recorded for playback ():
+
+```
+
+```
+
+### Response headers recorded for playback:
+
+```
+content-type: application/json
+set-cookie: a=1
+set-cookie: b=2
+```
+
+### Response body recorded for playback (200: application/json):
+
+```
+{\"temperature\": 21}
+```
+";
+        std::fs::write("../target/test_lf.md", markdown).unwrap();
+        std::fs::write("../target/test_crlf.md", markdown.replace('\n', "\r\n")).unwrap();
+
+        let manager = MarkdownInteractionManager::new("../target/test_crlf.md");
+        let interactions = manager.load_interactions().unwrap();
+        let lf_interactions = MarkdownInteractionManager::new("../target/test_lf.md")
+            .load_interactions()
+            .unwrap();
+
+        let request_headers = &interactions[0].request_data.headers;
+        let response_headers = &interactions[0].response_data.headers;
+        assert_eq!(request_headers["accept"], "application/json");
+        assert_eq!(request_headers["x-empty"], "");
+        assert_eq!(response_headers["set-cookie"], "a=1\nb=2");
+        assert!(request_headers
+            .values()
+            .chain(response_headers.values())
+            .all(|value| !value.contains('\r')));
+        assert!(manager.check_data_unchanged(&lf_interactions).is_ok());
+
+        TestSession::before_test(ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(manager),
+        ));
+        let response = client::get("/weather");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.headers["content-type"], ["application/json"]);
+        assert_eq!(response.headers["set-cookie"], ["a=1", "b=2"]);
+        assert_eq!(response.body, r#"{"temperature": 21}"#);
+    }
+
+    #[test]
+    fn non_utf8_bodies_follow_the_configured_policy() {
+        let path = "../target/test_non_utf8.md";
+        let mut markdown = b"## Interaction 0: GET /latin1
+
+### Request headers recorded for playback:
+
+```
+```
+
+### Request body recorded for playback ():
+
+```
+
+```
+
+### Response headers recorded for playback:
+
+```
+content-type: text/plain
+```
+
+### Response body recorded for playback (200: text/plain):
+
+```
+caf"
+        .to_vec();
+        let invalid_byte = markdown.len();
+        markdown.extend_from_slice(b"\xe9\n```\n");
+        std::fs::write(path, markdown).unwrap();
+
+        let error = MarkdownInteractionManager::new(path)
+            .load_interactions()
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains(&format!("isn't valid UTF-8 at byte {}", invalid_byte)));
+
+        let manager = MarkdownInteractionManager::new(path)
+            .with_non_utf8_body_policy(NonUtf8BodyPolicy::Lossy);
+        let indexed = manager.index_interactions().unwrap();
+        assert_eq!(
+            manager
+                .load_indexed_interaction(&indexed[0])
+                .unwrap()
+                .response_data
+                .body,
+            "caf\u{fffd}"
+        );
+
+        TestSession::before_test(ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(manager),
+        ));
+        let response = client::get("/latin1");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "caf\u{fffd}");
+    }
+
+    #[test]
+    fn base64_bodies_are_decoded_with_the_configured_policy() {
+        let path = "../target/test_base64_body.md";
+        let markdown = |content_type: &str, body: &str| {
+            format!(
+                "## Interaction 0: GET /latin1\n\n\
+                ### Request headers recorded for playback:\n\n```\n```\n\n\
+                ### Request body recorded for playback ():\n\n```\n\n```\n\n\
+                ### Response headers recorded for playback:\n\n```\ncontent-type: {}\n```\n\n\
+                ### Response body recorded for playback (200: {}):\n\n```base64\n{}\n```\n",
+                content_type, content_type, body
+            )
+        };
+        let playback = |non_utf8_body_policy: Option<NonUtf8BodyPolicy>| {
+            let mut config = ServirtiumConfiguration::new(
+                ServirtiumMode::Playback,
+                Box::new(MarkdownInteractionManager::new(path)),
+            );
+            if let Some(non_utf8_body_policy) = non_utf8_body_policy {
+                config.set_non_utf8_body_policy(non_utf8_body_policy);
+            }
+
+            PlaybackHandle::new(config)
+                .playback_response(&interaction("GET", "/latin1", "").request_data)
+        };
+
+        std::fs::write(path, markdown("text/plain; charset=ISO-8859-1", "Y2Fm6Q==")).unwrap();
+        assert_eq!(playback(None).unwrap().body, "Y2Fm6Q==");
+        assert_eq!(
+            playback(Some(NonUtf8BodyPolicy::Base64)).unwrap().body,
+            "caf\u{e9}"
+        );
+
+        std::fs::write(path, markdown("text/plain", "Y2Fm6Q==")).unwrap();
+        assert!(playback(Some(NonUtf8BodyPolicy::Base64))
+            .unwrap_err()
+            .to_string()
+            .contains("A base64 body of interaction 0 can't be decoded"));
+    }
+
+    #[test]
+    fn xml_is_pretty_printed_and_compared_regardless_of_indentation() {
+        let mut builder = MutationsBuilder::default();
+        builder.pretty_print_xml();
+
+        let mut xml = interaction("GET", "/", "<t><city>Oslo</city><now/></t>");
+        let mut json = interaction("GET", "/", r#"{"city": "Oslo"}"#);
+        for mutation in builder.into_response_mutations() {
+            mutation.mutate(&mut xml.response_data);
+            mutation.mutate(&mut json.response_data);
+        }
+
+        assert_eq!(
+            xml.response_data.body,
+            "<t>\n  <city>Oslo</city>\n  <now/>\n</t>"
+        );
+        assert_eq!(json.response_data.body, r#"{"city": "Oslo"}"#);
+
+        let manager = MemoryInteractionManager::with_interactions(vec![xml]);
+        let unchanged = interaction("GET", "/", "<t>\n<city>Oslo</city> <now/></t>");
+        let changed = interaction("GET", "/", "<t><city>Bergen</city><now/></t>");
+        assert!(manager.check_data_unchanged(&[unchanged]).is_ok());
+        assert!(manager.check_data_unchanged(&[changed]).is_err());
+    }
+
+    #[test]
+    fn self_signed_certificates_are_only_accepted_when_allowed() {
+        let domain_name = upstream::serve_tls(61510, echo_path);
+        let record = |accept_invalid_certs: bool| {
+            let manager = MemoryInteractionManager::new();
+            let mut config =
+                ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+            config.set_domain_name(domain_name.clone());
+            if accept_invalid_certs {
+                config.set_danger_accept_invalid_certs(true);
+            }
+
+            TestSession::before_test(config);
+            let response = client::get("/insecure");
+            (response, TestSession::after_test(), manager.interactions())
+        };
+
+        let (_, result, interactions) = record(false);
+        assert!(result.is_err());
+        assert!(interactions.is_empty());
+
+        let (response, result, interactions) = record(true);
+        result.unwrap();
+        assert_eq!(response.body, "/insecure");
+        assert_eq!(interactions.len(), 1);
+    }
+
+    #[test]
+    fn response_time_is_recorded() {
+        let path = "../target/test_response_time.md";
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Record,
+            Box::new(MarkdownInteractionManager::new(path).with_response_time(true)),
+        );
+        config.set_domain_name(upstream::serve(61511, slow_hello_world));
+
+        TestSession::before_test(config);
+        client::get("/slow");
+        TestSession::after_test().unwrap();
+
+        let interactions = MarkdownInteractionManager::new(path)
+            .load_interactions()
+            .unwrap();
+        assert!(interactions[0].response_time.unwrap() >= Duration::from_millis(50));
+        assert_eq!(interactions[0].request_data.uri, "/slow");
+    }
+
+    #[test]
+    fn external_bodies_are_written_next_to_the_markdown() {
+        let path = "../target/test_external_bodies.md";
+        let mut interactions = vec![
+            interaction("GET", "/small", "{}"),
+            interaction("GET", "/large", &"x".repeat(200)),
+            interaction("GET", "/image", "png"),
+        ];
+        for (index, content_type) in ["application/json", "application/json", "image/png"]
+            .iter()
+            .enumerate()
+        {
+            interactions[index].interaction_number = index;
+            interactions[index]
+                .response_data
+                .headers
+                .insert(String::from("content-type"), String::from(*content_type));
+        }
+
+        let manager = MarkdownInteractionManager::new(path).with_external_bodies(
+            ExternalBodies::new()
+                .with_min_length(100)
+                .with_content_type("image/*"),
+        );
+        manager.save_interactions(&interactions).unwrap();
+
+        let markdown = std::fs::read_to_string(path).unwrap();
+        assert!(markdown.contains("```\r\n{}\r\n```"));
+        assert!(markdown
+            .contains("[test_external_bodies.1.resp.json](test_external_bodies.1.resp.json)"));
+        assert!(markdown
+            .contains("[test_external_bodies.2.resp.body](test_external_bodies.2.resp.body)"));
+        assert!(!markdown.contains(&"x".repeat(200)));
+        assert_eq!(
+            std::fs::read_to_string("../target/test_external_bodies.1.resp.json").unwrap(),
+            "x".repeat(200)
+        );
+
+        for loaded in [
+            manager.load_interactions().unwrap(),
+            MarkdownInteractionManager::new(path)
+                .load_interactions()
+                .unwrap(),
+        ] {
+            let bodies = loaded
+                .iter()
+                .map(|interaction| interaction.response_data.body.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(bodies, ["{}", "x".repeat(200).as_str(), "png"]);
+        }
+        manager.check_data_unchanged(&interactions).unwrap();
+
+        std::fs::remove_file("../target/test_external_bodies.2.resp.body").unwrap();
+        assert!(manager
+            .load_interactions()
+            .unwrap_err()
+            .to_string()
+            .contains(
+                "test_external_bodies.2.resp.body linked from the markdown file doesn't exist"
+            ));
+    }
+
+    #[test]
+    fn redirects_are_followed_according_to_the_policy() {
+        let path = "../target/test_redirects.md";
+        let domain_name = upstream::serve(61537, redirecting);
+        upstream::serve_raw(
+            61538,
+            "HTTP/1.1 200 OK\r\ncontent-length: 9\r\n\r\nelsewhere",
+        );
+        let record = |redirect_policy: RedirectPolicy, requests: &[(&str, &str)]| {
+            let mut config = ServirtiumConfiguration::new(
+                ServirtiumMode::Record,
+                Box::new(MarkdownInteractionManager::new(path)),
+            );
+            config.set_domain_name(domain_name.clone());
+            config.set_redirect_policy(redirect_policy);
+
+            TestSession::before_test(config);
+            let responses = requests
+                .iter()
+                .map(|(method, path)| {
+                    let response = client::request(method, path, &[], "");
+                    (response.status_code, response.body)
+                })
+                .collect::<Vec<_>>();
+            TestSession::after_test().unwrap();
+            responses
+        };
+
+        assert_eq!(
+            record(RedirectPolicy::Record, &[("GET", "/old")]),
+            [(302, String::new())]
+        );
+        assert_eq!(
+            record(
+                RedirectPolicy::FollowSameOrigin,
+                &[("GET", "/old"), ("POST", "/form"), ("GET", "/away")]
+            ),
+            [
+                (200, String::from("GET /new")),
+                (200, String::from("GET /new")),
+                (302, String::new())
+            ]
+        );
+        let markdown = std::fs::read_to_string(path).unwrap();
+        assert!(markdown.contains("## Interaction 0: GET /old"));
+        assert!(markdown.contains("## Interaction 1: POST /form"));
+        assert_eq!(
+            record(RedirectPolicy::FollowAll, &[("GET", "/away")]),
+            [(200, String::from("elsewhere"))]
+        );
+    }
+
+    #[test]
+    fn recorded_response_times_are_waited_for_in_playback() {
+        let path = "../target/test_playback_response_time.md";
+        let interaction_markdown = |number: usize, uri: &str, response_time: &str| {
+            format!(
+                "## Interaction {}: GET {}
+
+{}### Request headers recorded for playback:
+
+```
+```
+
+### Request body recorded for playback ():
+
+```
+
+```
+
+### Response headers recorded for playback:
+
+```
+content-type: text/plain
+```
+
+### Response body recorded for playback (200: text/plain):
+
+```
+ok
+```
+
+",
+                number, uri, response_time
+            )
+        };
+        let markdown = interaction_markdown(0, "/slow", "Response time: 300 ms\n\n")
+            + &interaction_markdown(1, "/fast", "");
+        std::fs::write(path, markdown).unwrap();
+
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(MarkdownInteractionManager::new(path)),
+        );
+        config.set_playback_response_times(true);
+
+        TestSession::before_test(config);
+        let start = std::time::Instant::now();
+        client::get("/slow");
+        let slow_time = start.elapsed();
+        let start = std::time::Instant::now();
+        client::get("/fast");
+        let fast_time = start.elapsed();
+        TestSession::after_test().unwrap();
+
+        assert!(slow_time >= Duration::from_millis(300));
+        assert!(fast_time < Duration::from_millis(300));
+    }
+
+    #[test]
+    fn base_url_placeholder_is_written_and_removed_on_load() {
+        let path = "../target/test_base_url_placeholder.md";
+        let manager =
+            || MarkdownInteractionManager::new(path).with_base_url_placeholder("{{BASE_URL}}");
+        let mut config = ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager()));
+        config.set_domain_name(upstream::serve(61517, echo_path));
+
+        TestSession::before_test(config);
+        client::get("/users?page=2");
+        TestSession::after_test().unwrap();
+
+        let markdown = std::fs::read_to_string(path).unwrap();
+        assert!(markdown.starts_with("## Interaction 0: GET {{BASE_URL}}/users?page=2\r\n"));
+
+        // fixtures of other implementations may contain the real domain instead
+        let absolute = markdown.replacen("{{BASE_URL}}", "https://example.com", 1);
+        std::fs::write(path, absolute).unwrap();
+
+        TestSession::before_test(ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(manager()),
+        ));
+        let response = client::get("/users?page=2");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "/users");
+    }
+
+    #[test]
+    fn interactions_are_counted_without_loading_them() {
+        let path = "../target/test_count_interactions.md";
+        MarkdownInteractionManager::new(path)
+            .save_interactions(&[
+                interaction("GET", "/first", "## Interaction 5: GET / in a body"),
+                interaction("POST", "/second", ""),
+            ])
+            .unwrap();
+
+        assert_eq!(
+            MarkdownInteractionManager::count_interactions(path).unwrap(),
+            2
+        );
+        assert!(
+            MarkdownInteractionManager::count_interactions("../target/missing.md")
+                .unwrap_err()
+                .to_string()
+                .contains("doesn't exist")
+        );
+    }
+
+    #[test]
+    fn indexed_interactions_are_loaded_one_at_a_time() {
+        let path = "../target/test_index_interactions.md";
+        let manager = MarkdownInteractionManager::new(path);
+        let mut interactions = vec![
+            interaction("GET", "/users", "## Interaction 9: GET /fake"),
+            interaction("POST", "/users", "created"),
+            interaction("GET", "/orders?page=2", "orders"),
+        ];
+        for (number, interaction) in interactions.iter_mut().enumerate() {
+            interaction.interaction_number = number;
+        }
+        manager.save_interactions(&interactions).unwrap();
+
+        let index = manager.index_interactions().unwrap();
+        let signatures = index
+            .iter()
+            .map(|indexed| format!("{} {}", indexed.method, indexed.uri))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            signatures,
+            vec!["GET /users", "POST /users", "GET /orders?page=2"]
+        );
+
+        let orders = index
+            .iter()
+            .find(|indexed| indexed.uri.starts_with("/orders"))
+            .unwrap();
+        let loaded = manager.load_indexed_interaction(orders).unwrap();
+        assert_eq!(loaded.interaction_number, 2);
+        assert_eq!(loaded.response_data.body, "orders");
+        assert_eq!(
+            manager
+                .load_indexed_interaction(&index[0])
+                .unwrap()
+                .response_data
+                .body,
+            "## Interaction 9: GET /fake"
+        );
+    }
+
+    #[test]
+    fn manager_errors_convert_into_the_crate_error() {
+        fn load(path: &str) -> Result<usize, servirtium::Error> {
+            MarkdownInteractionManager::new(path).load_interactions()?;
+            Ok(MarkdownInteractionManager::count_interactions(path)?)
+        }
+
+        let error = load("../target/missing.md").unwrap_err();
+
+        assert!(matches!(error, servirtium::Error::InteractionManager(_)));
+        assert!(error.to_string().contains("doesn't exist"));
+    }
+
+    #[test]
+    fn raw_bodies_are_dumped_next_to_the_markdown() {
+        let path = "../target/test_dump_raw.md";
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Record,
+            Box::new(MarkdownInteractionManager::new(path)),
+        );
+        config.set_domain_name(upstream::serve(61519, latin1_body));
+        config.set_dump_raw(true);
+
+        TestSession::before_test(config);
+        let response = client::request("POST", "/menu", &[], "crème");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "caf\u{fffd}");
+        assert_eq!(
+            std::fs::read("../target/test_dump_raw.0.req.bin").unwrap(),
+            "crème".as_bytes()
+        );
+        assert_eq!(
+            std::fs::read("../target/test_dump_raw.0.resp.bin").unwrap(),
+            b"caf\xe9"
+        );
+    }
+
+    #[test]
+    fn charset_of_bodies_round_trips() {
+        let path = "../target/test_charset.md";
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Record,
+            Box::new(MarkdownInteractionManager::new(path)),
+        );
+        config.set_domain_name(upstream::serve(61525, latin1_text));
+
+        TestSession::before_test(config);
+        client::get("/menu");
+        TestSession::after_test().unwrap();
+
+        let markdown = std::fs::read_to_string(path).unwrap();
+        assert!(markdown.contains("café crème"));
+
+        let config = ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(MarkdownInteractionManager::new(path)),
+        );
+        let request_data = interaction("GET", "/menu", "").request_data;
+
+        TestSession::before_test(config);
+        let response_data = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(ReqwestHttpClient::new().make_request(client::SERVIRTIUM_URL, &request_data));
+        TestSession::after_test().unwrap();
+
+        let response_data = response_data.unwrap();
+        assert_eq!(response_data.body, "café crème");
+        assert_eq!(response_data.headers["content-length"], "10");
+    }
+
+    #[test]
+    fn chunked_responses_are_played_back_with_a_content_length() {
+        let path = "../target/test_chunked.md";
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Record,
+            Box::new(MarkdownInteractionManager::new(path)),
+        );
+        config.set_domain_name(upstream::serve(61527, chunked_hello_world));
+
+        TestSession::before_test(config);
+        assert_eq!(client::get("/").body, "hello world");
+        TestSession::after_test().unwrap();
+
+        let markdown = std::fs::read_to_string(path).unwrap();
+        assert!(markdown.contains("transfer-encoding: chunked"));
+
+        TestSession::before_test(ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(MarkdownInteractionManager::new(path)),
+        ));
+        let response = client::get("/");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "hello world");
+        assert_eq!(response.headers["content-length"], vec!["11"]);
+        assert!(!response.headers.contains_key("transfer-encoding"));
+    }
+
+    #[test]
+    fn strict_body_comparison_keeps_surrounding_whitespace() {
+        let path = "../target/test_strict_body_comparison.md";
+        let recorded = [interaction("GET", "/report", "  total: 3\n")];
+        let trimmed = [interaction("GET", "/report", "total: 3")];
+
+        let lenient = MemoryInteractionManager::with_interactions(recorded.to_vec());
+        assert!(lenient.check_data_unchanged(&trimmed).is_ok());
+        let strict = lenient.clone().with_strict_body_comparison(true);
+        assert!(strict.check_data_unchanged(&trimmed).is_err());
+
+        let mut config = playback_configuration(Vec::new());
+        config.set_interaction_manager(Box::new(lenient.clone()));
+        config.set_strict_body_comparison(true);
+        PlaybackHandle::new(config);
+        assert!(lenient.check_data_unchanged(&trimmed).is_err());
+
+        let manager = MarkdownInteractionManager::new(path).with_strict_body_comparison(true);
+        manager.save_interactions(&recorded).unwrap();
+        assert_eq!(
+            manager.load_interactions().unwrap()[0].response_data.body,
+            "  total: 3\n"
+        );
+        assert!(manager.check_data_unchanged(&recorded).is_ok());
+        assert!(manager.check_data_unchanged(&trimmed).is_err());
+    }
+
+    #[test]
+    fn strict_body_comparison_reports_appended_characters() {
+        let recorded = [interaction("GET", "/report", "abc")];
+        let manager = MemoryInteractionManager::with_interactions(recorded.to_vec())
+            .with_strict_body_comparison(true);
+
+        for (body, column) in [("abcdef", 4), ("abc  \n", 4), ("ab", 3)] {
+            let error = manager
+                .check_data_unchanged(&[interaction("GET", "/report", body)])
+                .unwrap_err();
+
+            assert!(
+                error
+                    .to_string()
+                    .contains(&format!("Bodies differ at line 1, column {}", column)),
+                "{}",
+                error
+            );
+        }
+        assert!(manager.check_data_unchanged(&recorded).is_ok());
+    }
+
+    #[test]
+    fn bodies_are_fenced_with_their_language() {
+        let path = "../target/test_body_language.md";
+        let mut users = interaction("GET", "/users", r#"{"name": "Ann"}"#);
+        users.response_data.headers.insert(
+            String::from("content-type"),
+            String::from("application/json; charset=utf-8"),
+        );
+
+        for strict in [false, true] {
+            let manager = MarkdownInteractionManager::new(path)
+                .with_body_language(true)
+                .with_strict_body_comparison(strict);
+            manager.save_interactions(&[users.clone()]).unwrap();
+
+            let markdown = std::fs::read_to_string(path).unwrap();
+            assert!(markdown.contains("```json\r\n{\"name\": \"Ann\"}\r\n```"));
+            assert!(markdown.contains("### Request body recorded for playback ():\r\n\r\n```\r\n"));
+
+            let loaded = manager.load_interactions().unwrap();
+            assert_eq!(loaded[0].response_data.body, r#"{"name": "Ann"}"#);
+            assert!(manager.check_data_unchanged(&[users.clone()]).is_ok());
+        }
+    }
+
+    #[test]
+    fn a_second_server_address_is_rejected() {
+        // make sure the server is running on the default address, whichever test starts it
+        TestSession::before_test(playback_configuration(Vec::new()));
+        TestSession::after_test().unwrap();
+
+        let mut config = playback_configuration(Vec::new());
+        config.set_server_address(([127, 0, 0, 1], 61526));
+        TestSession::before_test(config);
+        let error = TestSession::after_test().unwrap_err();
+
+        assert!(matches!(
+            error,
+            servirtium::Error::ServerAddressConflict { .. }
+        ));
+        assert!(error.to_string().contains("127.0.0.1:61417"));
+        assert!(error.to_string().contains("single server per process"));
+    }
+
+    #[test]
+    fn playback_response_callback_runs_last() {
+        let mut config = playback_configuration(vec![interaction("GET", "/users", "users")]);
+        config.add_playback_response_mutations(|builder| builder.body_replace("users", "people"));
+        config.set_on_playback_response(Box::new(|response_data| {
+            let signature = format!("sha-{}", response_data.body);
+            response_data
+                .headers
+                .insert(String::from("x-signature"), signature);
+        }));
+
+        TestSession::before_test(config);
+        let response = client::get("/users");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "people");
+        assert_eq!(response.headers["x-signature"], vec!["sha-people"]);
+    }
+
+    #[test]
+    fn session_timeout_turns_a_hang_into_a_failure() {
+        TestSession::before_test(playback_configuration(Vec::new()));
+
+        // the running test never finishes from the point of view of the second one
+        let mut config = playback_configuration(Vec::new());
+        config.set_session_timeout(Duration::from_millis(100));
+        let panic = std::thread::spawn(move || TestSession::before_test(config))
+            .join()
+            .unwrap_err();
+
+        TestSession::after_test().unwrap();
+
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.contains("probably deadlocked"));
+        assert!(message.contains("the previous test to finish"));
+    }
+
+    #[test]
+    fn playback_handle_answers_without_the_server() {
+        let mut config = playback_configuration(vec![interaction("GET", "/users", "users")]);
+        config.set_require_playback_used(true);
+        config.add_stub(
+            "get",
+            "/token",
+            ResponseData {
+                status_code: 201,
+                reason_phrase: None,
+                headers: HashMap::new(),
+                body: String::from("stubbed"),
+            },
+        );
+        let handle = PlaybackHandle::new(config);
+
+        let users = interaction("GET", "/users", "").request_data;
+        assert_eq!(handle.playback_response(&users).unwrap().body, "users");
+        assert!(handle.playback_response(&users).is_err());
+
+        let token = interaction("GET", "/token", "").request_data;
+        assert_eq!(handle.playback_response(&token).unwrap().status_code, 201);
+        assert!(handle.finish().is_ok());
+
+        let mut config = playback_configuration(vec![interaction("GET", "/users", "users")]);
+        config.set_require_playback_used(true);
+        assert!(matches!(
+            PlaybackHandle::new(config).finish(),
+            Err(servirtium::Error::PlaybackUnused)
+        ));
+    }
+
+    #[test]
+    fn form_bodies_are_written_a_pair_per_line() {
+        let path = "../target/test_form_bodies.md";
+        let mut signup = interaction("POST", "/signup", "");
+        signup.request_data.headers.insert(
+            String::from("content-type"),
+            String::from("application/x-www-form-urlencoded"),
+        );
+        signup.request_data.body = String::from("name=Ann%20Lee&city=Paris");
+        let mut search = interaction("POST", "/search", "");
+        search.request_data.body = String::from("a=1&b=2");
+        let interactions = [signup, search];
+
+        let manager = MarkdownInteractionManager::new(path).with_readable_form_bodies(true);
+        manager.save_interactions(&interactions).unwrap();
+
+        let markdown = std::fs::read_to_string(path).unwrap();
+        assert!(markdown.contains("```\r\nname=Ann%20Lee\r\ncity=Paris\r\n```"));
+        assert!(markdown.contains("```\r\na=1&b=2\r\n```"));
+
+        let loaded = manager.load_interactions().unwrap();
+        assert_eq!(loaded[0].request_data.body, "name=Ann%20Lee&city=Paris");
+        assert_eq!(loaded[1].request_data.body, "a=1&b=2");
+        assert!(manager.check_data_unchanged(&interactions).is_ok());
+    }
+
+    #[test]
+    fn large_response_bodies_are_streamed_to_a_linked_file() {
+        let path = "../target/test_streamed_body.md";
+        let domain_name = upstream::serve(61541, hello_world);
+        let record = || {
+            let mut config = ServirtiumConfiguration::new(
+                ServirtiumMode::Record,
+                Box::new(MarkdownInteractionManager::new(path)),
+            );
+            config.set_domain_name(domain_name.clone());
+            config.set_record_streaming_threshold(Some(5));
+            config.set_fail_if_markdown_changed(true);
+
+            TestSession::before_test(config);
+            let response = client::get("/");
+            TestSession::after_test().unwrap();
+
+            response
+        };
+
+        let _ = std::fs::remove_file(path);
+        assert_eq!(record().body, "");
+        // nothing changed, so recording again doesn't fail the test
+        record();
+
+        let markdown = std::fs::read_to_string(path).unwrap();
+        assert!(markdown.contains("content-length: 11"));
+        assert!(
+            markdown.contains("[test_streamed_body.0.resp.body](test_streamed_body.0.resp.body)")
+        );
+        assert_eq!(
+            std::fs::read_to_string("../target/test_streamed_body.0.resp.body").unwrap(),
+            "hello world"
+        );
+        assert!(!std::path::Path::new("../target/test_streamed_body.0.resp.body.part").exists());
+
+        TestSession::before_test(ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(MarkdownInteractionManager::new(path)),
+        ));
+        let response = client::get("/");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "hello world");
+        assert_eq!(response.headers["content-length"], vec!["11"]);
+    }
+
+    #[test]
+    fn changes_to_a_previous_recording_are_reported() {
+        let manager = MemoryInteractionManager::new();
+        let domain_name = upstream::serve(61520, echo_path);
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let record = |path: &str, fail_if_markdown_changed: bool| {
+            let mut config =
+                ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+            config.set_domain_name(domain_name.clone());
+            config.set_fail_if_markdown_changed(fail_if_markdown_changed);
+            let changes = changes.clone();
+            config.set_on_recording_changed(Box::new(move |summary| {
+                changes.lock().unwrap().push(String::from(summary))
+            }));
+
+            TestSession::before_test(config);
+            client::get(path);
+            TestSession::after_test()
+        };
+
+        record("/first", false).unwrap();
+        record("/first", false).unwrap();
+        assert!(changes.lock().unwrap().is_empty());
+
+        record("/second", false).unwrap();
+        assert_eq!(changes.lock().unwrap().len(), 1);
+        assert!(changes.lock().unwrap()[0].contains("Bodies differ"));
+
+        let error = record("/first", true).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("differ from the previous recording"));
+        assert_eq!(changes.lock().unwrap().len(), 1);
+        assert_eq!(manager.interactions()[0].response_data.body, "/second");
+
+        record("/first", false).unwrap();
+        assert_eq!(manager.interactions()[0].response_data.body, "/first");
+
+        manager
+            .save_interactions(&[interaction("GET", "/first", "€€€€")])
+            .unwrap();
+        record("/first", false).unwrap();
+        assert_eq!(changes.lock().unwrap().len(), 3);
+        assert!(changes.lock().unwrap()[2].contains("Bodies differ"));
+    }
+
+    #[test]
+    fn requests_outside_the_allowed_paths_are_not_forwarded() {
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Record,
+            Box::new(MemoryInteractionManager::new()),
+        );
+        config.set_domain_name(upstream::serve(61512, echo_path));
+        config.set_allowed_path_prefixes(vec!["/api/"]);
+
+        TestSession::before_test(config);
+        let allowed = client::get("/api/users?page=2");
+        client::get("/favicon.ico");
+        let result = TestSession::after_test();
+
+        assert_eq!(allowed.body, "/api/users");
+        assert!(result.unwrap_err().to_string().contains("/favicon.ico"));
+    }
+
+    #[test]
+    fn encrypted_markdown_is_unreadable_without_the_key() {
+        let path = "../target/test_encrypted.md";
+        let manager = |key_variable: &str| {
+            EncryptedInteractionManager::new(MarkdownInteractionManager::new(path), key_variable)
+        };
+        std::env::set_var("SERVIRTIUM_TEST_KEY", "00".repeat(32));
+        std::env::set_var("SERVIRTIUM_TEST_WRONG_KEY", "01".repeat(32));
+
+        manager("SERVIRTIUM_TEST_KEY")
+            .save_interactions(&[interaction("GET", "/secret", "top secret")])
+            .unwrap();
+
+        let contents = std::fs::read(path).unwrap();
+        assert!(!String::from_utf8_lossy(&contents).contains("top secret"));
+
+        let interactions = manager("SERVIRTIUM_TEST_KEY").load_interactions().unwrap();
+        assert_eq!(interactions[0].response_data.body, "top secret");
+        assert!(manager("SERVIRTIUM_TEST_WRONG_KEY")
+            .load_interactions()
+            .is_err());
+        assert!(manager("SERVIRTIUM_TEST_MISSING_KEY")
+            .load_interactions()
+            .is_err());
+    }
+
+    #[test]
+    fn expect_continue_is_answered_and_not_forwarded() {
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name(upstream::serve(61518, echo_expect));
+
+        TestSession::before_test(config);
+        let (recorded_interim, recorded) = client::post_expecting_continue("/upload", "hello");
+        TestSession::after_test().unwrap();
+
+        assert!(recorded_interim.starts_with("HTTP/1.1 100 Continue"));
+        assert!(recorded.ends_with("\r\n\r\nnone"));
+        assert!(!manager.interactions()[0]
+            .request_data
+            .headers
+            .contains_key("expect"));
+
+        TestSession::before_test(ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(manager),
+        ));
+        let (played_back_interim, played_back) =
+            client::post_expecting_continue("/upload", "hello");
+        TestSession::after_test().unwrap();
+
+        assert!(played_back_interim.starts_with("HTTP/1.1 100 Continue"));
+        assert!(played_back.ends_with("\r\n\r\nnone"));
+    }
+
+    #[test]
+    fn gzip_markdown_is_compressed_on_disk() {
+        let path = "../target/test_gzip.md";
+        let _ = std::fs::remove_file(path);
+        let manager = GzipInteractionManager::new(MarkdownInteractionManager::new(path));
+        let recorded = [interaction("GET", "/report", &"row\n".repeat(1000))];
+
+        manager.save_interactions(&recorded).unwrap();
+
+        let compressed = std::fs::read("../target/test_gzip.md.gz").unwrap();
+        assert_eq!(compressed[..2], [0x1f, 0x8b]);
+        assert!(compressed.len() < 1000);
+        assert!(!std::path::Path::new(path).exists());
+
+        let loaded = manager.load_interactions().unwrap();
+        assert_eq!(
+            loaded[0].response_data.body,
+            recorded[0].response_data.body.trim()
+        );
+        assert!(manager.check_data_unchanged(&recorded).is_ok());
+        assert!(manager
+            .check_data_unchanged(&[interaction("GET", "/report", "column")])
+            .is_err());
+    }
+
+    #[test]
+    fn accept_encoding_is_replaced_when_recording() {
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name(upstream::serve(61521, echo_accept_encoding));
+        config.set_record_accept_encoding(Some(String::from("identity")));
+
+        TestSession::before_test(config);
+        let response = client::request("GET", "/", &[("Accept-Encoding", "gzip, br")], "");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "identity");
+        assert_eq!(
+            manager.interactions()[0].request_data.headers["accept-encoding"],
+            "identity"
+        );
+    }
+
+    #[test]
+    fn absolute_urls_are_rewritten_to_the_servirtium_server() {
+        let manager = MemoryInteractionManager::new();
+        let domain_name = upstream::serve(61522, paginated);
+        let configuration = |mode| {
+            let mut config = ServirtiumConfiguration::new(mode, Box::new(manager.clone()));
+            config.set_domain_name(domain_name.clone());
+            config.set_rewrite_absolute_urls(true);
+            config
+        };
+
+        TestSession::before_test(configuration(ServirtiumMode::Record));
+        let recorded = client::get("/users");
+        TestSession::after_test().unwrap();
+
+        TestSession::before_test(configuration(ServirtiumMode::Playback));
+        let played_back = client::get("/users");
+        TestSession::after_test().unwrap();
+
+        let expected = r#"{"next": "http://localhost:61417/users?page=2"}"#;
+        assert_eq!(recorded.body, expected);
+        assert_eq!(played_back.body, expected);
+        assert!(manager.interactions()[0]
+            .response_data
+            .body
+            .contains("http://127.0.0.1:61522/users"));
+
+        // without a host header the URLs point to the address the server is bound to
+        TestSession::before_test(configuration(ServirtiumMode::Playback));
+        let played_back = client::send_raw("GET /users HTTP/1.0\r\n\r\n");
+        TestSession::after_test().unwrap();
+
+        assert!(played_back.ends_with(r#"{"next": "http://127.0.0.1:61417/users?page=2"}"#));
+    }
+
+    #[test]
+    fn recorded_interactions_are_numbered_consecutively() {
+        let domain_name = upstream::serve(61523, echo_path);
+        let record = |first_interaction_number: Option<usize>| {
+            let manager = MemoryInteractionManager::new();
+            let mut config =
+                ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+            config.set_domain_name(domain_name.clone());
+            if let Some(first_interaction_number) = first_interaction_number {
+                config.set_first_interaction_number(first_interaction_number);
+            }
+
+            TestSession::before_test(config);
+            client::get("/first");
+            client::get("/second");
+            TestSession::after_test().unwrap();
+
+            manager
+                .interactions()
+                .iter()
+                .map(|interaction| interaction.interaction_number)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(record(None), vec![0, 1]);
+        assert_eq!(record(Some(5)), vec![5, 6]);
+        assert_eq!(record(Some(255)), vec![255, 256]);
+
+        let mut interaction = interaction("GET", "/", "");
+        interaction.interaction_number = 256;
+        let manager = MarkdownInteractionManager::new("../target/test_interaction_numbers.md");
+        manager.save_interactions(&[interaction]).unwrap();
+        assert_eq!(
+            manager.load_interactions().unwrap()[0].interaction_number,
+            256
+        );
+    }
+
+    #[test]
+    fn multipart_boundary_is_normalized_when_saved() {
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name(upstream::serve(61513, hello_world));
+
+        let body = "--x7Rq\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\r\n\
+            hello\r\n\
+            --x7Rq--\r\n";
+        TestSession::before_test(config);
+        client::request(
+            "POST",
+            "/upload",
+            &[("Content-Type", "multipart/form-data; boundary=\"x7Rq\"")],
+            body,
+        );
+        TestSession::after_test().unwrap();
+
+        let request_data = &manager.interactions()[0].request_data;
+        assert_eq!(
+            request_data.headers["content-type"],
+            "multipart/form-data; boundary=\"servirtium-boundary\""
+        );
+        assert_eq!(
+            request_data.body,
+            body.replace("--x7Rq", "--servirtium-boundary")
+        );
+        assert_eq!(
+            request_data.headers["content-length"],
+            request_data.body.len().to_string()
+        );
+    }
+
+    fn multipart_upload(boundary: &str) -> RequestData {
+        let mut request_data = interaction("POST", "/upload", "").request_data;
+        request_data.headers.insert(
+            String::from("content-type"),
+            format!("multipart/form-data; boundary={}", boundary),
+        );
+        request_data.body = format!(
+            "--{boundary}\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            Holiday\r\n\
+            --{boundary}\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            line 1\r\n\r\nline 2\r\n\
+            --{boundary}--\r\n",
+            boundary = boundary
+        );
+        request_data
+    }
+
+    #[test]
+    fn multipart_parts_are_saved_without_the_boundary() {
+        let path = "../target/test_multipart.md";
+        let mut upload = interaction("POST", "/upload", "");
+        upload.request_data = multipart_upload("servirtium-boundary");
+        let interactions = [upload];
+
+        let manager = MarkdownInteractionManager::new(path);
+        manager.save_interactions(&interactions).unwrap();
+
+        let markdown = std::fs::read_to_string(path).unwrap();
+        assert!(markdown.contains(
+            "#### Part 0:\r\n\r\n```\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            Holiday\r\n```"
+        ));
+        assert!(markdown.contains(
+            "#### Part 1:\r\n\r\n```\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            line 1\r\n\r\nline 2\r\n```"
+        ));
+        assert!(!markdown.contains("--servirtium-boundary"));
+
+        let loaded = manager.load_interactions().unwrap();
+        assert_eq!(
+            loaded[0].request_data.body,
+            interactions[0].request_data.body
+        );
+        assert!(manager.check_data_unchanged(&interactions).is_ok());
+    }
+
+    #[test]
+    fn multipart_uploads_with_another_boundary_are_played_back() {
+        let mut upload = interaction("POST", "/upload", "uploaded");
+        upload.request_data = multipart_upload("servirtium-boundary");
+        let mut config = playback_configuration(vec![upload]);
+        config
+            .match_configuration_mut()
+            .set_match_request_body(true);
+
+        let request_data = multipart_upload("----WebKitFormBoundary7MA4YWxk");
+        TestSession::before_test(config);
+        let response = client::request(
+            "POST",
+            "/upload",
+            &[("Content-Type", &request_data.headers["content-type"])],
+            &request_data.body,
+        );
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "uploaded");
+    }
+
+    #[test]
+    fn host_header_can_be_left_out_of_the_saved_request() {
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name(upstream::serve(61514, echo_host));
+        config.set_record_host_header(false);
+
+        TestSession::before_test(config);
+        let response = client::get("/");
+        TestSession::after_test().unwrap();
+
+        assert!(response.body.starts_with("127.0.0.1"));
+        assert!(!manager.interactions()[0]
+            .request_data
+            .headers
+            .contains_key("host"));
+    }
+
+    #[test]
+    fn ignored_query_params_are_not_matched() {
+        let mut config = playback_configuration(vec![
+            interaction("GET", "/feed?cache_bust=1&page=2", "page 2"),
+            interaction("GET", "/feed?cache_bust=1", "page 1"),
+        ]);
+        config
+            .match_configuration_mut()
+            .match_ignoring_query_params(vec!["cache_bust"]);
+
+        TestSession::before_test(config);
+        let first = client::get("/feed");
+        let second = client::get("/feed?page=2&cache_bust=9");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(first.body, "page 1");
+        assert_eq!(second.body, "page 2");
+    }
+
+    #[test]
+    fn duplicate_query_keys_are_recorded_and_played_back() {
+        let path = "../target/test_duplicate_query_keys.md";
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Record,
+            Box::new(MarkdownInteractionManager::new(path)),
+        );
+        config.set_domain_name(upstream::serve(61533, echo_query));
+
+        TestSession::before_test(config);
+        let recorded = client::get("/items?tag=a&tag=b");
+        TestSession::after_test().unwrap();
+
+        let interactions = MarkdownInteractionManager::new(path)
+            .load_interactions()
+            .unwrap();
+
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(MarkdownInteractionManager::new(path)),
+        );
+        config
+            .match_configuration_mut()
+            .match_ignoring_query_params(vec!["_"]);
+
+        TestSession::before_test(config);
+        let played_back = client::get("/items?tag=a&_=1&tag=b");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(recorded.body, "tag=a&tag=b");
+        assert_eq!(interactions[0].request_data.uri, "/items?tag=a&tag=b");
+        assert_eq!(played_back.body, "tag=a&tag=b");
+    }
+
+    #[test]
+    fn duplicate_query_keys_are_matched_in_order() {
+        let mut match_configuration = MatchConfiguration::new();
+        match_configuration.match_ignoring_query_params(vec!["_"]);
+
+        assert!(match_configuration.uris_match("/items?tag=a&tag=b", "/items?_=1&tag=a&_=2&tag=b"));
+        assert!(!match_configuration.uris_match("/items?tag=a&tag=b", "/items?tag=b&tag=a"));
+        assert!(!match_configuration.uris_match("/items?tag=a&tag=b", "/items?tag=a"));
+        assert!(!match_configuration.uris_match("/items?tag=a", "/items?tag=a&tag=a"));
+    }
+
+    #[test]
+    fn event_streams_are_recorded_up_to_the_limits() {
+        let domain_name = upstream::serve(61515, endless_events);
+        let record = |max_events: usize| {
+            let manager = MemoryInteractionManager::new();
+            let mut config =
+                ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+            config.set_domain_name(domain_name.clone());
+            config.set_record_event_stream_limits(max_events, Duration::from_millis(200));
+
+            TestSession::before_test(config);
+            let response = client::get("/events");
+            TestSession::after_test().unwrap();
+
+            assert_eq!(manager.interactions()[0].response_data.body, response.body);
+            response.body
+        };
+
+        assert_eq!(record(2), "data: 1\n\ndata: 2\n\n");
+        assert_eq!(record(10), "data: 1\n\ndata: 2\n\ndata: 3\n\n");
+    }
+
+    #[test]
+    fn truncated_responses_are_rejected() {
+        let record = |domain_name: String| {
+            let mut config = ServirtiumConfiguration::new(
+                ServirtiumMode::Record,
+                Box::new(MemoryInteractionManager::new()),
+            );
+            config.set_domain_name(domain_name);
+            config.set_record_event_stream_limits(2, Duration::from_millis(200));
+
+            TestSession::before_test(config);
+            let response = client::get("/");
+            (response, TestSession::after_test())
+        };
+
+        let (_, result) = record(upstream::serve_raw(
+            61542,
+            "HTTP/1.1 200 OK\r\ncontent-length: 20\r\n\r\nshort",
+        ));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The response is truncated: expected 20 bytes, received 5"
+        );
+
+        // an event stream is cut short on purpose when it reaches the limits
+        let (response, result) = record(upstream::serve_raw(
+            61543,
+            "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncontent-length: 1000\r\n\r\n\
+             data: 1\n\ndata: 2\n\ndata: 3\n\n",
+        ));
+        result.unwrap();
+        assert_eq!(response.body, "data: 1\n\ndata: 2\n\n");
+    }
+
+    #[test]
+    fn blocking_http_client_is_used_for_recording() {
+        let manager = MemoryInteractionManager::new();
+        let mut config =
+            ServirtiumConfiguration::new(ServirtiumMode::Record, Box::new(manager.clone()));
+        config.set_domain_name("http://blocking");
+        config.set_blocking_http_client(FakeBlockingClient);
+
+        TestSession::before_test(config);
+        let response = client::get("/path");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "http://blocking/path");
+        assert_eq!(manager.interactions().len(), 1);
+    }
+
+    #[test]
+    fn ranges_are_served_from_the_full_response() {
+        let mut config = playback_configuration(vec![
+            interaction("GET", "/file", "0123456789"),
+            interaction("GET", "/file", "0123456789"),
+            interaction("GET", "/file", "0123456789"),
+        ]);
+        config.set_serve_ranges(true);
+
+        TestSession::before_test(config);
+        let middle = client::request("GET", "/file", &[("Range", "bytes=2-4")], "");
+        let suffix = client::request("GET", "/file", &[("Range", "bytes=-3")], "");
+        let outside = client::request("GET", "/file", &[("Range", "bytes=20-")], "");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(middle.status_code, 206);
+        assert_eq!(middle.body, "234");
+        assert_eq!(middle.headers["content-range"], vec!["bytes 2-4/10"]);
+        assert_eq!(suffix.body, "789");
+        assert_eq!(outside.status_code, 416);
+        assert_eq!(outside.headers["content-range"], vec!["bytes */10"]);
+    }
+
+    #[test]
+    fn responses_are_overridden_by_interaction_number() {
+        let mut second = interaction("GET", "/weather", "sunny");
+        second.interaction_number = 1;
+        let mut config =
+            playback_configuration(vec![interaction("GET", "/weather", "rainy"), second]);
+        config.override_response_status(1, 500);
+        config.override_response_body(1, "Internal Server Error");
+
+        TestSession::before_test(config);
+        let first = client::get("/weather");
+        let second = client::get("/weather");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(first.status_code, 200);
+        assert_eq!(first.body, "rainy");
+        assert_eq!(second.status_code, 500);
+        assert_eq!(second.body, "Internal Server Error");
+    }
+
+    #[derive(Debug)]
+    struct BodyLengthHeader;
+
+    impl FullRequestMutation for BodyLengthHeader {
+        fn mutate(&self, request_data: &mut RequestData) {
+            let length = request_data.body.len().to_string();
+            request_data
+                .headers
+                .insert(String::from("x-body-length"), length);
+        }
+    }
+
+    #[derive(Debug)]
+    struct StatusHeader;
+
+    impl FullResponseMutation for StatusHeader {
+        fn mutate(&self, response_data: &mut ResponseData) {
+            let status_code = response_data.status_code.to_string();
+            response_data
+                .headers
+                .insert(String::from("x-status"), status_code);
+        }
+    }
+
+    #[test]
+    fn full_mutations_see_the_whole_request_and_response() {
+        let mut config = playback_configuration(vec![interaction("GET", "/", "hello")]);
+        config.add_full_playback_response_mutation(StatusHeader);
+
+        TestSession::before_test(config);
+        let response = client::get("/");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.headers["x-status"], vec!["200"]);
+
+        let mut request_data = interaction("POST", "/", "").request_data;
+        request_data.body = String::from("hello");
+        RequestMutation::from_full(BodyLengthHeader).mutate(&mut request_data);
+        assert_eq!(request_data.headers["x-body-length"], "5");
+    }
+
+    #[test]
+    fn conditional_mutations_apply_only_when_the_predicate_holds() {
+        let mut builder = MutationsBuilder::default();
+        builder.when(
+            |headers, _| {
+                headers.get("content-type").map(String::as_str) == Some("application/json")
+            },
+            |builder| {
+                builder
+                    .body_replace("secret", "***")
+                    .add_header("x-scrubbed", "1")
+            },
+        );
+        let mutations = builder.into_response_mutations();
+
+        let mut json = interaction("GET", "/", "secret").response_data;
+        json.headers.insert(
+            String::from("content-type"),
+            String::from("application/json"),
+        );
+        let mut text = interaction("GET", "/", "secret").response_data;
+        for mutation in &mutations {
+            mutation.mutate(&mut json);
+            mutation.mutate(&mut text);
+        }
+
+        assert_eq!(json.body, "***");
+        assert_eq!(json.headers["x-scrubbed"], "1");
+        assert_eq!(text.body, "secret");
+        assert!(text.headers.is_empty());
+    }
+
+    #[test]
+    fn interactions_are_written_to_and_read_from_streams() {
+        let path = "../target/test_stream.md";
+        let interactions = vec![
+            interaction("GET", "/first", "one"),
+            interaction("POST", "/second", "two"),
+        ];
+
+        StreamInteractionManager::to_writer(std::fs::File::create(path).unwrap())
+            .save_interactions(&interactions)
+            .unwrap();
+
+        let manager = StreamInteractionManager::from_reader(std::fs::File::open(path).unwrap());
+        let loaded = manager.load_interactions().unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[1].request_data.uri, "/second");
+        assert_eq!(loaded[1].response_data.body, "two");
+        assert!(manager.check_data_unchanged(&interactions).is_ok());
+    }
+
+    #[test]
+    fn jsonl_interactions_are_appended_one_per_line() {
+        let path = "../target/test_interactions.jsonl";
+        let _ = std::fs::remove_file(path);
+        let manager = JsonlInteractionManager::new(path);
+
+        let mut first = interaction("GET", "/first", "one\ntwo");
+        first
+            .response_data
+            .headers
+            .insert(String::from("set-cookie"), String::from("a=1\nb=2"));
+        first.response_time = Some(Duration::from_millis(120));
+        manager.save_interactions(&[first]).unwrap();
+        manager
+            .save_interactions(&[interaction("POST", "/second", "three")])
+            .unwrap();
+
+        let loaded = manager.load_interactions().unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap().lines().count(), 2);
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].response_data.body, "one\ntwo");
+        assert_eq!(loaded[0].response_data.headers["set-cookie"], "a=1\nb=2");
+        assert_eq!(loaded[0].response_time, Some(Duration::from_millis(120)));
+        assert_eq!(loaded[1].request_data.method, "POST");
+        assert_eq!(loaded[1].request_data.uri, "/second");
+
+        TestSession::before_test(ServirtiumConfiguration::new(
+            ServirtiumMode::Playback,
+            Box::new(manager),
+        ));
+        let response = client::request("POST", "/second", &[], "");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(response.body, "three");
+    }
+
+    #[test]
+    fn malformed_jsonl_lines_are_reported_with_their_number() {
+        let path = "../target/test_malformed.jsonl";
+        let _ = std::fs::remove_file(path);
+        let manager = JsonlInteractionManager::new(path);
+        manager
+            .save_interactions(&[interaction("GET", "/", "ok")])
+            .unwrap();
+
+        let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+        std::io::Write::write_all(&mut file, b"\n{\"interaction_number\": 1\n").unwrap();
+
+        let error = manager.load_interactions().unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Invalid JSONL interaction on line 3:"));
+    }
+
+    #[test]
+    fn not_modified_is_only_replayed_for_conditional_requests() {
+        let mut not_modified = interaction("GET", "/resource", "");
+        not_modified.response_data.status_code = 304;
+        not_modified
+            .request_data
+            .headers
+            .insert(String::from("if-none-match"), String::from("\"v1\""));
+
+        TestSession::before_test(playback_configuration(vec![
+            not_modified.clone(),
+            interaction("GET", "/resource", "full"),
+        ]));
+        let unconditional = client::get("/resource");
+        let conditional = client::request("GET", "/resource", &[("If-None-Match", "\"v1\"")], "");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(unconditional.status_code, 200);
+        assert_eq!(unconditional.body, "full");
+        assert_eq!(conditional.status_code, 304);
+
+        TestSession::before_test(playback_configuration(vec![not_modified]));
+        let only_not_modified = client::get("/resource");
+        TestSession::after_test().unwrap();
+
+        assert_eq!(only_not_modified.status_code, 304);
+    }
+
+    #[test]
+    fn requests_with_disallowed_methods_are_not_forwarded() {
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Record,
+            Box::new(MemoryInteractionManager::new()),
+        );
+        config.set_domain_name(upstream::serve(61516, echo_path));
+        config.set_allowed_record_methods(vec!["GET", "HEAD"]);
+
+        TestSession::before_test(config);
+        let allowed = client::get("/users/1");
+        client::request("DELETE", "/users/1", &[], "");
+        let result = TestSession::after_test();
+
+        assert_eq!(allowed.body, "/users/1");
+        assert!(result.unwrap_err().to_string().contains("DELETE /users/1"));
+    }
+
+    #[test]
+    fn header_differences_are_found_on_both_sides() {
+        let headers = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(name, value)| (String::from(*name), String::from(*value)))
+                .collect::<HashMap<_, _>>()
+        };
+        let old_headers = headers(&[("accept", "*/*"), ("date", "today ")]);
+
+        assert!(servirtium::compare_headers(
+            &old_headers,
+            &headers(&[("accept", "*/*"), ("date", "today")])
+        )
+        .is_none());
+
+        let added = servirtium::compare_headers(
+            &old_headers,
+            &headers(&[("accept", "*/*"), ("date", "today"), ("age", "1")]),
+        )
+        .unwrap();
+        assert_eq!(added.header_name, "age");
+        assert_eq!(added.old_header_value, None);
+        assert_eq!(added.new_header_value.as_deref(), Some("1"));
+
+        let changed =
+            servirtium::compare_headers(&old_headers, &headers(&[("accept", "text/html")]))
+                .unwrap();
+        assert_eq!(changed.header_name, "accept");
+        assert_eq!(changed.old_header_value.as_deref(), Some("*/*"));
+        assert_eq!(changed.new_header_value.as_deref(), Some("text/html"));
+    }
+
+    #[test]
+    fn responses_without_content_type_are_saved_and_loaded() {
+        let path = "../target/test_no_content_type.md";
+        let manager = MarkdownInteractionManager::new(path);
+        manager
+            .save_interactions(&[interaction("GET", "/", r#"{"city": "Oslo"}"#)])
+            .unwrap();
+
+        let markdown = std::fs::read_to_string(path).unwrap();
+        assert!(markdown.contains("### Response body recorded for playback (200: ):"));
+
+        let response_data = manager.load_interactions().unwrap().remove(0).response_data;
+        assert_eq!(response_data.content_type(), None);
+        assert_eq!(
+            response_data.json::<Temperature>().unwrap_err().to_string(),
+            "The Content-Type header is missing"
+        );
+    }
+
+    fn configure_user_schema(config: &mut ServirtiumConfiguration) {
+        config
+            .set_response_schema(
+                regex::Regex::new("^/users/").unwrap(),
+                &serde_json::json!({
+                    "type": "object",
+                    "required": ["id", "name"],
+                    "properties": {
+                        "id": { "type": "integer", "minimum": 1 },
+                        "name": { "type": "string" }
+                    }
+                }),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn recorded_responses_are_validated_against_their_json_schema() {
+        let mut config = ServirtiumConfiguration::new(
+            ServirtiumMode::Record,
+            Box::new(MemoryInteractionManager::new()),
+        );
+        config.set_domain_name(upstream::serve_raw(
+            61536,
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 14\r\n\r\n{\"id\":\"seven\"}",
+        ));
+        configure_user_schema(&mut config);
+
+        TestSession::before_test(config);
+        client::get("/users/7");
+        let error = TestSession::after_test().unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "The response to GET /users/7 doesn't conform to its JSON schema: /: \"name\" is a required property"
+        );
+    }
+
+    #[test]
+    fn played_back_responses_are_validated_against_their_json_schema() {
+        let mut config = playback_configuration(vec![
+            interaction("GET", "/users/1", r#"{"id":1,"name":"Ann"}"#),
+            interaction("GET", "/health", "ok"),
+            interaction("GET", "/users/2", r#"{"id":0,"name":"Bob"}"#),
+        ]);
+        configure_user_schema(&mut config);
+
+        TestSession::before_test(config);
+        assert_eq!(client::get("/users/1").status_code, 200);
+        assert_eq!(client::get("/health").status_code, 200);
+        client::get("/users/2");
+        let error = TestSession::after_test().unwrap_err();
+
+        assert!(error
+            .to_string()
+            .ends_with("/id: 0 is less than the minimum of 1"));
+    }
+
+    #[test]
+    fn invalid_json_schemas_are_rejected() {
+        let mut config = playback_configuration(Vec::new());
+
+        let error = config
+            .set_response_schema(
+                regex::Regex::new("^/users/").unwrap(),
+                &serde_json::json!({ "type": "user" }),
+            )
+            .unwrap_err();
+
+        assert!(error
+            .to_string()
+            .starts_with("The JSON schema of the responses is invalid: "));
+    }
+
+    #[test]
+    fn fixtures_are_collected_when_tests_start() {
+        let markdown = MarkdownInteractionManager::new("../target/test_referenced.md");
+        assert_eq!(
+            markdown.path(),
+            std::path::Path::new("../target/test_referenced.md")
+        );
+
+        for interaction_manager in [
+            Box::new(markdown) as Box<dyn InteractionManager + Send + Sync>,
+            Box::new(GzipInteractionManager::new(
+                MarkdownInteractionManager::new("../target/test_referenced_gzip.md"),
+            )),
+        ] {
+            TestSession::before_test(ServirtiumConfiguration::new(
+                ServirtiumMode::Playback,
+                interaction_manager,
+            ));
+            TestSession::after_test().unwrap();
+        }
+
+        let referenced = servirtium::referenced_fixtures();
+        assert!(referenced.contains(&std::path::PathBuf::from("../target/test_referenced.md")));
+        assert!(referenced.contains(&std::path::PathBuf::from(
+            "../target/test_referenced_gzip.md.gz"
+        )));
+    }
+
+    #[test]
+    fn nested_tests_fail_instead_of_hanging() {
+        TestSession::before_test(playback_configuration(Vec::new()));
+        let nested = std::panic::catch_unwind(|| {
+            TestSession::before_test(playback_configuration(Vec::new()));
+        });
+        TestSession::after_test().unwrap();
+
+        let message = nested.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains("the same thread is already running one"));
+    }
+
+    #[test]
+    fn compare_reports_differences_in_non_ascii_and_empty_bodies() {
+        use servirtium::{MarkdownsDifference, MarkdownsDifferenceType};
+
+        let body_difference = |old: &str, new: &str| {
+            let differences = MarkdownInteractionManager::compare(
+                &[interaction("GET", "/", old)],
+                &[interaction("GET", "/", new)],
+                Vec::<String>::new(),
+            )
+            .unwrap_err();
+
+            match differences.into_iter().next() {
+                Some(MarkdownsDifference::Interaction {
+                    difference_type: MarkdownsDifferenceType::Body(difference),
+                    ..
+                }) => difference,
+                difference => panic!("unexpected difference: {:?}", difference),
+            }
+        };
+
+        let difference = body_difference("prix : 10 €, café é", "prix : 10 €, café è");
+        assert_eq!((difference.line, difference.column), (1, 19));
+        assert_eq!(difference.old_context, "€, café é");
+        assert_eq!(difference.new_context, "€, café è");
+
+        let difference = body_difference("", "é");
+        assert_eq!(
+            (
+                difference.old_context.as_str(),
+                difference.new_context.as_str()
+            ),
+            ("", "é")
+        );
+
+        let difference = body_difference("ééé", "ééééé");
+        assert_eq!((difference.line, difference.column), (1, 4));
+        assert_eq!(difference.old_context, "ééé");
+        assert_eq!(difference.new_context, "ééééé");
+    }
+
+    #[test]
+    fn compare_returns_all_differences_between_recordings() {
+        use servirtium::{
+            MarkdownsDifference, MarkdownsDifferenceLocation, MarkdownsDifferenceType,
+        };
+
+        let mut old = vec![
+            interaction("GET", "/users", "[]"),
+            interaction("GET", "/posts", "[]"),
+        ];
+        old[0].response_data.headers.insert(
+            String::from("Date"),
+            String::from("Mon, 01 Jan 2024 00:00:00 GMT"),
+        );
+        let mut new = old.clone();
+        new[0].response_data.headers.insert(
+            String::from("Date"),
+            String::from("Tue, 02 Jan 2024 00:00:00 GMT"),
+        );
+
+        assert!(MarkdownInteractionManager::compare(&old, &new, ["date"]).is_ok());
+
+        new[0].response_data.headers.insert(
+            String::from("Content-Type"),
+            String::from("application/json"),
+        );
+        new[1].request_data.uri = String::from("/comments");
+        new[1].response_data.status_code = 404;
+        new[1].response_data.body = String::from("[1]");
+
+        let differences = MarkdownInteractionManager::compare(&old, &new, ["date"]).unwrap_err();
+
+        assert_eq!(differences.len(), 4);
+        assert!(matches!(
+            &differences[0],
+            MarkdownsDifference::Interaction {
+                index: 0,
+                location: MarkdownsDifferenceLocation::Response,
+                difference_type: MarkdownsDifferenceType::Header(difference),
+            } if difference.header_name == "Content-Type" && difference.old_header_value.is_none()
+        ));
+        assert!(matches!(
+            &differences[1],
+            MarkdownsDifference::Interaction {
+                index: 1,
+                location: MarkdownsDifferenceLocation::Request,
+                difference_type: MarkdownsDifferenceType::Signature { old, new },
+            } if old == "GET /posts" && new == "GET /comments"
+        ));
+        assert!(matches!(
+            &differences[2],
+            MarkdownsDifference::Interaction {
+                index: 1,
+                location: MarkdownsDifferenceLocation::Response,
+                difference_type: MarkdownsDifferenceType::StatusCode { old: 200, new: 404 },
+            }
+        ));
+        assert!(matches!(
+            &differences[3],
+            MarkdownsDifference::Interaction {
+                index: 1,
+                location: MarkdownsDifferenceLocation::Response,
+                difference_type: MarkdownsDifferenceType::Body(_),
+            }
+        ));
+
+        new.pop();
+        let differences = MarkdownInteractionManager::compare(&old, &new, ["date"]).unwrap_err();
+        assert!(matches!(
+            differences[0],
+            MarkdownsDifference::InteractionCount { old: 2, new: 1 }
+        ));
+    }
 }
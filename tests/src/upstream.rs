@@ -0,0 +1,121 @@
+use hyper::{
+    server::conn::Http,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use std::{
+    convert::Infallible,
+    io::{Read, Write},
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+use tokio::runtime::Runtime;
+
+/// Starts a fake destination API on the given port in a background thread and returns its URL.
+pub fn serve(port: u16, handler: fn(Request<Body>) -> Response<Body>) -> String {
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+
+    thread::spawn(move || {
+        Runtime::new().unwrap().block_on(async move {
+            let server =
+                Server::from_tcp(listener)
+                    .unwrap()
+                    .serve(make_service_fn(move |_| async move {
+                        Ok::<_, Infallible>(service_fn(move |req| async move {
+                            Ok::<_, Infallible>(handler(req))
+                        }))
+                    }));
+
+            let _ = server.await;
+        });
+    });
+
+    format!("http://127.0.0.1:{}", port)
+}
+
+/// Like [`serve`], but over HTTPS with the self-signed certificate in `certificates`, which
+/// isn't trusted by default.
+pub fn serve_tls(port: u16, handler: fn(Request<Body>) -> Response<Body>) -> String {
+    let identity = native_tls::Identity::from_pkcs8(
+        include_bytes!("../certificates/self_signed.crt"),
+        include_bytes!("../certificates/self_signed.key"),
+    )
+    .unwrap();
+    let acceptor = tokio_native_tls::TlsAcceptor::from(
+        native_tls::TlsAcceptor::builder(identity).build().unwrap(),
+    );
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+    listener.set_nonblocking(true).unwrap();
+
+    thread::spawn(move || {
+        Runtime::new().unwrap().block_on(async move {
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+
+                tokio::spawn(async move {
+                    // the handshake fails when the client doesn't trust the certificate
+                    if let Ok(stream) = acceptor.accept(stream).await {
+                        let service =
+                            service_fn(move |req| async move { Ok::<_, Infallible>(handler(req)) });
+                        let _ = Http::new().serve_connection(stream, service).await;
+                    }
+                });
+            }
+        });
+    });
+
+    format!("https://localhost:{}", port)
+}
+
+/// Like [`serve`], but also returns the number of connections the fake destination API has
+/// accepted so far.
+pub fn serve_counting_connections(
+    port: u16,
+    handler: fn(Request<Body>) -> Response<Body>,
+) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = connections.clone();
+
+    thread::spawn(move || {
+        Runtime::new().unwrap().block_on(async move {
+            let server = Server::from_tcp(listener)
+                .unwrap()
+                .serve(make_service_fn(move |_| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |req| async move {
+                            Ok::<_, Infallible>(handler(req))
+                        }))
+                    }
+                }));
+
+            let _ = server.await;
+        });
+    });
+
+    (format!("http://127.0.0.1:{}", port), connections)
+}
+
+/// Starts a fake destination API that answers every connection with the given raw bytes and
+/// closes it, which allows sending responses hyper wouldn't produce.
+pub fn serve_raw(port: u16, response: &'static str) -> String {
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut buffer = [0; 4096];
+            let _ = stream.read(&mut buffer);
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+
+    format!("http://127.0.0.1:{}", port)
+}
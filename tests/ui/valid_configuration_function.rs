@@ -0,0 +1,11 @@
+use servirtium::ServirtiumConfiguration;
+use servirtium_codegen::servirtium_playback;
+
+fn configure(config: &mut ServirtiumConfiguration) {
+    config.set_domain_name("http://localhost");
+}
+
+#[servirtium_playback("test_playback.md", configure)]
+fn valid_configuration_function() {}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use servirtium_codegen::servirtium_playback;
+
+fn configure(_: &str) {}
+
+#[servirtium_playback("test_playback.md", configure)]
+fn wrong_configuration_function() {}
+
+fn main() {}